@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Shortcuts bound through the GlobalShortcuts portal, by app ID
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlobalShortcuts {
+    pub apps: BTreeMap<String, Vec<Shortcut>>,
+}
+
+impl GlobalShortcuts {
+    pub fn app(&self, app_id: &str) -> &[Shortcut] {
+        self.apps.get(app_id).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Shortcut {
+    /// ID chosen by the app
+    pub id: String,
+    pub description: String,
+    /// Trigger in the XDG shortcuts format, such as `CTRL+ALT+p`. Empty if unassigned.
+    pub trigger: String,
+}
@@ -1,11 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+pub mod global_shortcuts;
 pub mod screenshot;
 
 use cosmic_config::CosmicConfigEntry;
 use cosmic_config::cosmic_config_derive::CosmicConfigEntry;
 use serde::{Deserialize, Serialize};
 
+use global_shortcuts::GlobalShortcuts;
 use screenshot::Screenshot;
 
 pub const APP_ID: &str = "com.system76.CosmicPortal";
@@ -18,6 +20,9 @@ pub const CONFIG_VERSION: u64 = 1;
 pub struct Config {
     /// Interactive screenshot settings
     pub screenshot: Screenshot,
+    /// Shortcuts apps have bound through the GlobalShortcuts portal
+    #[serde(default)]
+    pub global_shortcuts: GlobalShortcuts,
 }
 
 impl Config {
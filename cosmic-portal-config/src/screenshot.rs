@@ -1,6 +1,23 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Screenshot file name used when no template is configured, without extension
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "Screenshot_%Y-%m-%d_%H-%M-%S";
+
+/// Encoder quality used when none is configured
+pub const DEFAULT_QUALITY: u8 = 90;
+
+/// Tesseract language used when none is configured
+pub const DEFAULT_OCR_LANGUAGE: &str = "eng";
+
+/// Number of recent screenshots kept when none is configured. The history is
+/// opt-in, as it keeps copies of captures in the cache, even clipboard-only ones.
+pub const DEFAULT_HISTORY_COUNT: u32 = 0;
+
+/// Megabytes of recent screenshots kept when none is configured
+pub const DEFAULT_HISTORY_SIZE: u32 = 256;
 
 /// Logical coordinates of a rectangle selection
 #[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
@@ -18,16 +35,93 @@ pub struct Screenshot {
     pub choice: Choice,
     #[serde(default)]
     pub last_rectangle: Option<Rect>,
+    /// File name for saved screenshots, without extension
+    ///
+    /// Accepts `strftime` specifiers and the `{output}`, `{title}` and `{app_id}` placeholders.
+    #[serde(default)]
+    pub filename_template: Option<String>,
+    /// Format saved screenshots are encoded in
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// Quality from 1 to 100 for lossy formats
+    #[serde(default)]
+    pub quality: Option<u8>,
+    /// Add a drop shadow around window screenshots
+    #[serde(default)]
+    pub window_shadow: bool,
+    /// How a rectangle spanning outputs with different scales is saved
+    #[serde(default)]
+    pub stitching: Stitching,
+    /// Tesseract languages for copying text from a selection, such as `eng+deu`
+    #[serde(default)]
+    pub ocr_language: Option<String>,
+    /// Number of recent screenshots kept in the history, where 0, the default, disables it
+    #[serde(default)]
+    pub history_count: Option<u32>,
+    /// Megabytes the history may take up in the cache
+    #[serde(default)]
+    pub history_size: Option<u32>,
+}
+
+impl Screenshot {
+    pub fn filename_template(&self) -> &str {
+        self.filename_template
+            .as_deref()
+            .unwrap_or(DEFAULT_FILENAME_TEMPLATE)
+    }
+
+    pub fn quality(&self) -> u8 {
+        self.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100)
+    }
+
+    pub fn ocr_language(&self) -> &str {
+        self.ocr_language.as_deref().unwrap_or(DEFAULT_OCR_LANGUAGE)
+    }
+
+    pub fn history_count(&self) -> u32 {
+        self.history_count.unwrap_or(DEFAULT_HISTORY_COUNT)
+    }
+
+    /// History size limit in bytes
+    pub fn history_size(&self) -> u64 {
+        u64::from(self.history_size.unwrap_or(DEFAULT_HISTORY_SIZE)) * 1024 * 1024
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum Stitching {
+    /// One image at the highest scale of the outputs
+    #[default]
+    MaxScale,
+    /// One image per output, each at its own scale, when saving to a folder.
+    ///
+    /// The portal response names only the first file, and the clipboard gets
+    /// the whole selection at the highest scale.
+    PerOutput,
 }
 
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// Lossless WebP
+    WebP,
+    Avif,
+    /// Lossless, and much faster to encode than PNG
+    Qoi,
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub enum ImageSaveLocation {
     Clipboard,
     #[default]
     Pictures,
     Documents,
-    // Custom(PathBuf), // TODO
+    Custom(PathBuf),
 }
 
 // TODO: Use type from screenshot directly?
@@ -37,6 +131,7 @@ pub enum Choice {
     Output(Option<String>),
     Rectangle,
     Window,
+    Scrolling,
 }
 
 impl From<&mut Choice> for Choice {
@@ -47,6 +142,7 @@ impl From<&mut Choice> for Choice {
             Choice::Output(output) => Choice::Output(output.take()),
             Choice::Rectangle => Choice::Rectangle,
             Choice::Window => Choice::Window,
+            Choice::Scrolling => Choice::Scrolling,
         }
     }
 }
@@ -1,15 +1,68 @@
 allow = Allow
 cancel = Cancel
+set = Set
 capture = Capture
 share = Share
+open = Open
+save = Save
 save-to = Save to
     .clipboard = { save-to } Clipboard
     .pictures = { save-to } Pictures
     .documents = { save-to } Documents
+    .folder = { save-to } {$folder}
 choose-folder = Choose folder
+delay = Delay
+    .none = No delay
+    .seconds = { $seconds ->
+        [one] 1 second
+       *[other] {$seconds} seconds
+    }
+window-shadow = Shadow
+per-display = Split by display
+copy-text = Copy text
+annotate = Annotate
+    .pen = Pen
+    .arrow = Arrow
+    .rectangle = Box
+    .text = Text
+    .highlight = Highlight
+    .pixelate = Pixelate
+    .label = Label text
+scrolling = Scrolling capture
+    .hint = Scroll the window down to capture more of it
+    .frames = { $frames ->
+        [one] 1 frame
+       *[other] {$frames} frames
+    }
+    .done = Done
+history = History
+    .empty = No recent screenshots
+    .copy = Copy
+    .show-in-folder = Show in folder
+    .remove = Remove from history
 
 share-screen = Share your screen
     .description = The system wants to share the contents of your screen with "{$app_name}". Select a screen or window to share.
+    .remember = Remember this selection
 unknown-application = Unknown Application
+remote-desktop = Allow remote control
+    .description = "{$app_name}" wants to control this computer remotely, using the following devices.
+keyboard = Keyboard
+pointer = Pointer
+set-wallpaper = Set wallpaper
+    .description = "{$app_name}" wants to set this image as your wallpaper.
+    .all-displays = Set on all displays
+    .this-display = Set on this display
+open-with = Open with
+    .description = Choose an application to open the file from "{$app_name}".
+    .file = Choose an application to open "{$filename}".
+search-applications = Search applications
+always-use = Always use for {$content_type}
+global-shortcuts = Global shortcuts
+    .description = "{$app_name}" wants to use shortcuts that work while it's in the background.
+    .unassigned = Unassigned
+    .taken = Used by another shortcut, so it will be left unassigned
+    .invalid = Not a valid shortcut, so it will be left unassigned
+    .hint = Shortcuts use the form CTRL+ALT+p; modifiers are CTRL, ALT, SHIFT and LOGO.
 output = Output
 window = Window
@@ -0,0 +1,699 @@
+//! Shapes drawn over a screenshot in the editor, and rendering them into the
+//! image before it's saved.
+
+use cosmic::iced::advanced::graphics::text::{cosmic_text, font_system};
+use image::RgbaImage;
+use std::collections::HashSet;
+use tiny_skia::{
+    BlendMode, Color, ColorU8, FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap,
+    PremultipliedColorU8, Stroke, Transform,
+};
+
+/// Position in image pixels
+pub type Point = (f32, f32);
+
+/// Colors offered for annotations
+pub const COLORS: [[u8; 3]; 6] = [
+    [0xE0, 0x1B, 0x24],
+    [0xF6, 0xD3, 0x2D],
+    [0x33, 0xD1, 0x7A],
+    [0x35, 0x84, 0xE4],
+    [0xFF, 0xFF, 0xFF],
+    [0x00, 0x00, 0x00],
+];
+
+// Sizes in logical pixels, scaled to image pixels when a shape is started
+const STROKE_WIDTH: f32 = 4.0;
+const HIGHLIGHT_WIDTH: f32 = 20.0;
+const TEXT_SIZE: f32 = 24.0;
+const PIXELATE_RADIUS: f32 = 16.0;
+const PIXELATE_BLOCK: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tool {
+    #[default]
+    Pen,
+    Arrow,
+    Rectangle,
+    Text,
+    Highlight,
+    Pixelate,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 6] = [
+        Tool::Pen,
+        Tool::Arrow,
+        Tool::Rectangle,
+        Tool::Text,
+        Tool::Highlight,
+        Tool::Pixelate,
+    ];
+}
+
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Pen {
+        points: Vec<Point>,
+        color: [u8; 3],
+        width: f32,
+    },
+    Arrow {
+        start: Point,
+        end: Point,
+        color: [u8; 3],
+        width: f32,
+    },
+    Rectangle {
+        start: Point,
+        end: Point,
+        color: [u8; 3],
+        width: f32,
+    },
+    Text {
+        position: Point,
+        text: String,
+        color: [u8; 3],
+        size: f32,
+    },
+    Highlight {
+        points: Vec<Point>,
+        color: [u8; 3],
+        width: f32,
+    },
+    /// Pixels under the brush are replaced by blocks of their average color
+    Pixelate {
+        points: Vec<Point>,
+        radius: f32,
+        block: u32,
+    },
+}
+
+impl Shape {
+    /// Starts a shape at `start`. `scale` is the number of image pixels per
+    /// logical pixel, so sizes look the same at any zoom. Returns `None` for a
+    /// text label without text.
+    pub fn new(tool: Tool, start: Point, color: [u8; 3], text: &str, scale: f32) -> Option<Self> {
+        Some(match tool {
+            Tool::Pen => Shape::Pen {
+                points: vec![start],
+                color,
+                width: STROKE_WIDTH * scale,
+            },
+            Tool::Arrow => Shape::Arrow {
+                start,
+                end: start,
+                color,
+                width: STROKE_WIDTH * scale,
+            },
+            Tool::Rectangle => Shape::Rectangle {
+                start,
+                end: start,
+                color,
+                width: STROKE_WIDTH * scale,
+            },
+            Tool::Text if text.trim().is_empty() => return None,
+            Tool::Text => Shape::Text {
+                position: start,
+                text: text.to_string(),
+                color,
+                size: TEXT_SIZE * scale,
+            },
+            Tool::Highlight => Shape::Highlight {
+                points: vec![start],
+                color,
+                width: HIGHLIGHT_WIDTH * scale,
+            },
+            Tool::Pixelate => Shape::Pixelate {
+                points: vec![start],
+                radius: PIXELATE_RADIUS * scale,
+                block: (PIXELATE_BLOCK * scale).round().max(2.0) as u32,
+            },
+        })
+    }
+
+    /// Part of a `width` by `height` image the shape can draw on, or `None` if
+    /// it's outside of the image.
+    pub fn region(&self, width: u32, height: u32) -> Option<Region> {
+        let points: &[Point] = match self {
+            Shape::Pen { points, .. }
+            | Shape::Highlight { points, .. }
+            | Shape::Pixelate { points, .. } => points,
+            Shape::Arrow { start, end, .. } | Shape::Rectangle { start, end, .. } => {
+                &[*start, *end]
+            }
+            Shape::Text { position, .. } => std::slice::from_ref(position),
+        };
+        let (mut left, mut top, mut right, mut bottom) = points.iter().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(left, top, right, bottom), &(x, y)| {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            },
+        );
+        // Strokes reach past their points, and anti-aliasing a pixel further
+        let margin = 1.0
+            + match self {
+                Shape::Pen { width, .. } | Shape::Highlight { width, .. } => *width,
+                Shape::Rectangle { width, .. } => *width,
+                // The head is wider than the line
+                Shape::Arrow { width, .. } => *width * 3.0,
+                Shape::Pixelate { radius, block, .. } => *radius + *block as f32,
+                Shape::Text { .. } => 0.0,
+            };
+        if let Shape::Text { text, size, .. } = self {
+            // Labels are only as wide as the image allows
+            right = width as f32;
+            bottom = top + *size * 1.2 * text.lines().count().max(1) as f32 + *size;
+        }
+        left -= margin;
+        top -= margin;
+        right += margin;
+        bottom += margin;
+        if let Shape::Pixelate { block, .. } = self {
+            // Blocks are aligned to the image, so keep the region aligned too
+            let block = *block as f32;
+            left = (left / block).floor() * block;
+            top = (top / block).floor() * block;
+        }
+
+        let x = left.max(0.0) as u32;
+        let y = top.max(0.0) as u32;
+        let right = (right.ceil().max(0.0) as u32).min(width);
+        let bottom = (bottom.ceil().max(0.0) as u32).min(height);
+        (x < right && y < bottom).then(|| Region {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+
+    /// The shape moved by `offset`
+    pub fn translated(&self, (dx, dy): Point) -> Shape {
+        let mut shape = self.clone();
+        let translate = |(x, y): &mut Point| {
+            *x += dx;
+            *y += dy;
+        };
+        match &mut shape {
+            Shape::Pen { points, .. }
+            | Shape::Highlight { points, .. }
+            | Shape::Pixelate { points, .. } => points.iter_mut().for_each(translate),
+            Shape::Arrow { start, end, .. } | Shape::Rectangle { start, end, .. } => {
+                translate(start);
+                translate(end);
+            }
+            Shape::Text { position, .. } => translate(position),
+        }
+        shape
+    }
+
+    /// Extends the shape as the pointer is dragged to `point`.
+    pub fn drag_to(&mut self, point: Point) {
+        match self {
+            Shape::Pen { points, .. }
+            | Shape::Highlight { points, .. }
+            | Shape::Pixelate { points, .. } => points.push(point),
+            Shape::Arrow { end, .. } | Shape::Rectangle { end, .. } => *end = point,
+            Shape::Text { position, .. } => *position = point,
+        }
+    }
+}
+
+/// Part of an image, in image pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Shapes drawn so far, with undo and redo
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    shapes: Vec<Shape>,
+    undone: Vec<Shape>,
+}
+
+impl History {
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    pub fn push(&mut self, shape: Shape) {
+        self.shapes.push(shape);
+        self.undone.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.shapes.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some(shape) = self.shapes.pop() else {
+            return false;
+        };
+        self.undone.push(shape);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(shape) = self.undone.pop() else {
+            return false;
+        };
+        self.shapes.push(shape);
+        true
+    }
+}
+
+/// Draws `shapes` into `image`, in order.
+pub fn render(image: &mut RgbaImage, shapes: &[Shape]) {
+    if shapes.is_empty() {
+        return;
+    }
+    let Some(mut pixmap) = Pixmap::new(image.width(), image.height()) else {
+        return;
+    };
+    for (dst, src) in pixmap.pixels_mut().iter_mut().zip(image.pixels()) {
+        let [r, g, b, a] = src.0;
+        *dst = ColorU8::from_rgba(r, g, b, a).premultiply();
+    }
+
+    for shape in shapes {
+        match shape {
+            Shape::Pen {
+                points,
+                color,
+                width,
+            } => stroke_polyline(&mut pixmap, points, paint(*color, 1.0), *width),
+            Shape::Highlight {
+                points,
+                color,
+                width,
+            } => {
+                let mut paint = paint(*color, 0.5);
+                paint.blend_mode = BlendMode::Multiply;
+                stroke_polyline(&mut pixmap, points, paint, *width);
+            }
+            Shape::Arrow {
+                start,
+                end,
+                color,
+                width,
+            } => draw_arrow(&mut pixmap, *start, *end, *color, *width),
+            Shape::Rectangle {
+                start,
+                end,
+                color,
+                width,
+            } => {
+                let rect = tiny_skia::Rect::from_ltrb(
+                    start.0.min(end.0),
+                    start.1.min(end.1),
+                    start.0.max(end.0),
+                    start.1.max(end.1),
+                );
+                if let Some(rect) = rect {
+                    let path = PathBuilder::from_rect(rect);
+                    let stroke = Stroke {
+                        width: *width,
+                        line_join: LineJoin::Miter,
+                        ..Stroke::default()
+                    };
+                    pixmap.stroke_path(
+                        &path,
+                        &paint(*color, 1.0),
+                        &stroke,
+                        Transform::identity(),
+                        None,
+                    );
+                }
+            }
+            Shape::Text {
+                position,
+                text,
+                color,
+                size,
+            } => draw_text(&mut pixmap, *position, text, *color, *size),
+            Shape::Pixelate {
+                points,
+                radius,
+                block,
+            } => pixelate(&mut pixmap, points, *radius, *block),
+        }
+    }
+
+    for (dst, src) in image.pixels_mut().zip(pixmap.pixels()) {
+        let color = src.demultiply();
+        dst.0 = [color.red(), color.green(), color.blue(), color.alpha()];
+    }
+}
+
+fn paint(color: [u8; 3], opacity: f32) -> Paint<'static> {
+    let [r, g, b] = color;
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgba8(r, g, b, (opacity * 255.0) as u8));
+    paint.anti_alias = true;
+    paint
+}
+
+fn stroke_polyline(pixmap: &mut Pixmap, points: &[Point], paint: Paint, width: f32) {
+    let Some(&(x, y)) = points.first() else {
+        return;
+    };
+    let mut builder = PathBuilder::new();
+    builder.move_to(x, y);
+    if points.len() == 1 {
+        // A click draws a dot
+        builder.line_to(x + 0.01, y);
+    }
+    for &(x, y) in &points[1..] {
+        builder.line_to(x, y);
+    }
+    let Some(path) = builder.finish() else {
+        return;
+    };
+    let stroke = Stroke {
+        width,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..Stroke::default()
+    };
+    pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+}
+
+fn draw_arrow(pixmap: &mut Pixmap, start: Point, end: Point, color: [u8; 3], width: f32) {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = dx.hypot(dy);
+    if length < 1.0 {
+        return;
+    }
+    let (ux, uy) = (dx / length, dy / length);
+    let head_length = (width * 4.0).min(length);
+    let head_width = width * 2.5;
+    // The line stops inside the head, so its cap doesn't poke out of the tip
+    let base = (end.0 - ux * head_length, end.1 - uy * head_length);
+    let line_end = (base.0 + ux * width, base.1 + uy * width);
+    stroke_polyline(pixmap, &[start, line_end], paint(color, 1.0), width);
+
+    let mut builder = PathBuilder::new();
+    builder.move_to(end.0, end.1);
+    builder.line_to(base.0 - uy * head_width, base.1 + ux * head_width);
+    builder.line_to(base.0 + uy * head_width, base.1 - ux * head_width);
+    builder.close();
+    if let Some(path) = builder.finish() {
+        pixmap.fill_path(
+            &path,
+            &paint(color, 1.0),
+            FillRule::Winding,
+            Transform::identity(),
+            None,
+        );
+    }
+}
+
+fn draw_text(pixmap: &mut Pixmap, (x, y): Point, text: &str, color: [u8; 3], size: f32) {
+    use cosmic_text::{Attrs, Buffer, Metrics, Shaping, SwashCache, Weight};
+
+    let Ok(mut font_system) = font_system().write() else {
+        log::error!("Failed to lock font system for text annotation");
+        return;
+    };
+    let font_system = font_system.raw();
+    let mut buffer = Buffer::new(font_system, Metrics::new(size, size * 1.2));
+    buffer.set_size(font_system, None, None);
+    buffer.set_text(
+        font_system,
+        text,
+        &Attrs::new().weight(Weight::BOLD),
+        Shaping::Advanced,
+        None,
+    );
+    buffer.shape_until_scroll(font_system, false);
+
+    let [r, g, b] = color;
+    let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+    let pixels = pixmap.pixels_mut();
+    let mut swash_cache = SwashCache::new();
+    buffer.draw(
+        font_system,
+        &mut swash_cache,
+        cosmic_text::Color::rgb(r, g, b),
+        |glyph_x, glyph_y, glyph_width, glyph_height, glyph_color| {
+            let source = ColorU8::from_rgba(r, g, b, glyph_color.a()).premultiply();
+            for py in glyph_y..glyph_y + glyph_height as i32 {
+                for px in glyph_x..glyph_x + glyph_width as i32 {
+                    let (px, py) = (x as i32 + px, y as i32 + py);
+                    if px < 0 || py < 0 || px >= width || py >= height {
+                        continue;
+                    }
+                    let pixel = &mut pixels[(py * width + px) as usize];
+                    *pixel = blend(source, *pixel);
+                }
+            }
+        },
+    );
+}
+
+/// Source-over blending of premultiplied colors
+fn blend(src: PremultipliedColorU8, dst: PremultipliedColorU8) -> PremultipliedColorU8 {
+    let inv_alpha = 255 - u16::from(src.alpha());
+    let channel = |s: u8, d: u8| (u16::from(s) + u16::from(d) * inv_alpha / 255) as u8;
+    PremultipliedColorU8::from_rgba(
+        channel(src.red(), dst.red()),
+        channel(src.green(), dst.green()),
+        channel(src.blue(), dst.blue()),
+        channel(src.alpha(), dst.alpha()),
+    )
+    .unwrap_or(dst)
+}
+
+fn pixelate(pixmap: &mut Pixmap, points: &[Point], radius: f32, block: u32) {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let blocks_x = width.div_ceil(block);
+    let blocks_y = height.div_ceil(block);
+
+    // Blocks with their center under the brush, sampled along each segment
+    let mut touched = HashSet::new();
+    let step = (block as f32 / 2.0).max(1.0);
+    let segments = points
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .chain(points.first().map(|&point| (point, point)));
+    for (from, to) in segments {
+        let length = (to.0 - from.0).hypot(to.1 - from.1);
+        let samples = (length / step).ceil() as u32;
+        for i in 0..=samples {
+            let t = if samples == 0 {
+                0.0
+            } else {
+                i as f32 / samples as f32
+            };
+            let (cx, cy) = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+            let min_x = ((cx - radius) / block as f32).floor().max(0.0) as u32;
+            let max_x = (((cx + radius) / block as f32).ceil() as u32).min(blocks_x);
+            let min_y = ((cy - radius) / block as f32).floor().max(0.0) as u32;
+            let max_y = (((cy + radius) / block as f32).ceil() as u32).min(blocks_y);
+            for by in min_y..max_y {
+                for bx in min_x..max_x {
+                    let center_x = (bx as f32 + 0.5) * block as f32;
+                    let center_y = (by as f32 + 0.5) * block as f32;
+                    if (center_x - cx).hypot(center_y - cy) <= radius {
+                        touched.insert((bx, by));
+                    }
+                }
+            }
+        }
+    }
+
+    let pixels = pixmap.pixels_mut();
+    for (bx, by) in touched {
+        let xs = bx * block..((bx + 1) * block).min(width);
+        let ys = by * block..((by + 1) * block).min(height);
+        let mut sum = [0u32; 4];
+        let mut count = 0;
+        for y in ys.clone() {
+            for x in xs.clone() {
+                let pixel = pixels[(y * width + x) as usize];
+                sum[0] += u32::from(pixel.red());
+                sum[1] += u32::from(pixel.green());
+                sum[2] += u32::from(pixel.blue());
+                sum[3] += u32::from(pixel.alpha());
+                count += 1;
+            }
+        }
+        if count == 0 {
+            continue;
+        }
+        let [r, g, b, a] = sum.map(|channel| (channel / count) as u8);
+        let Some(average) = PremultipliedColorU8::from_rgba(r, g, b, a) else {
+            continue;
+        };
+        for y in ys.clone() {
+            for x in xs.clone() {
+                pixels[(y * width + x) as usize] = average;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAY: image::Rgba<u8> = image::Rgba([128, 128, 128, 255]);
+
+    fn rectangle(start: Point, end: Point, color: [u8; 3]) -> Shape {
+        Shape::Rectangle {
+            start,
+            end,
+            color,
+            width: 2.0,
+        }
+    }
+
+    /// Image whose pixels all differ, so pixelating changes them
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([x as u8 * 4, y as u8 * 4, 0, 255])
+        })
+    }
+
+    #[test]
+    fn renders_rectangle_outline() {
+        let mut image = RgbaImage::from_pixel(40, 40, GRAY);
+        render(
+            &mut image,
+            &[rectangle((10.0, 10.0), (30.0, 30.0), COLORS[0])],
+        );
+
+        let [r, g, b] = COLORS[0];
+        assert_eq!(image.get_pixel(10, 20), &image::Rgba([r, g, b, 255]));
+        assert_eq!(image.get_pixel(20, 29), &image::Rgba([r, g, b, 255]));
+        // Inside and outside are left alone
+        assert_eq!(image.get_pixel(20, 20), &GRAY);
+        assert_eq!(image.get_pixel(2, 2), &GRAY);
+    }
+
+    #[test]
+    fn renders_nothing_without_shapes() {
+        let mut image = gradient();
+        render(&mut image, &[]);
+        assert_eq!(image, gradient());
+    }
+
+    #[test]
+    fn pixelates_blocks_under_brush() {
+        let mut image = gradient();
+        let shape = Shape::Pixelate {
+            points: vec![(20.0, 20.0)],
+            radius: 8.0,
+            block: 8,
+        };
+        render(&mut image, &[shape]);
+
+        // The block around the brush has one color
+        let block = *image.get_pixel(16, 16);
+        assert_ne!(block, *gradient().get_pixel(16, 16));
+        for (x, y) in [(17, 17), (23, 16), (16, 23), (23, 23)] {
+            assert_eq!(image.get_pixel(x, y), &block);
+        }
+        // Blocks away from the brush are left alone
+        assert_eq!(image.get_pixel(60, 60), gradient().get_pixel(60, 60));
+    }
+
+    #[test]
+    fn region_renders_like_the_whole_image() {
+        let shapes = [
+            Shape::Pen {
+                points: vec![(5.0, 50.0), (30.0, 40.0), (33.0, 12.0)],
+                color: COLORS[3],
+                width: 4.0,
+            },
+            Shape::Arrow {
+                start: (50.0, 50.0),
+                end: (20.0, 20.0),
+                color: COLORS[1],
+                width: 3.0,
+            },
+            Shape::Pixelate {
+                points: vec![(21.0, 23.0), (40.0, 37.0)],
+                radius: 6.0,
+                block: 5,
+            },
+        ];
+        for shape in shapes {
+            let mut whole = gradient();
+            render(&mut whole, std::slice::from_ref(&shape));
+
+            let region = shape.region(64, 64).unwrap();
+            let mut part = image::imageops::crop_imm(
+                &gradient(),
+                region.x,
+                region.y,
+                region.width,
+                region.height,
+            )
+            .to_image();
+            render(
+                &mut part,
+                &[shape.translated((-(region.x as f32), -(region.y as f32)))],
+            );
+
+            let mut combined = gradient();
+            image::imageops::replace(&mut combined, &part, region.x.into(), region.y.into());
+            // Anti-aliasing may round differently away from the origin
+            for (a, b) in combined.pixels().zip(whole.pixels()) {
+                for (a, b) in a.0.iter().zip(b.0) {
+                    assert!(a.abs_diff(b) <= 2, "{shape:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn region_is_clamped_to_image() {
+        let shape = rectangle((-10.0, -10.0), (5.0, 5.0), COLORS[0]);
+        let region = shape.region(64, 64).unwrap();
+        assert_eq!((region.x, region.y), (0, 0));
+        assert!(region.width <= 64 && region.height <= 64);
+
+        let outside = rectangle((100.0, 100.0), (120.0, 120.0), COLORS[0]);
+        assert_eq!(outside.region(64, 64), None);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let first = rectangle((0.0, 0.0), (1.0, 1.0), COLORS[0]);
+        let second = rectangle((2.0, 2.0), (3.0, 3.0), COLORS[1]);
+        let mut history = History::default();
+        assert!(!history.can_undo());
+        assert!(!history.undo());
+
+        history.push(first);
+        history.push(second);
+        assert!(history.undo());
+        assert_eq!(history.shapes().len(), 1);
+        assert!(history.can_redo());
+
+        assert!(history.redo());
+        assert_eq!(history.shapes().len(), 2);
+        assert!(!history.can_redo());
+        assert!(!history.redo());
+    }
+
+    #[test]
+    fn push_clears_redo() {
+        let mut history = History::default();
+        history.push(rectangle((0.0, 0.0), (1.0, 1.0), COLORS[0]));
+        assert!(history.undo());
+        history.push(rectangle((2.0, 2.0), (3.0, 3.0), COLORS[1]));
+        assert!(!history.can_redo());
+        assert_eq!(history.shapes().len(), 1);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{access, config, file_chooser, screencast_dialog, screenshot, subscription};
+use crate::{
+    access, app_chooser, color_picker, config, file_chooser, global_shortcuts_dialog,
+    remote_desktop_dialog, screencast_dialog, screenshot, settings, subscription, wallpaper,
+};
 use cosmic::iced::core::event::wayland::OutputEvent;
 use cosmic::iced::platform_specific::shell::commands::layer_surface::get_layer_surface;
 use cosmic::iced::runtime::platform_specific::wayland::layer_surface::{
@@ -35,10 +38,18 @@ pub struct CosmicPortal {
     pub file_choosers: HashMap<window::Id, (file_chooser::Args, file_chooser::Dialog)>,
 
     pub screenshot_args: Option<screenshot::Args>,
+    pub screenshot_countdown: Option<screenshot::Countdown>,
+    pub screenshot_scroll: Option<screenshot::ScrollCapture>,
+    pub color_picker_args: Option<color_picker::Args>,
     pub screencast_args: Option<screencast_dialog::Args>,
     pub screencast_tab_model:
         widget::segmented_button::Model<widget::segmented_button::SingleSelect>,
+    pub remote_desktop_args: Option<remote_desktop_dialog::Args>,
+    pub wallpaper_args: Option<wallpaper::Args>,
+    pub app_chooser_args: Option<app_chooser::Args>,
+    pub global_shortcuts_args: Option<global_shortcuts_dialog::Args>,
     pub location_options: Vec<String>,
+    pub delay_options: Vec<String>,
     pub prev_rectangle: Option<screenshot::Rect>,
     pub wayland_helper: crate::wayland::WaylandHelper,
 
@@ -63,7 +74,12 @@ pub enum Msg {
     Access(access::Msg),
     FileChooser(window::Id, file_chooser::Msg),
     Screenshot(screenshot::Msg),
+    ColorPicker(color_picker::Msg),
     Screencast(screencast_dialog::Msg),
+    RemoteDesktop(remote_desktop_dialog::Msg),
+    Wallpaper(wallpaper::Msg),
+    AppChooser(app_chooser::Msg),
+    GlobalShortcuts(global_shortcuts_dialog::Msg),
     Portal(subscription::Event),
     Output(OutputEvent, WlOutput),
     ConfigSetScreenshot(config::screenshot::Screenshot),
@@ -112,9 +128,17 @@ impl cosmic::Application for CosmicPortal {
                 access_args: Default::default(),
                 file_choosers: Default::default(),
                 screenshot_args: Default::default(),
+                screenshot_countdown: Default::default(),
+                screenshot_scroll: Default::default(),
+                color_picker_args: Default::default(),
                 screencast_args: Default::default(),
                 screencast_tab_model: Default::default(),
+                remote_desktop_args: Default::default(),
+                wallpaper_args: Default::default(),
+                app_chooser_args: Default::default(),
+                global_shortcuts_args: Default::default(),
                 location_options: Vec::new(),
+                delay_options: Vec::new(),
                 prev_rectangle: Default::default(),
                 outputs: Default::default(),
                 active_output: Default::default(),
@@ -147,8 +171,38 @@ impl cosmic::Application for CosmicPortal {
             access::view(self).map(Msg::Access)
         } else if id == *screencast_dialog::SCREENCAST_ID {
             screencast_dialog::view(self).map(Msg::Screencast)
+        } else if id == *remote_desktop_dialog::REMOTE_DESKTOP_ID {
+            remote_desktop_dialog::view(self).map(Msg::RemoteDesktop)
+        } else if id == *app_chooser::APP_CHOOSER_ID {
+            app_chooser::view(self).map(Msg::AppChooser)
+        } else if id == *global_shortcuts_dialog::GLOBAL_SHORTCUTS_ID {
+            global_shortcuts_dialog::view(self).map(Msg::GlobalShortcuts)
+        } else if self
+            .color_picker_args
+            .as_ref()
+            .is_some_and(|args| args.surfaces.contains_key(&id))
+        {
+            color_picker::view(self, id).map(Msg::ColorPicker)
+        } else if self
+            .wallpaper_args
+            .as_ref()
+            .is_some_and(|args| args.surfaces.contains_key(&id))
+        {
+            wallpaper::view(self, id).map(Msg::Wallpaper)
         } else if self.outputs.iter().any(|o| o.id == id) {
             screenshot::view(self, id).map(Msg::Screenshot)
+        } else if self
+            .screenshot_countdown
+            .as_ref()
+            .is_some_and(|countdown| countdown.surface == id)
+        {
+            screenshot::countdown_view(self).map(Msg::Screenshot)
+        } else if self
+            .screenshot_scroll
+            .as_ref()
+            .is_some_and(|scroll| scroll.surface == id)
+        {
+            screenshot::scroll_view(self).map(Msg::Screenshot)
         } else if self.dummy_id == id {
             widget::space::Space::new()
                 .width(Length::Fill)
@@ -174,16 +228,53 @@ impl cosmic::Application for CosmicPortal {
                 subscription::Event::Screenshot(args) => {
                     screenshot::update_args(self, args).map(cosmic::Action::App)
                 }
+                subscription::Event::ScreenshotCountdown(seconds, tx) => {
+                    screenshot::countdown(self, seconds, screenshot::AfterCountdown::Notify(tx))
+                        .map(cosmic::Action::App)
+                }
+                subscription::Event::PickColor(args) => {
+                    color_picker::update_args(self, args).map(cosmic::Action::App)
+                }
                 subscription::Event::Screencast(args) => {
                     screencast_dialog::update_args(self, args).map(cosmic::Action::App)
                 }
                 subscription::Event::CancelScreencast(handle) => {
                     screencast_dialog::cancel(self, handle).map(cosmic::Action::App)
                 }
+                subscription::Event::RemoteDesktop(args) => {
+                    remote_desktop_dialog::update_args(self, args).map(cosmic::Action::App)
+                }
+                subscription::Event::CancelRemoteDesktop(handle) => {
+                    remote_desktop_dialog::cancel(self, handle).map(cosmic::Action::App)
+                }
+                subscription::Event::Wallpaper(args) => {
+                    wallpaper::update_args(self, args).map(cosmic::Action::App)
+                }
+                subscription::Event::AppChooser(args) => {
+                    app_chooser::update_args(self, args).map(cosmic::Action::App)
+                }
+                subscription::Event::UpdateAppChooser(handle, choices) => {
+                    app_chooser::update_choices(self, handle, choices).map(cosmic::Action::App)
+                }
+                subscription::Event::CancelAppChooser(handle) => {
+                    app_chooser::cancel(self, handle).map(cosmic::Action::App)
+                }
+                subscription::Event::GlobalShortcuts(args) => {
+                    global_shortcuts_dialog::update_args(self, args).map(cosmic::Action::App)
+                }
+                subscription::Event::CancelGlobalShortcuts(handle) => {
+                    global_shortcuts_dialog::cancel(self, handle).map(cosmic::Action::App)
+                }
                 subscription::Event::Config(config) => self.update(Msg::ConfigSubUpdate(config)),
-                subscription::Event::Accent(_)
-                | subscription::Event::IsDark(_)
-                | subscription::Event::HighContrast(_) => cosmic::iced::Task::none(),
+                // Config changes for the settings portal
+                event @ subscription::Event::Settings(_) => {
+                    if let Some(tx) = self.tx.clone() {
+                        tokio::spawn(async move {
+                            _ = tx.send(event).await;
+                        });
+                    }
+                    Task::none()
+                }
                 subscription::Event::Init(tx) => {
                     self.tx = Some(tx);
                     Task::none()
@@ -194,7 +285,16 @@ impl cosmic::Application for CosmicPortal {
                 }
             },
             Msg::Screenshot(m) => screenshot::update_msg(self, m).map(cosmic::Action::App),
+            Msg::ColorPicker(m) => color_picker::update_msg(self, m).map(cosmic::Action::App),
             Msg::Screencast(m) => screencast_dialog::update_msg(self, m).map(cosmic::Action::App),
+            Msg::RemoteDesktop(m) => {
+                remote_desktop_dialog::update_msg(self, m).map(cosmic::Action::App)
+            }
+            Msg::Wallpaper(m) => wallpaper::update_msg(self, m).map(cosmic::Action::App),
+            Msg::AppChooser(m) => app_chooser::update_msg(self, m).map(cosmic::Action::App),
+            Msg::GlobalShortcuts(m) => {
+                global_shortcuts_dialog::update_msg(self, m).map(cosmic::Action::App)
+            }
             Msg::Output(o_event, wl_output) => {
                 match o_event {
                     OutputEvent::Created(Some(info))
@@ -323,7 +423,9 @@ impl cosmic::Application for CosmicPortal {
             && let Some(tx) = self.tx.clone()
         {
             tokio::spawn(async move {
-                _ = tx.send(subscription::Event::IsDark(new)).await;
+                _ = tx
+                    .send(subscription::Event::Settings(settings::Update::IsDark(new)))
+                    .await;
             });
         }
         Task::none()
@@ -334,25 +436,12 @@ impl cosmic::Application for CosmicPortal {
         _keys: &[&'static str],
         new_theme: &cosmic::cosmic_theme::Theme,
     ) -> cosmic::iced::Task<cosmic::Action<Self::Message>> {
-        let old = self.core.system_theme().cosmic();
-        let mut msgs = Vec::with_capacity(3);
-
-        if old.accent_color() != new_theme.accent_color() {
-            msgs.push(subscription::Event::Accent(new_theme.accent_color()));
-        }
-        if old.is_high_contrast != new_theme.is_high_contrast {
-            msgs.push(subscription::Event::HighContrast(
-                new_theme.is_high_contrast,
-            ));
-        }
-        {
-            if let Some(tx) = self.tx.clone() {
-                tokio::spawn(async move {
-                    for msg in msgs {
-                        _ = tx.send(msg).await;
-                    }
-                });
-            }
+        // Only changed settings are signalled, so the whole theme is sent
+        if let Some(tx) = self.tx.clone() {
+            let update = settings::Update::Theme(Box::new(new_theme.clone()));
+            tokio::spawn(async move {
+                _ = tx.send(subscription::Event::Settings(update)).await;
+            });
         }
         Task::none()
     }
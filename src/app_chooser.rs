@@ -0,0 +1,431 @@
+use cosmic::desktop::IconSourceExt;
+use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::key::Named;
+use cosmic::iced::platform_specific::shell::commands::layer_surface::{
+    KeyboardInteractivity, Layer, destroy_layer_surface, get_layer_surface,
+};
+use cosmic::iced::runtime::platform_specific::wayland::layer_surface::SctkLayerSurfaceSettings;
+use cosmic::iced::{self, window};
+use cosmic::widget::{self, autosize};
+use cosmic::{Task, theme};
+use cosmic_files::mime_app::MimeAppCache;
+use freedesktop_desktop_entry::{DesktopEntry, IconSource, get_languages_from_env};
+use std::sync::LazyLock;
+use tokio::sync::mpsc;
+use zbus::zvariant;
+
+use crate::app::CosmicPortal;
+use crate::screencast_dialog::{get_desktop_entry, load_desktop_entries};
+use crate::widget::keyboard_wrapper::KeyboardWrapper;
+use crate::{PortalResponse, Request, fl, subscription};
+
+pub static APP_CHOOSER_ID: LazyLock<window::Id> = LazyLock::new(window::Id::unique);
+pub static APP_CHOOSER_WIDGET_ID: LazyLock<widget::Id> =
+    LazyLock::new(|| widget::Id::new("app-chooser".to_string()));
+static SEARCH_ID: LazyLock<widget::Id> =
+    LazyLock::new(|| widget::Id::new("app-chooser-search".to_string()));
+
+#[derive(zvariant::DeserializeDict, zvariant::Type, Debug)]
+#[zvariant(signature = "a{sv}")]
+struct ChooseApplicationOptions {
+    last_choice: Option<String>,
+    content_type: Option<String>,
+    filename: Option<String>,
+}
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct ChooseApplicationResult {
+    choice: String,
+}
+
+/// An application that can be chosen, from its desktop entry
+#[derive(Clone, Debug)]
+pub struct Choice {
+    /// Desktop file ID, without the `.desktop` suffix
+    id: String,
+    name: String,
+    icon: Option<String>,
+    mime_types: Vec<String>,
+}
+
+fn app_choices(entries: &[DesktopEntry], locales: &[String], ids: &[String]) -> Vec<Choice> {
+    ids.iter()
+        .map(|id| {
+            let entry = get_desktop_entry(entries, id);
+            Choice {
+                id: id.clone(),
+                name: entry
+                    .and_then(|x| Some(x.name(locales)?.into_owned()))
+                    .unwrap_or_else(|| id.clone()),
+                icon: entry.and_then(|x| Some(x.icon()?.to_string())),
+                mime_types: entry
+                    .and_then(|x| x.mime_type())
+                    .into_iter()
+                    .flatten()
+                    .map(str::to_string)
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Makes `app_id` the default handler for `content_type`, with the mime-apps handling of
+/// cosmic-files' "Open with" dialog
+async fn set_default_app(content_type: &str, app_id: &str) -> anyhow::Result<()> {
+    let mime = content_type.parse::<mime::Mime>()?;
+    let app_id = app_id.to_string();
+    tokio::task::spawn_blocking(move || MimeAppCache::new().set_default(mime, app_id)).await?;
+    Ok(())
+}
+
+pub struct AppChooser {
+    tx: mpsc::Sender<subscription::Event>,
+}
+
+impl AppChooser {
+    pub fn new(tx: mpsc::Sender<subscription::Event>) -> Self {
+        Self { tx }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.AppChooser")]
+impl AppChooser {
+    async fn choose_application(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        choices: Vec<String>,
+        options: ChooseApplicationOptions,
+    ) -> PortalResponse<ChooseApplicationResult> {
+        log::debug!("Choose application {app_id} {parent_window} {choices:?} {options:?}");
+        let on_cancel = || async {
+            let _ = self
+                .tx
+                .send(subscription::Event::CancelAppChooser(handle.to_owned()))
+                .await;
+        };
+        Request::run(connection, &handle, on_cancel, async {
+            let locales = get_languages_from_env();
+            let desktop_entries = load_desktop_entries(&locales).await;
+            let app_name = get_desktop_entry(&desktop_entries, app_id)
+                .and_then(|x| Some(x.name(&locales)?.into_owned()));
+            let choices = app_choices(&desktop_entries, &locales, &choices);
+            let selected = options.last_choice.clone();
+
+            let (tx, mut rx) = mpsc::channel(1);
+            let args = Args {
+                handle: handle.to_owned(),
+                app_name,
+                content_type: options.content_type.clone(),
+                filename: options.filename.clone(),
+                choices,
+                selected,
+                search: String::new(),
+                always_use: false,
+                tx,
+            };
+            if let Err(err) = self.tx.send(subscription::Event::AppChooser(args)).await {
+                log::error!("Failed to send app chooser event, {}", err);
+                return PortalResponse::Other;
+            }
+
+            let Some(Some((choice, always_use))) = rx.recv().await else {
+                return PortalResponse::Cancelled;
+            };
+            if always_use
+                && let Some(content_type) = &options.content_type
+                && let Err(err) = set_default_app(content_type, &choice).await
+            {
+                log::error!("Failed to set default application: {}", err);
+            }
+            PortalResponse::Success(ChooseApplicationResult { choice })
+        })
+        .await
+    }
+
+    async fn update_choices(&self, handle: zvariant::ObjectPath<'_>, choices: Vec<String>) {
+        let locales = get_languages_from_env();
+        let desktop_entries = load_desktop_entries(&locales).await;
+        let choices = app_choices(&desktop_entries, &locales, &choices);
+        if let Err(err) = self
+            .tx
+            .send(subscription::Event::UpdateAppChooser(
+                handle.to_owned(),
+                choices,
+            ))
+            .await
+        {
+            log::error!("Failed to send app chooser update, {}", err);
+        }
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+}
+
+fn create_dialog() -> Task<crate::app::Msg> {
+    get_layer_surface(SctkLayerSurfaceSettings {
+        id: *APP_CHOOSER_ID,
+        keyboard_interactivity: KeyboardInteractivity::Exclusive,
+        namespace: "app chooser".into(),
+        layer: Layer::Overlay,
+        size: None,
+        ..Default::default()
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Args {
+    handle: zvariant::ObjectPath<'static>,
+    app_name: Option<String>,
+    content_type: Option<String>,
+    filename: Option<String>,
+    choices: Vec<Choice>,
+    /// ID of the selected choice
+    selected: Option<String>,
+    search: String,
+    always_use: bool,
+    // Should be oneshot, but need `Clone` bound
+    tx: mpsc::Sender<Option<(String, bool)>>,
+}
+
+impl Args {
+    fn send_response(self, response: Option<(String, bool)>) {
+        tokio::spawn(async move {
+            if let Err(err) = self.tx.send(response).await {
+                log::error!("Failed to send app chooser response: {}", err);
+            }
+        });
+    }
+
+    /// Choices that handle the content type and match the search. If no choice
+    /// lists the content type, they are all shown.
+    fn visible_choices(&self) -> impl Iterator<Item = &Choice> {
+        let handles_type = |choice: &Choice| {
+            self.content_type
+                .as_ref()
+                .is_none_or(|content_type| choice.mime_types.contains(content_type))
+        };
+        let filter_type = self.choices.iter().any(handles_type);
+        let search = self.search.to_lowercase();
+        self.choices.iter().filter(move |choice| {
+            (!filter_type || handles_type(choice)) && choice.name.to_lowercase().contains(&search)
+        })
+    }
+
+    fn select_first_visible(&mut self) {
+        let selected_visible = self
+            .visible_choices()
+            .any(|choice| Some(&choice.id) == self.selected.as_ref());
+        if !selected_visible {
+            self.selected = self
+                .visible_choices()
+                .next()
+                .map(|choice| choice.id.clone());
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Msg {
+    Select(String),
+    SelectNext,
+    SelectPrevious,
+    Search(String),
+    AlwaysUse(bool),
+    Open,
+    Cancel,
+}
+
+pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> Task<crate::app::Msg> {
+    let Some(args) = portal.app_chooser_args.as_mut() else {
+        return Task::none();
+    };
+
+    match msg {
+        Msg::Select(id) => args.selected = Some(id),
+        Msg::SelectNext | Msg::SelectPrevious => {
+            let next = matches!(msg, Msg::SelectNext);
+            let visible: Vec<_> = args.visible_choices().map(|x| x.id.clone()).collect();
+            let position = visible
+                .iter()
+                .position(|id| Some(id) == args.selected.as_ref());
+            let position = match position {
+                Some(i) if next => (i + 1).min(visible.len().saturating_sub(1)),
+                Some(i) => i.saturating_sub(1),
+                None => 0,
+            };
+            if let Some(id) = visible.get(position) {
+                args.selected = Some(id.clone());
+            }
+        }
+        Msg::Search(search) => {
+            args.search = search;
+            args.select_first_visible();
+        }
+        Msg::AlwaysUse(always_use) => args.always_use = always_use,
+        Msg::Open => {
+            if args.selected.is_some() {
+                let mut args = portal.app_chooser_args.take().unwrap();
+                let response = args.selected.take().map(|id| (id, args.always_use));
+                args.send_response(response);
+                return destroy_layer_surface(*APP_CHOOSER_ID);
+            }
+        }
+        Msg::Cancel => {
+            let args = portal.app_chooser_args.take().unwrap();
+            args.send_response(None);
+            return destroy_layer_surface(*APP_CHOOSER_ID);
+        }
+    }
+    Task::none()
+}
+
+pub fn update_args(portal: &mut CosmicPortal, mut args: Args) -> Task<crate::app::Msg> {
+    // If the dialog is already open, cancel previous request, but re-use dialog surface
+    let command = if let Some(args) = portal.app_chooser_args.take() {
+        args.send_response(None);
+        Task::none()
+    } else {
+        create_dialog()
+    };
+    args.select_first_visible();
+    portal.app_chooser_args = Some(args);
+    Task::batch([command, widget::text_input::focus(SEARCH_ID.clone())])
+}
+
+pub fn update_choices(
+    portal: &mut CosmicPortal,
+    handle: zvariant::ObjectPath<'static>,
+    choices: Vec<Choice>,
+) -> Task<crate::app::Msg> {
+    if let Some(args) = portal
+        .app_chooser_args
+        .as_mut()
+        .filter(|args| args.handle == handle)
+    {
+        args.choices = choices;
+        args.select_first_visible();
+    }
+    Task::none()
+}
+
+pub fn cancel(
+    portal: &mut CosmicPortal,
+    handle: zvariant::ObjectPath<'static>,
+) -> Task<crate::app::Msg> {
+    if portal
+        .app_chooser_args
+        .as_ref()
+        .is_some_and(|args| args.handle == handle)
+    {
+        let args = portal.app_chooser_args.take().unwrap();
+        args.send_response(None);
+        destroy_layer_surface(*APP_CHOOSER_ID)
+    } else {
+        Task::none()
+    }
+}
+
+fn choice_button(choice: &Choice, is_selected: bool) -> cosmic::Element<'_, Msg> {
+    let icon = IconSource::from_unknown(choice.icon.as_deref().unwrap_or_default());
+    let mut children = vec![
+        icon.as_cosmic_icon().icon().size(32).into(),
+        widget::text(&choice.name).width(iced::Length::Fill).into(),
+    ];
+    if is_selected {
+        children.push(
+            widget::icon::from_name("object-select-symbolic")
+                .size(16)
+                .icon()
+                .into(),
+        );
+    }
+    widget::button::custom(
+        widget::row::with_children(children)
+            .spacing(12)
+            .align_y(iced::Alignment::Center),
+    )
+    .width(iced::Length::Fill)
+    .class(theme::style::Button::MenuItem)
+    .selected(is_selected)
+    .on_press(Msg::Select(choice.id.clone()))
+    .into()
+}
+
+pub(crate) fn view(portal: &CosmicPortal) -> cosmic::Element<'_, Msg> {
+    let Some(args) = portal.app_chooser_args.as_ref() else {
+        return widget::space::horizontal()
+            .width(iced::Length::Fixed(1.0))
+            .into();
+    };
+    let cancel_button = widget::button::standard(fl!("cancel")).on_press(Msg::Cancel);
+    let mut open_button =
+        widget::button::standard(fl!("open")).class(cosmic::style::Button::Suggested);
+    if args.selected.is_some() {
+        open_button = open_button.on_press(Msg::Open);
+    }
+
+    let search = widget::search_input(fl!("search-applications"), &args.search)
+        .id(SEARCH_ID.clone())
+        .on_input(Msg::Search)
+        .on_submit(|_| Msg::Open);
+
+    let mut list = widget::column::with_capacity(args.choices.len());
+    for choice in args.visible_choices() {
+        let is_selected = args.selected.as_ref() == Some(&choice.id);
+        list = list.push(choice_button(choice, is_selected));
+    }
+    let list = widget::container(widget::scrollable(list))
+        .max_height(380.)
+        .width(iced::Length::Fill);
+
+    let mut control = widget::column::with_children(vec![search.into(), list.into()]).spacing(8);
+    if let Some(content_type) = &args.content_type {
+        control = control.push(
+            widget::checkbox(
+                fl!("always-use", content_type = content_type.as_str()),
+                args.always_use,
+            )
+            .on_toggle(Msg::AlwaysUse),
+        );
+    }
+
+    let body = match &args.filename {
+        Some(filename) => fl!("open-with", "file", filename = filename.as_str()),
+        None => {
+            let unknown = fl!("unknown-application");
+            let app_name = args.app_name.as_deref().unwrap_or(&unknown);
+            fl!("open-with", "description", app_name = app_name)
+        }
+    };
+
+    autosize::autosize(
+        KeyboardWrapper::new(
+            widget::dialog()
+                .title(fl!("open-with"))
+                .body(body)
+                .secondary_action(cancel_button)
+                .primary_action(open_button)
+                .control(control),
+            |key, _| match key {
+                Key::Named(Named::Enter) => Some(Msg::Open),
+                Key::Named(Named::Escape) => Some(Msg::Cancel),
+                Key::Named(Named::ArrowDown) => Some(Msg::SelectNext),
+                Key::Named(Named::ArrowUp) => Some(Msg::SelectPrevious),
+                _ => None,
+            },
+        ),
+        APP_CHOOSER_WIDGET_ID.clone(),
+    )
+    .max_width(480.)
+    .max_height(720.)
+    .min_width(1.)
+    .min_height(1.)
+    .into()
+}
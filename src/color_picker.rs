@@ -0,0 +1,142 @@
+use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::key::Named;
+use cosmic::iced::platform_specific::shell::commands::layer_surface::{
+    destroy_layer_surface, get_layer_surface,
+};
+use cosmic::iced::runtime::platform_specific::wayland::layer_surface::{
+    IcedOutput, SctkLayerSurfaceSettings,
+};
+use cosmic::iced::{Length, Limits, window};
+use cosmic::widget::space;
+use cosmic_client_toolkit::sctk::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use zbus::zvariant;
+
+use crate::PortalResponse;
+use crate::app::{CosmicPortal, OutputState};
+use crate::screenshot::ScreenshotImage;
+use crate::widget::color_picker::ColorPicker;
+use crate::widget::keyboard_wrapper::KeyboardWrapper;
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+pub struct PickColorResult {
+    color: (f64, f64, f64), // (ddd)
+}
+
+impl From<[u8; 3]> for PickColorResult {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        // Components are scaled linearly to the 0..1 range
+        Self {
+            color: (
+                f64::from(r) / 255.,
+                f64::from(g) / 255.,
+                f64::from(b) / 255.,
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Args {
+    pub handle: zvariant::ObjectPath<'static>,
+    pub app_id: String,
+    pub parent_window: String,
+    pub output_images: HashMap<String, ScreenshotImage>,
+    pub tx: Sender<PortalResponse<PickColorResult>>,
+    /// Layer surface for each output, by output name. Filled in by `update_args`.
+    pub surfaces: HashMap<window::Id, String>,
+}
+
+impl Args {
+    fn send_response(self, response: PortalResponse<PickColorResult>) {
+        tokio::spawn(async move {
+            if let Err(err) = self.tx.send(response).await {
+                log::error!("Failed to send pick color response: {}", err);
+            }
+        });
+    }
+
+    fn destroy_surfaces(&self) -> cosmic::Task<crate::app::Msg> {
+        cosmic::Task::batch(self.surfaces.keys().map(|id| destroy_layer_surface(*id)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Msg {
+    Pick([u8; 3]),
+    Cancel,
+}
+
+pub(crate) fn view(portal: &CosmicPortal, id: window::Id) -> cosmic::Element<'_, Msg> {
+    let Some(img) = portal.color_picker_args.as_ref().and_then(|args| {
+        let name = args.surfaces.get(&id)?;
+        args.output_images.get(name)
+    }) else {
+        return space::horizontal().width(Length::Fixed(1.0)).into();
+    };
+
+    KeyboardWrapper::new(
+        ColorPicker::new(&img.rgba, img.handle.clone(), Msg::Pick),
+        |key, _| match key {
+            Key::Named(Named::Escape) => Some(Msg::Cancel),
+            _ => None,
+        },
+    )
+    .into()
+}
+
+pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::app::Msg> {
+    let Some(args) = portal.color_picker_args.take() else {
+        log::error!("Failed to find color picker Args for {:?} message.", msg);
+        return cosmic::Task::none();
+    };
+    let cmd = args.destroy_surfaces();
+    match msg {
+        Msg::Pick(color) => args.send_response(PortalResponse::Success(color.into())),
+        Msg::Cancel => args.send_response(PortalResponse::Cancelled),
+    }
+    cmd
+}
+
+pub fn update_args(portal: &mut CosmicPortal, mut args: Args) -> cosmic::Task<crate::app::Msg> {
+    let mut cmds = Vec::with_capacity(portal.outputs.len() + 1);
+
+    // Only one color picker can be shown at a time; cancel the previous request
+    if let Some(prev) = portal.color_picker_args.take() {
+        cmds.push(prev.destroy_surfaces());
+        prev.send_response(PortalResponse::Cancelled);
+    }
+
+    for OutputState { output, name, .. } in &portal.outputs {
+        if !args.output_images.contains_key(name) {
+            log::warn!("No color picker image for output {}", name);
+            continue;
+        }
+        let id = window::Id::unique();
+        args.surfaces.insert(id, name.clone());
+        cmds.push(get_layer_surface(SctkLayerSurfaceSettings {
+            id,
+            layer: Layer::Overlay,
+            keyboard_interactivity: KeyboardInteractivity::Exclusive,
+            input_zone: None,
+            anchor: Anchor::all(),
+            output: IcedOutput::Output(output.clone()),
+            namespace: "color picker".to_string(),
+            size: Some((None, None)),
+            exclusive_zone: -1,
+            size_limits: Limits::NONE.min_height(1.0).min_width(1.0),
+            ..Default::default()
+        }));
+    }
+
+    if args.surfaces.is_empty() {
+        log::error!("No outputs to show color picker on");
+        args.send_response(PortalResponse::Other);
+        return cosmic::Task::batch(cmds);
+    }
+
+    portal.color_picker_args = Some(args);
+    cosmic::Task::batch(cmds)
+}
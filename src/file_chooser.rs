@@ -5,8 +5,8 @@ use cosmic_files::dialog::{
     DialogResult, DialogSettings,
 };
 use std::ffi::OsString;
-use std::os::unix::ffi::OsStringExt;
-use std::path::PathBuf;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 use tokio::sync::mpsc::Sender;
 use zbus::zvariant;
 
@@ -33,6 +33,22 @@ pub struct OpenFileOptions {
     current_folder: Option<Vec<u8>>,
 }
 
+impl OpenFileOptions {
+    /// Options for choosing one folder, for dialogs opened by the portal itself
+    pub fn folder(accept_label: String, current_folder: Option<&Path>) -> Self {
+        Self {
+            accept_label: Some(accept_label),
+            modal: None,
+            multiple: Some(false),
+            directory: Some(true),
+            filters: None,
+            current_filter: None,
+            choices: None,
+            current_folder: current_folder.map(|path| path.as_os_str().as_bytes().to_vec()),
+        }
+    }
+}
+
 #[derive(zvariant::DeserializeDict, zvariant::Type, Clone, Debug)]
 #[zvariant(signature = "a{sv}")]
 pub struct SaveFileOptions {
@@ -135,6 +151,16 @@ pub struct FileChooserResult {
     current_filter: Option<Filter>,
 }
 
+impl FileChooserResult {
+    /// Local paths of the chosen files
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.uris
+            .iter()
+            .filter_map(|uri| url::Url::parse(uri).ok()?.to_file_path().ok())
+            .collect()
+    }
+}
+
 pub struct FileChooser {
     tx: Sender<subscription::Event>,
 }
@@ -0,0 +1,528 @@
+use cosmic_config::{ConfigGet, ConfigSet};
+use cosmic_settings_config::shortcuts::{self, Action, Binding};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::Sender;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant;
+
+use crate::config::{self, global_shortcuts::Shortcut};
+use crate::{DBUS_NAME, DBUS_PATH, PortalResponse, Request, global_shortcuts_dialog, subscription};
+
+const ACTIVATIONS_INTERFACE: &str = "com.system76.CosmicPortal.GlobalShortcuts";
+const GLOBAL_SHORTCUTS_KEY: &str = "global_shortcuts";
+// Keys of the system and user defined shortcuts in the COSMIC shortcuts config
+const DEFAULT_SHORTCUTS_KEY: &str = "defaults";
+const CUSTOM_SHORTCUTS_KEY: &str = "custom";
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct CreateSessionResult {
+    session_id: String,
+}
+
+#[derive(zvariant::DeserializeDict, zvariant::Type, Debug)]
+#[zvariant(signature = "a{sv}")]
+struct NewShortcut {
+    description: Option<String>,
+    preferred_trigger: Option<String>,
+}
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct ShortcutInfo {
+    description: String,
+    trigger_description: String,
+}
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct ShortcutsResult {
+    shortcuts: Vec<(String, ShortcutInfo)>,
+}
+
+impl ShortcutsResult {
+    fn new(shortcuts: &[Shortcut]) -> Self {
+        Self {
+            shortcuts: shortcuts
+                .iter()
+                .map(|shortcut| {
+                    let info = ShortcutInfo {
+                        description: shortcut.description.clone(),
+                        trigger_description: cosmic_trigger(&shortcut.trigger),
+                    };
+                    (shortcut.id.clone(), info)
+                })
+                .collect(),
+        }
+    }
+}
+
+struct SessionData {
+    app_id: String,
+    /// IDs of the shortcuts bound in this session
+    shortcuts: Vec<String>,
+}
+
+type Sessions = Arc<Mutex<HashMap<zvariant::OwnedObjectPath, SessionData>>>;
+
+/// Converts a trigger in the XDG shortcuts format, like `CTRL+ALT+p`, to the
+/// format of COSMIC keybindings, like `Ctrl+Alt+p`.
+fn cosmic_trigger(trigger: &str) -> String {
+    trigger
+        .split('+')
+        .map(|part| match part.to_ascii_uppercase().as_str() {
+            "CTRL" | "CONTROL" => "Ctrl",
+            "ALT" => "Alt",
+            "SHIFT" => "Shift",
+            "LOGO" | "SUPER" => "Super",
+            _ => part,
+        })
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Start of the commands for `app_id`'s keybindings
+fn activate_command_prefix(app_id: &str) -> String {
+    format!(
+        "dbus-send --session --type=method_call --dest={DBUS_NAME} {DBUS_PATH} {ACTIVATIONS_INTERFACE}.Activate string:{} string:",
+        shell_quote(app_id)
+    )
+}
+
+/// Command the compositor runs for a keybinding, calling `Activate` on the
+/// private activation interface.
+fn activate_command(app_id: &str, shortcut_id: &str) -> String {
+    format!(
+        "{}{}",
+        activate_command_prefix(app_id),
+        shell_quote(shortcut_id)
+    )
+}
+
+/// Why a trigger can't be bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerConflict {
+    /// Not a key combination
+    Invalid,
+    /// Used by another keybinding
+    Taken,
+}
+
+/// Parses a trigger, checking it isn't one of the `taken` bindings
+pub fn check_trigger(trigger: &str, taken: &HashSet<Binding>) -> Result<Binding, TriggerConflict> {
+    let binding =
+        Binding::from_str(&cosmic_trigger(trigger)).map_err(|_| TriggerConflict::Invalid)?;
+    if taken.contains(&binding) {
+        return Err(TriggerConflict::Taken);
+    }
+    Ok(binding)
+}
+
+/// The default COSMIC keybindings, and the custom ones without `app_id`'s global shortcuts
+fn keybindings(
+    context: &cosmic_config::Config,
+    app_id: &str,
+) -> (shortcuts::Shortcuts, shortcuts::Shortcuts) {
+    let defaults = context
+        .get::<shortcuts::Shortcuts>(DEFAULT_SHORTCUTS_KEY)
+        .unwrap_or_default();
+    let mut custom = context
+        .get::<shortcuts::Shortcuts>(CUSTOM_SHORTCUTS_KEY)
+        .unwrap_or_default();
+    let prefix = activate_command_prefix(app_id);
+    custom.0.retain(
+        |_, action| !matches!(action, Action::Spawn(command) if command.starts_with(&prefix)),
+    );
+    (defaults, custom)
+}
+
+/// Bindings `app_id`'s shortcuts can't use, since other keybindings have them
+pub fn taken_bindings(app_id: &str) -> HashSet<Binding> {
+    let context = match shortcuts::context() {
+        Ok(context) => context,
+        Err(err) => {
+            log::error!("Failed to open shortcuts config: {}", err);
+            return HashSet::new();
+        }
+    };
+    let (defaults, custom) = keybindings(&context, app_id);
+    defaults.0.into_keys().chain(custom.0.into_keys()).collect()
+}
+
+fn load_shortcuts() -> config::global_shortcuts::GlobalShortcuts {
+    cosmic_config::Config::new(config::APP_ID, config::CONFIG_VERSION)
+        .and_then(|handler| handler.get(GLOBAL_SHORTCUTS_KEY))
+        .unwrap_or_default()
+}
+
+/// Persists the app's shortcuts, and replaces the app's COSMIC keybindings with them.
+///
+/// Triggers that are invalid or already used by another keybinding are left
+/// unbound. Returns the IDs of those shortcuts.
+fn save_shortcuts(app_id: &str, app_shortcuts: Vec<Shortcut>) -> anyhow::Result<Vec<String>> {
+    let handler = cosmic_config::Config::new(config::APP_ID, config::CONFIG_VERSION)?;
+    let mut global_shortcuts = load_shortcuts();
+    global_shortcuts
+        .apps
+        .insert(app_id.to_string(), app_shortcuts.clone());
+    handler.set(GLOBAL_SHORTCUTS_KEY, global_shortcuts)?;
+
+    let context = shortcuts::context()?;
+    let (defaults, mut custom) = keybindings(&context, app_id);
+    let mut taken: HashSet<_> = defaults
+        .0
+        .into_keys()
+        .chain(custom.0.keys().cloned())
+        .collect();
+    let mut unbound = Vec::new();
+    for shortcut in &app_shortcuts {
+        if shortcut.trigger.is_empty() {
+            continue;
+        }
+        match check_trigger(&shortcut.trigger, &taken) {
+            Ok(binding) => {
+                taken.insert(binding.clone());
+                custom.0.insert(
+                    binding,
+                    Action::Spawn(activate_command(app_id, &shortcut.id)),
+                );
+            }
+            Err(TriggerConflict::Taken) => {
+                log::warn!(
+                    "Shortcut trigger '{}' is already bound, leaving '{}' unbound",
+                    shortcut.trigger,
+                    shortcut.id
+                );
+                unbound.push(shortcut.id.clone());
+            }
+            Err(TriggerConflict::Invalid) => {
+                log::error!("Invalid shortcut trigger '{}'", shortcut.trigger);
+                unbound.push(shortcut.id.clone());
+            }
+        }
+    }
+    context.set(CUSTOM_SHORTCUTS_KEY, custom)?;
+    Ok(unbound)
+}
+
+pub struct GlobalShortcuts {
+    tx: Sender<subscription::Event>,
+    sessions: Sessions,
+}
+
+impl GlobalShortcuts {
+    pub fn new(tx: Sender<subscription::Event>) -> Self {
+        Self {
+            tx,
+            sessions: Default::default(),
+        }
+    }
+
+    /// Interface for receiving activations from the compositor's keybindings
+    pub fn activations(&self) -> Activations {
+        Activations {
+            sessions: self.sessions.clone(),
+        }
+    }
+
+    fn session_app_id(&self, session_handle: &zvariant::ObjectPath<'_>) -> Option<String> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(&zvariant::OwnedObjectPath::from(session_handle.to_owned()))?;
+        Some(session.app_id.clone())
+    }
+
+    /// Emits `ShortcutsChanged` for each of the app's sessions
+    async fn emit_shortcuts_changed(
+        &self,
+        connection: &zbus::Connection,
+        app_id: &str,
+        app_shortcuts: &[Shortcut],
+    ) {
+        let changed: Vec<_> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, session)| session.app_id == app_id)
+            .map(|(session_handle, session)| {
+                let shortcuts: Vec<_> = app_shortcuts
+                    .iter()
+                    .filter(|shortcut| session.shortcuts.contains(&shortcut.id))
+                    .cloned()
+                    .collect();
+                (session_handle.clone(), ShortcutsResult::new(&shortcuts))
+            })
+            .collect();
+
+        let signal_ctxt = match SignalEmitter::new(connection, DBUS_PATH) {
+            Ok(signal_ctxt) => signal_ctxt,
+            Err(err) => {
+                log::error!("Failed to create signal emitter: {}", err);
+                return;
+            }
+        };
+        for (session_handle, result) in changed {
+            if let Err(err) =
+                Self::shortcuts_changed(&signal_ctxt, session_handle.as_ref(), result.shortcuts)
+                    .await
+            {
+                log::error!("Failed to emit shortcuts changed: {}", err);
+            }
+        }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.GlobalShortcuts")]
+impl GlobalShortcuts {
+    async fn create_session(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        _handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: String,
+        _options: HashMap<String, zvariant::OwnedValue>,
+    ) -> PortalResponse<CreateSessionResult> {
+        let key = zvariant::OwnedObjectPath::from(session_handle.to_owned());
+        self.sessions.lock().unwrap().insert(
+            key.clone(),
+            SessionData {
+                app_id,
+                shortcuts: Vec::new(),
+            },
+        );
+        let sessions = self.sessions.clone();
+        let session = crate::Session::new((), move |_| {
+            sessions.lock().unwrap().remove(&key);
+        });
+        if let Err(err) = connection
+            .object_server()
+            .at(&session_handle, session)
+            .await
+        {
+            log::error!("Failed to create global shortcuts session: {}", err);
+            return PortalResponse::Other;
+        }
+        PortalResponse::Success(CreateSessionResult {
+            session_id: session_handle.to_string(),
+        })
+    }
+
+    async fn bind_shortcuts(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcuts: Vec<(String, NewShortcut)>,
+        _parent_window: String,
+        _options: HashMap<String, zvariant::OwnedValue>,
+    ) -> PortalResponse<ShortcutsResult> {
+        let on_cancel = || global_shortcuts_dialog::hide_prompt(&self.tx, &handle);
+        Request::run(connection, &handle, on_cancel, async {
+            let Some(app_id) = self.session_app_id(&session_handle) else {
+                return PortalResponse::Other;
+            };
+
+            let global_shortcuts = load_shortcuts();
+            let stored = global_shortcuts.app(&app_id);
+            let requested: Vec<Shortcut> = shortcuts
+                .into_iter()
+                .map(|(id, new)| {
+                    let stored = stored.iter().find(|shortcut| shortcut.id == id);
+                    Shortcut {
+                        description: new
+                            .description
+                            .or_else(|| Some(stored?.description.clone()))
+                            .unwrap_or_default(),
+                        trigger: stored
+                            .map(|shortcut| shortcut.trigger.clone())
+                            .or(new.preferred_trigger)
+                            .unwrap_or_default(),
+                        id,
+                    }
+                })
+                .collect();
+
+            // Only ask the user if there are shortcuts they haven't seen before
+            let is_new = requested
+                .iter()
+                .any(|shortcut| !stored.iter().any(|x| x.id == shortcut.id));
+            let bound = if is_new {
+                let taken = taken_bindings(&app_id);
+                match global_shortcuts_dialog::show_prompt(
+                    &self.tx, &handle, &app_id, requested, taken,
+                )
+                .await
+                {
+                    Some(bound) => bound,
+                    None => return PortalResponse::Cancelled,
+                }
+            } else {
+                requested
+            };
+
+            let mut app_shortcuts = stored.to_vec();
+            for shortcut in &bound {
+                match app_shortcuts.iter_mut().find(|x| x.id == shortcut.id) {
+                    Some(x) => *x = shortcut.clone(),
+                    None => app_shortcuts.push(shortcut.clone()),
+                }
+            }
+            let unbound = match save_shortcuts(&app_id, app_shortcuts.clone()) {
+                Ok(unbound) => unbound,
+                Err(err) => {
+                    log::error!("Failed to save global shortcuts: {}", err);
+                    return PortalResponse::Other;
+                }
+            };
+            let bound: Vec<_> = bound
+                .into_iter()
+                .filter(|shortcut| !unbound.contains(&shortcut.id))
+                .collect();
+
+            {
+                let key = zvariant::OwnedObjectPath::from(session_handle.to_owned());
+                let mut sessions = self.sessions.lock().unwrap();
+                for (session_handle, session) in sessions.iter_mut() {
+                    if *session_handle == key {
+                        session.shortcuts =
+                            bound.iter().map(|shortcut| shortcut.id.clone()).collect();
+                    } else if session.app_id == app_id {
+                        session.shortcuts.retain(|id| !unbound.contains(id));
+                    }
+                }
+            }
+            self.emit_shortcuts_changed(connection, &app_id, &app_shortcuts)
+                .await;
+
+            PortalResponse::Success(ShortcutsResult::new(&bound))
+        })
+        .await
+    }
+
+    async fn list_shortcuts(
+        &self,
+        _handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+    ) -> PortalResponse<ShortcutsResult> {
+        let key = zvariant::OwnedObjectPath::from(session_handle.to_owned());
+        let Some((app_id, ids)) = self
+            .sessions
+            .lock()
+            .unwrap()
+            .get(&key)
+            .map(|session| (session.app_id.clone(), session.shortcuts.clone()))
+        else {
+            return PortalResponse::Other;
+        };
+        let global_shortcuts = load_shortcuts();
+        let bound: Vec<_> = global_shortcuts
+            .app(&app_id)
+            .iter()
+            .filter(|shortcut| ids.contains(&shortcut.id))
+            .cloned()
+            .collect();
+        PortalResponse::Success(ShortcutsResult::new(&bound))
+    }
+
+    #[zbus(signal)]
+    async fn activated(
+        signal_ctxt: &SignalEmitter<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcut_id: &str,
+        timestamp: u64,
+        options: HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn deactivated(
+        signal_ctxt: &SignalEmitter<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcut_id: &str,
+        timestamp: u64,
+        options: HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn shortcuts_changed(
+        signal_ctxt: &SignalEmitter<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcuts: Vec<(String, ShortcutInfo)>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+/// Private interface the compositor's keybindings call when a shortcut is pressed.
+///
+/// This is not a security boundary. Any unsandboxed client on the session bus can call
+/// `Activate`, but such a client can already send input as the user, and sandboxed apps
+/// can't reach the portal's bus name.
+pub struct Activations {
+    sessions: Sessions,
+}
+
+#[zbus::interface(name = "com.system76.CosmicPortal.GlobalShortcuts")]
+impl Activations {
+    async fn activate(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        app_id: &str,
+        shortcut_id: &str,
+    ) -> zbus::fdo::Result<()> {
+        let session_handles: Vec<_> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, session)| {
+                session.app_id == app_id && session.shortcuts.iter().any(|id| id == shortcut_id)
+            })
+            .map(|(session_handle, _)| session_handle.clone())
+            .collect();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+
+        let signal_ctxt = match SignalEmitter::new(connection, DBUS_PATH) {
+            Ok(signal_ctxt) => signal_ctxt,
+            Err(err) => {
+                log::error!("Failed to create signal emitter: {}", err);
+                return Err(zbus::fdo::Error::Failed(err.to_string()));
+            }
+        };
+        for session_handle in session_handles {
+            if let Err(err) = GlobalShortcuts::activated(
+                &signal_ctxt,
+                session_handle.as_ref(),
+                shortcut_id,
+                timestamp,
+                HashMap::new(),
+            )
+            .await
+            {
+                log::error!("Failed to emit shortcut activation: {}", err);
+            }
+            // Holding a shortcut down isn't supported: COSMIC keybindings only run their
+            // command on press, so the release is reported right away
+            let _ = GlobalShortcuts::deactivated(
+                &signal_ctxt,
+                session_handle.as_ref(),
+                shortcut_id,
+                timestamp,
+                HashMap::new(),
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
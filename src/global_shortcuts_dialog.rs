@@ -0,0 +1,244 @@
+use crate::app::CosmicPortal;
+use crate::config::global_shortcuts::Shortcut;
+use crate::fl;
+use crate::global_shortcuts::{TriggerConflict, check_trigger};
+use crate::screencast_dialog::{get_desktop_entry, load_desktop_entries};
+use crate::widget::keyboard_wrapper::KeyboardWrapper;
+use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::key::Named;
+use cosmic::iced::platform_specific::shell::commands::layer_surface::{
+    KeyboardInteractivity, Layer, destroy_layer_surface, get_layer_surface,
+};
+use cosmic::iced::runtime::platform_specific::wayland::layer_surface::SctkLayerSurfaceSettings;
+use cosmic::iced::{self, window};
+use cosmic::widget;
+use cosmic::widget::autosize;
+use cosmic_settings_config::shortcuts::Binding;
+use freedesktop_desktop_entry::get_languages_from_env;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use tokio::sync::mpsc;
+use zbus::zvariant;
+
+pub static GLOBAL_SHORTCUTS_ID: LazyLock<window::Id> = LazyLock::new(window::Id::unique);
+pub static GLOBAL_SHORTCUTS_WIDGET_ID: LazyLock<widget::Id> =
+    LazyLock::new(|| widget::Id::new("global-shortcuts".to_string()));
+
+pub async fn hide_prompt(
+    subscription_tx: &mpsc::Sender<crate::subscription::Event>,
+    handle: &zvariant::ObjectPath<'_>,
+) {
+    let _ = subscription_tx
+        .send(crate::subscription::Event::CancelGlobalShortcuts(
+            handle.to_owned(),
+        ))
+        .await;
+}
+
+/// Asks the user to confirm the shortcuts `app_id` wants to bind, and lets them
+/// edit the triggers. Triggers using one of the `taken` bindings are marked as
+/// going to be left unassigned. Returns the confirmed shortcuts, or `None` if cancelled.
+pub async fn show_prompt(
+    subscription_tx: &mpsc::Sender<crate::subscription::Event>,
+    handle: &zvariant::ObjectPath<'_>,
+    app_id: &str,
+    shortcuts: Vec<Shortcut>,
+    taken: HashSet<Binding>,
+) -> Option<Vec<Shortcut>> {
+    let locales = get_languages_from_env();
+    let desktop_entries = load_desktop_entries(&locales).await;
+    let app_name = get_desktop_entry(&desktop_entries, app_id)
+        .and_then(|x| Some(x.name(&locales)?.into_owned()));
+
+    let (tx, mut rx) = mpsc::channel(1);
+    let args = Args {
+        handle: handle.to_owned(),
+        app_name,
+        shortcuts,
+        taken,
+        tx,
+    };
+    subscription_tx
+        .send(crate::subscription::Event::GlobalShortcuts(args))
+        .await
+        .unwrap();
+    rx.recv().await.flatten()
+}
+
+fn create_dialog() -> cosmic::Task<crate::app::Msg> {
+    get_layer_surface(SctkLayerSurfaceSettings {
+        id: *GLOBAL_SHORTCUTS_ID,
+        keyboard_interactivity: KeyboardInteractivity::Exclusive,
+        namespace: "global shortcuts".into(),
+        layer: Layer::Overlay,
+        size: None,
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    handle: zvariant::ObjectPath<'static>,
+    app_name: Option<String>,
+    shortcuts: Vec<Shortcut>,
+    /// Bindings of other keybindings
+    taken: HashSet<Binding>,
+    // Should be oneshot, but need `Clone` bound
+    tx: mpsc::Sender<Option<Vec<Shortcut>>>,
+}
+
+impl Args {
+    fn send_response(self, response: Option<Vec<Shortcut>>) {
+        tokio::spawn(async move {
+            if let Err(err) = self.tx.send(response).await {
+                log::error!("Failed to send global shortcuts response: {}", err);
+            }
+        });
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Msg {
+    Trigger(usize, String),
+    Allow,
+    Cancel,
+}
+
+pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::app::Msg> {
+    let Some(args) = portal.global_shortcuts_args.as_mut() else {
+        return cosmic::Task::none();
+    };
+
+    match msg {
+        Msg::Trigger(i, trigger) => {
+            if let Some(shortcut) = args.shortcuts.get_mut(i) {
+                shortcut.trigger = trigger;
+            }
+            cosmic::Task::none()
+        }
+        Msg::Allow => {
+            let mut args = portal.global_shortcuts_args.take().unwrap();
+            let shortcuts = std::mem::take(&mut args.shortcuts);
+            args.send_response(Some(shortcuts));
+            destroy_layer_surface(*GLOBAL_SHORTCUTS_ID)
+        }
+        Msg::Cancel => {
+            let args = portal.global_shortcuts_args.take().unwrap();
+            args.send_response(None);
+            destroy_layer_surface(*GLOBAL_SHORTCUTS_ID)
+        }
+    }
+}
+
+pub fn update_args(portal: &mut CosmicPortal, args: Args) -> cosmic::Task<crate::app::Msg> {
+    // If the dialog is already open, cancel previous request, but re-use dialog surface
+    let command = if let Some(args) = portal.global_shortcuts_args.take() {
+        args.send_response(None);
+        cosmic::Task::none()
+    } else {
+        create_dialog()
+    };
+    portal.global_shortcuts_args = Some(args);
+    command
+}
+
+pub fn cancel(
+    portal: &mut CosmicPortal,
+    handle: zvariant::ObjectPath<'static>,
+) -> cosmic::Task<crate::app::Msg> {
+    if portal
+        .global_shortcuts_args
+        .as_ref()
+        .is_some_and(|args| args.handle == handle)
+    {
+        let args = portal.global_shortcuts_args.take().unwrap();
+        args.send_response(None);
+        destroy_layer_surface(*GLOBAL_SHORTCUTS_ID)
+    } else {
+        cosmic::Task::none()
+    }
+}
+
+pub(crate) fn view(portal: &CosmicPortal) -> cosmic::Element<'_, Msg> {
+    let Some(args) = portal.global_shortcuts_args.as_ref() else {
+        return widget::space::horizontal()
+            .width(iced::Length::Fixed(1.0))
+            .into();
+    };
+    let cancel_button = widget::button::standard(fl!("cancel")).on_press(Msg::Cancel);
+    let allow_button = widget::button::standard(fl!("allow"))
+        .class(cosmic::style::Button::Suggested)
+        .on_press(Msg::Allow);
+
+    let mut shortcuts = widget::ListColumn::new();
+    // Bindings of the rows above, which the app's later shortcuts can't use either
+    let mut bound = Vec::new();
+    for (i, shortcut) in args.shortcuts.iter().enumerate() {
+        let description: &str = if shortcut.description.is_empty() {
+            &shortcut.id
+        } else {
+            &shortcut.description
+        };
+        let conflict = if shortcut.trigger.is_empty() {
+            None
+        } else {
+            match check_trigger(&shortcut.trigger, &args.taken) {
+                Ok(binding) if bound.contains(&binding) => Some(TriggerConflict::Taken),
+                Ok(binding) => {
+                    bound.push(binding);
+                    None
+                }
+                Err(conflict) => Some(conflict),
+            }
+        };
+        let mut label = widget::column::with_capacity(2)
+            .push(widget::text(description))
+            .width(iced::Length::Fill);
+        if let Some(conflict) = conflict {
+            label = label.push(widget::text::caption(match conflict {
+                TriggerConflict::Invalid => fl!("global-shortcuts", "invalid"),
+                TriggerConflict::Taken => fl!("global-shortcuts", "taken"),
+            }));
+        }
+        shortcuts = shortcuts.add(
+            widget::row::with_children(vec![
+                label.into(),
+                widget::text_input(fl!("global-shortcuts", "unassigned"), &shortcut.trigger)
+                    .on_input(move |trigger| Msg::Trigger(i, trigger))
+                    .width(iced::Length::Fixed(180.))
+                    .into(),
+            ])
+            .spacing(12)
+            .align_y(iced::Alignment::Center),
+        );
+    }
+    let control = widget::column::with_children(vec![
+        shortcuts.into(),
+        widget::text::caption(fl!("global-shortcuts", "hint")).into(),
+    ])
+    .spacing(8);
+
+    let unknown = fl!("unknown-application");
+    let app_name = args.app_name.as_deref().unwrap_or(&unknown);
+
+    autosize::autosize(
+        KeyboardWrapper::new(
+            widget::dialog()
+                .title(fl!("global-shortcuts"))
+                .body(fl!("global-shortcuts", "description", app_name = app_name))
+                .secondary_action(cancel_button)
+                .primary_action(allow_button)
+                .control(control),
+            |key, _| match key {
+                Key::Named(Named::Enter) => Some(Msg::Allow),
+                Key::Named(Named::Escape) => Some(Msg::Cancel),
+                _ => None,
+            },
+        ),
+        GLOBAL_SHORTCUTS_WIDGET_ID.clone(),
+    )
+    .max_width(572.)
+    .min_width(1.)
+    .min_height(1.)
+    .into()
+}
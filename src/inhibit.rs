@@ -0,0 +1,265 @@
+use futures::StreamExt;
+use futures::future::{AbortHandle, abortable};
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, zvariant};
+
+use crate::{DBUS_PATH, PORTAL_RESPONSE_OTHER, PORTAL_RESPONSE_SUCCESS};
+
+const INHIBIT_LOGOUT: u32 = 1;
+const INHIBIT_USER_SWITCH: u32 = 2;
+const INHIBIT_SUSPEND: u32 = 4;
+const INHIBIT_IDLE: u32 = 8;
+
+const SESSION_STATE_RUNNING: u32 = 1;
+
+/// Idle inhibition, implemented by cosmic-idle
+#[zbus::proxy(
+    interface = "org.freedesktop.ScreenSaver",
+    default_service = "org.freedesktop.ScreenSaver",
+    default_path = "/org/freedesktop/ScreenSaver"
+)]
+trait ScreenSaver {
+    fn inhibit(&self, application_name: &str, reason_for_inhibit: &str) -> zbus::Result<u32>;
+
+    fn un_inhibit(&self, cookie: u32) -> zbus::Result<()>;
+
+    fn get_active(&self) -> zbus::Result<bool>;
+
+    #[zbus(signal)]
+    fn active_changed(&self, active: bool) -> zbus::Result<()>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait LogindManager {
+    fn inhibit(
+        &self,
+        what: &str,
+        who: &str,
+        why: &str,
+        mode: &str,
+    ) -> zbus::Result<zvariant::OwnedFd>;
+}
+
+#[derive(zvariant::DeserializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct InhibitOptions {
+    reason: Option<String>,
+}
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}", rename_all = "kebab-case")]
+struct State {
+    screensaver_active: bool,
+    session_state: u32,
+}
+
+/// Request object for an active inhibition. Closing it releases the inhibition.
+struct InhibitRequest {
+    screensaver: Option<(ScreenSaverProxy<'static>, u32)>,
+    // Logind releases the inhibitor lock when this is closed
+    logind_fd: Option<zvariant::OwnedFd>,
+}
+
+impl InhibitRequest {
+    async fn release(&mut self) {
+        if let Some((screensaver, cookie)) = self.screensaver.take()
+            && let Err(err) = screensaver.un_inhibit(cookie).await
+        {
+            log::error!("Failed to release idle inhibitor: {}", err);
+        }
+        self.logind_fd = None;
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Request")]
+impl InhibitRequest {
+    async fn close(&mut self, #[zbus(signal_context)] signal_ctxt: SignalEmitter<'_>) {
+        self.release().await;
+        let _ = signal_ctxt
+            .connection()
+            .object_server()
+            .remove::<Self, _>(signal_ctxt.path())
+            .await;
+    }
+}
+
+pub struct Inhibit;
+
+impl Inhibit {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Inhibit")]
+impl Inhibit {
+    /// Fails, without inhibiting anything, unless every flag can be honoured
+    async fn inhibit(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        window: &str,
+        flags: u32,
+        options: InhibitOptions,
+    ) -> fdo::Result<()> {
+        log::debug!("Inhibit {app_id} {window} {flags}");
+        // COSMIC has no session manager API to delay logout or user switching
+        if flags & (INHIBIT_LOGOUT | INHIBIT_USER_SWITCH) != 0 {
+            return Err(fdo::Error::NotSupported(
+                "Inhibiting logout and user switch isn't supported".to_string(),
+            ));
+        }
+
+        let reason = options.reason.unwrap_or_default();
+        let mut request = InhibitRequest {
+            screensaver: None,
+            logind_fd: None,
+        };
+
+        if flags & INHIBIT_IDLE != 0 {
+            let result = async {
+                let screensaver = ScreenSaverProxy::new(connection).await?;
+                let cookie = screensaver.inhibit(app_id, &reason).await?;
+                zbus::Result::Ok((screensaver, cookie))
+            };
+            match result.await {
+                Ok(screensaver) => request.screensaver = Some(screensaver),
+                Err(err) => {
+                    log::error!("Failed to inhibit idle: {}", err);
+                    return Err(fdo::Error::Failed(format!("Failed to inhibit idle: {err}")));
+                }
+            }
+        }
+
+        if flags & INHIBIT_SUSPEND != 0 {
+            match logind_inhibit("sleep", app_id, &reason).await {
+                Ok(fd) => request.logind_fd = Some(fd),
+                Err(err) => {
+                    log::error!("Failed to inhibit suspend: {}", err);
+                    request.release().await;
+                    return Err(fdo::Error::Failed(format!(
+                        "Failed to inhibit suspend: {err}"
+                    )));
+                }
+            }
+        }
+
+        if let Err(err) = connection.object_server().at(&handle, request).await {
+            log::error!("Failed to create inhibit request object: {}", err);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    async fn create_monitor(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        _handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        _app_id: &str,
+        _window: &str,
+    ) -> u32 {
+        let (task, abort_handle) = abortable(monitor(
+            connection.clone(),
+            session_handle.clone().into_owned(),
+        ));
+        let session = crate::Session::new(abort_handle, |abort_handle: &mut AbortHandle| {
+            abort_handle.abort()
+        });
+        if let Err(err) = connection
+            .object_server()
+            .at(&session_handle, session)
+            .await
+        {
+            log::error!("Failed to create inhibit monitor session: {}", err);
+            return PORTAL_RESPONSE_OTHER;
+        }
+        tokio::spawn(task);
+        PORTAL_RESPONSE_SUCCESS
+    }
+
+    /// Only valid after `StateChanged` reports the session is ending, which isn't done
+    async fn query_end_response(
+        &self,
+        _session_handle: zvariant::ObjectPath<'_>,
+    ) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "Session never queries the end of inhibitions".to_string(),
+        ))
+    }
+
+    #[zbus(signal)]
+    async fn state_changed(
+        signal_ctxt: &SignalEmitter<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        state: State,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        3
+    }
+}
+
+async fn logind_inhibit(what: &str, who: &str, why: &str) -> zbus::Result<zvariant::OwnedFd> {
+    let connection = zbus::Connection::system().await?;
+    let logind = LogindManagerProxy::new(&connection).await?;
+    logind.inhibit(what, who, why, "block").await
+}
+
+/// Reports screensaver state to the monitor session until it's closed.
+async fn monitor(connection: zbus::Connection, session_handle: zvariant::OwnedObjectPath) {
+    let emit = |screensaver_active| {
+        let connection = &connection;
+        let session_handle = &session_handle;
+        async move {
+            let signal_ctxt = SignalEmitter::new(connection, DBUS_PATH)?;
+            Inhibit::state_changed(
+                &signal_ctxt,
+                session_handle.as_ref(),
+                State {
+                    screensaver_active,
+                    session_state: SESSION_STATE_RUNNING,
+                },
+            )
+            .await
+        }
+    };
+
+    // The initial state is sent after querying the screensaver, by which time
+    // the frontend has received the `CreateMonitor` response.
+    let screensaver = match ScreenSaverProxy::new(&connection).await {
+        Ok(screensaver) => Some(screensaver),
+        Err(err) => {
+            log::error!("Failed to connect to screensaver service: {}", err);
+            None
+        }
+    };
+    let mut active = false;
+    if let Some(screensaver) = &screensaver {
+        active = screensaver.get_active().await.unwrap_or(false);
+    }
+    if let Err(err) = emit(active).await {
+        log::error!("Failed to emit inhibit state: {}", err);
+    }
+
+    let Some(screensaver) = screensaver else {
+        return;
+    };
+    let Ok(mut active_changed) = screensaver.receive_active_changed().await else {
+        return;
+    };
+    while let Some(signal) = active_changed.next().await {
+        let Ok(args) = signal.args() else {
+            continue;
+        };
+        if let Err(err) = emit(args.active).await {
+            log::error!("Failed to emit inhibit state: {}", err);
+        }
+    }
+}
@@ -1,23 +1,38 @@
-use cosmic::cosmic_theme::palette::Srgba;
 use futures::future::{AbortHandle, abortable};
 use std::collections::HashMap;
 use std::future::Future;
 use zbus::object_server::{InterfaceRef, SignalEmitter};
-use zbus::zvariant::{self, OwnedValue};
+use zbus::zvariant;
 
 pub use cosmic_portal_config as config;
 
 mod access;
+mod annotation;
 mod app;
+mod app_chooser;
 mod buffer;
+mod color_picker;
 mod documents;
 mod file_chooser;
+mod global_shortcuts;
+mod global_shortcuts_dialog;
+mod inhibit;
 mod localize;
+mod ocr;
+mod permission_store;
+mod remote_desktop;
+mod remote_desktop_dialog;
+mod remote_desktop_eis;
 mod screencast;
 mod screencast_dialog;
 mod screencast_thread;
 mod screenshot;
+mod screenshot_editor;
+mod screenshot_history;
+mod scroll_capture;
+mod settings;
 mod subscription;
+mod wallpaper;
 mod wayland;
 mod widget;
 
@@ -160,174 +175,6 @@ async fn session_interface<Data: Send + Sync + 'static>(
         .ok()
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum ColorScheme {
-    /// No preference
-    NoPreference,
-    /// Prefers dark appearance
-    PreferDark,
-    /// Prefers light appearance
-    PreferLight,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum Contrast {
-    /// No preference
-    NoPreference,
-    /// Higher contrast
-    High,
-}
-
-#[derive(Debug, Clone, Copy, zvariant::Value, zvariant::OwnedValue)]
-struct Color {
-    red: f64,
-    green: f64,
-    blue: f64,
-}
-
-const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
-const COLOR_SCHEME_KEY: &str = "color-scheme";
-const ACCENT_COLOR_KEY: &str = "accent-color";
-const CONTRAST_KEY: &str = "contrast";
-
-const GNOME_DESKTOP_INTERFACE_NAMESPACE: &str = "org.gnome.desktop.interface";
-const TEXT_SCALING_FACTOR_KEY: &str = "text-scaling-factor";
-
-/// Matches a namespace pattern against a known namespace.
-/// Supports trailing '*' glob (e.g., "org.gnome.*" matches "org.gnome.desktop.interface").
-fn namespace_matches(pattern: &str, namespace: &str) -> bool {
-    if let Some(prefix) = pattern.strip_suffix('*') {
-        namespace.starts_with(prefix)
-    } else {
-        pattern == namespace
-    }
-}
-
-struct Settings {
-    pub color_scheme: ColorScheme,
-    pub contrast: Contrast,
-    pub accent: Srgba<f64>,
-}
-
-impl Settings {
-    pub fn new() -> Self {
-        let theme = cosmic::theme::system_preference();
-        let cosmic = theme.cosmic();
-        Self {
-            contrast: if cosmic.is_high_contrast {
-                Contrast::High
-            } else {
-                Contrast::NoPreference
-            },
-            color_scheme: if cosmic.is_dark {
-                ColorScheme::PreferDark
-            } else {
-                ColorScheme::PreferLight
-            },
-            accent: cosmic.accent_color().into_format(),
-        }
-    }
-}
-
-#[zbus::interface(name = "org.freedesktop.impl.portal.Settings")]
-impl Settings {
-    /// Read method (deprecated)
-    async fn read(&self, namespace: &str, key: &str) -> zbus::fdo::Result<zvariant::OwnedValue> {
-        self.read_one(namespace, key).await
-    }
-
-    /// ReadAll method
-    async fn read_all(
-        &self,
-        namespaces: Vec<&str>,
-    ) -> HashMap<String, HashMap<String, OwnedValue>> {
-        let mut map = HashMap::new();
-
-        let all_namespaces: &[&str] = &[APPEARANCE_NAMESPACE, GNOME_DESKTOP_INTERFACE_NAMESPACE];
-
-        for &known_ns in all_namespaces {
-            let matched = namespaces.is_empty()
-                || namespaces
-                    .iter()
-                    .any(|pattern| namespace_matches(pattern, known_ns));
-            if !matched {
-                continue;
-            }
-            match known_ns {
-                APPEARANCE_NAMESPACE => {
-                    let mut inner = HashMap::new();
-                    inner.insert(
-                        COLOR_SCHEME_KEY.to_string(),
-                        OwnedValue::from(self.color_scheme as u32),
-                    );
-                    inner.insert(
-                        CONTRAST_KEY.to_string(),
-                        OwnedValue::from(self.contrast as u32),
-                    );
-                    if let Ok(value) = OwnedValue::try_from(Color {
-                        red: self.accent.red,
-                        green: self.accent.green,
-                        blue: self.accent.blue,
-                    }) {
-                        inner.insert(ACCENT_COLOR_KEY.to_string(), value);
-                    }
-                    map.insert(APPEARANCE_NAMESPACE.to_string(), inner);
-                }
-                GNOME_DESKTOP_INTERFACE_NAMESPACE => {
-                    let mut inner = HashMap::new();
-                    inner.insert(
-                        TEXT_SCALING_FACTOR_KEY.to_string(),
-                        OwnedValue::from(1.0_f64),
-                    );
-                    map.insert(GNOME_DESKTOP_INTERFACE_NAMESPACE.to_string(), inner);
-                }
-                _ => {}
-            }
-        }
-        map
-    }
-
-    /// ReadOne method
-    async fn read_one(&self, namespace: &str, key: &str) -> zbus::fdo::Result<OwnedValue> {
-        match (namespace, key) {
-            (APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY) => {
-                Ok(OwnedValue::from(self.color_scheme as u32))
-            }
-            (APPEARANCE_NAMESPACE, CONTRAST_KEY) => Ok(OwnedValue::from(self.contrast as u32)),
-            (APPEARANCE_NAMESPACE, ACCENT_COLOR_KEY) => OwnedValue::try_from(Color {
-                red: self.accent.red,
-                green: self.accent.green,
-                blue: self.accent.blue,
-            })
-            .map_err(|e| zbus::fdo::Error::Failed(e.to_string())),
-            (GNOME_DESKTOP_INTERFACE_NAMESPACE, TEXT_SCALING_FACTOR_KEY) => {
-                Ok(OwnedValue::from(1.0_f64))
-            }
-            _ => Err(zbus::fdo::Error::Failed(
-                "Unknown namespace or key".to_string(),
-            )),
-        }
-    }
-
-    /// SettingChanged signal
-    #[zbus(signal)]
-    async fn setting_changed(
-        &self,
-        _signal_ctxt: &SignalEmitter<'_>,
-        namespace: &str,
-        key: &str,
-        value: zvariant::Value<'_>,
-    ) -> zbus::Result<()>;
-
-    /// version property
-    #[zbus(property, name = "version")]
-    fn version(&self) -> u32 {
-        2
-    }
-}
-
 fn main() -> cosmic::iced::Result {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
     localize::localize();
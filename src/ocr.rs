@@ -0,0 +1,36 @@
+//! Recognizes text in screenshots with a local `tesseract`, so nothing leaves the machine
+
+use image::RgbaImage;
+use std::io::Cursor;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+pub async fn recognize_text(image: RgbaImage, language: &str) -> anyhow::Result<String> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout", "-l", language])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("failed to run tesseract: {err}"))?;
+
+    // tesseract reads all of stdin before writing, so this can't fill the stdout pipe
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&png).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "tesseract failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
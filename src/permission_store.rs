@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+use zbus::zvariant;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.impl.portal.PermissionStore",
+    default_service = "org.freedesktop.impl.portal.PermissionStore",
+    default_path = "/org/freedesktop/impl/portal/PermissionStore"
+)]
+pub trait PermissionStore {
+    fn lookup(
+        &self,
+        table: &str,
+        id: &str,
+    ) -> zbus::Result<(HashMap<String, Vec<String>>, zvariant::OwnedValue)>;
+
+    fn set(
+        &self,
+        table: &str,
+        create: bool,
+        id: &str,
+        app_permissions: HashMap<&str, Vec<&str>>,
+        data: &zvariant::Value<'_>,
+    ) -> zbus::Result<()>;
+
+    fn delete(&self, table: &str, id: &str) -> zbus::Result<()>;
+}
@@ -0,0 +1,558 @@
+use ashpd::desktop::remote_desktop::DeviceType;
+use ashpd::enumflags2::BitFlags;
+use futures::future::{AbortHandle, abortable};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use tokio::sync::mpsc::Sender;
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_pointer;
+use zbus::{fdo, zvariant};
+
+use crate::screencast::{self, SessionData, StreamProps};
+use crate::wayland::{VirtualKeyboard, VirtualPointer, WaylandHelper};
+use crate::{
+    PortalResponse, Request, remote_desktop_dialog, remote_desktop_eis, screencast_dialog,
+    subscription,
+};
+
+const AXIS_VERTICAL_SCROLL: u32 = 0;
+const AXIS_HORIZONTAL_SCROLL: u32 = 1;
+
+const KEY_STATE_PRESSED: u32 = 1;
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct CreateSessionResult {
+    session_id: String,
+}
+
+#[derive(zvariant::DeserializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct SelectDevicesOptions {
+    // Default: all
+    types: Option<u32>,
+}
+
+#[derive(zvariant::DeserializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct NotifyPointerAxisOptions {
+    finish: Option<bool>,
+}
+
+#[derive(zvariant::SerializeDict, zvariant::Type)]
+#[zvariant(signature = "a{sv}")]
+struct StartResult {
+    devices: u32,
+    streams: Option<Vec<(u32, StreamProps)>>,
+}
+
+/// Remote desktop state of a `SessionData`
+pub(crate) struct RemoteDesktopData {
+    device_types: BitFlags<DeviceType>,
+    /// Created when the session is started, and moved to the EIS server by `ConnectToEIS`
+    devices: Option<VirtualDevices>,
+    /// EIS server of the session, stopped when the session closes
+    eis: Option<AbortHandle>,
+}
+
+impl Drop for RemoteDesktopData {
+    fn drop(&mut self) {
+        if let Some(eis) = &self.eis {
+            eis.abort();
+        }
+    }
+}
+
+pub(crate) struct VirtualDevices {
+    pub pointer: Option<VirtualPointer>,
+    /// Pointers for absolute motion on each output, created on demand
+    output_pointers: HashMap<WlOutput, VirtualPointer>,
+    pub keyboard: Option<VirtualKeyboard>,
+}
+
+impl VirtualDevices {
+    fn new(wayland_helper: &WaylandHelper, device_types: BitFlags<DeviceType>) -> Self {
+        Self {
+            pointer: if device_types.contains(DeviceType::Pointer) {
+                wayland_helper.create_virtual_pointer(None)
+            } else {
+                None
+            },
+            output_pointers: HashMap::new(),
+            keyboard: if device_types.contains(DeviceType::Keyboard) {
+                wayland_helper.create_virtual_keyboard()
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Moves the pointer to a position on `output`, within `extent`
+    pub fn motion_absolute(
+        &mut self,
+        wayland_helper: &WaylandHelper,
+        output: WlOutput,
+        x: f64,
+        y: f64,
+        extent: (u32, u32),
+    ) {
+        if self.pointer.is_none() {
+            return;
+        }
+        let pointer = match self.output_pointers.entry(output) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let Some(pointer) = wayland_helper.create_virtual_pointer(Some(entry.key())) else {
+                    return;
+                };
+                entry.insert(pointer)
+            }
+        };
+        pointer.motion_absolute(x, y, extent);
+    }
+}
+
+pub struct RemoteDesktop {
+    wayland_helper: WaylandHelper,
+    tx: Sender<subscription::Event>,
+}
+
+impl RemoteDesktop {
+    pub fn new(wayland_helper: WaylandHelper, tx: Sender<subscription::Event>) -> Self {
+        Self { wayland_helper, tx }
+    }
+
+    fn supported_device_types(&self) -> BitFlags<DeviceType> {
+        let mut device_types = BitFlags::empty();
+        if self.wayland_helper.has_virtual_keyboard() {
+            device_types |= DeviceType::Keyboard;
+        }
+        if self.wayland_helper.has_virtual_pointer() {
+            device_types |= DeviceType::Pointer;
+        }
+        device_types
+    }
+
+    /// Runs `f` with the virtual devices of a started session
+    async fn with_devices<F: FnOnce(&mut VirtualDevices)>(
+        &self,
+        connection: &zbus::Connection,
+        session_handle: &zvariant::ObjectPath<'_>,
+        f: F,
+    ) {
+        let Some(interface) =
+            crate::session_interface::<SessionData>(connection, session_handle).await
+        else {
+            log::error!("No remote desktop session {}", session_handle);
+            return;
+        };
+        let mut session_data = interface.get_mut().await;
+        match session_data
+            .remote_desktop
+            .as_mut()
+            .and_then(|data| data.devices.as_mut())
+        {
+            Some(devices) => f(devices),
+            None => log::error!(
+                "Remote desktop session {} not started, or connected to EIS",
+                session_handle
+            ),
+        }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.RemoteDesktop")]
+impl RemoteDesktop {
+    async fn create_session(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        _handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        _app_id: String,
+        _options: HashMap<String, zvariant::OwnedValue>,
+    ) -> PortalResponse<CreateSessionResult> {
+        let session_data = SessionData {
+            remote_desktop: Some(RemoteDesktopData {
+                device_types: self.supported_device_types(),
+                devices: None,
+                eis: None,
+            }),
+            ..Default::default()
+        };
+        if let Err(err) = connection
+            .object_server()
+            .at(
+                &session_handle,
+                crate::Session::new(session_data, |session_data| session_data.close()),
+            )
+            .await
+        {
+            log::error!("Failed to create remote desktop session: {}", err);
+            return PortalResponse::Other;
+        }
+        PortalResponse::Success(CreateSessionResult {
+            session_id: session_handle.to_string(),
+        })
+    }
+
+    async fn select_devices(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        _handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        _app_id: String,
+        options: SelectDevicesOptions,
+    ) -> PortalResponse<HashMap<String, zvariant::OwnedValue>> {
+        let Some(interface) =
+            crate::session_interface::<SessionData>(connection, &session_handle).await
+        else {
+            return PortalResponse::Other;
+        };
+        let mut session_data = interface.get_mut().await;
+        let Some(remote_desktop) = session_data.remote_desktop.as_mut() else {
+            log::error!("{} is not a remote desktop session", session_handle);
+            return PortalResponse::Other;
+        };
+        let available = self.supported_device_types();
+        remote_desktop.device_types = match options.types {
+            Some(types) => BitFlags::from_bits_truncate(types) & available,
+            None => available,
+        };
+        PortalResponse::Success(HashMap::new())
+    }
+
+    async fn start(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: String,
+        _parent_window: String,
+        _options: HashMap<String, zvariant::OwnedValue>,
+    ) -> PortalResponse<StartResult> {
+        let on_cancel = || async {
+            remote_desktop_dialog::hide_remote_desktop_prompt(&self.tx, &session_handle).await;
+            screencast_dialog::hide_screencast_prompt(&self.tx, &session_handle).await;
+        };
+        Request::run(connection, &handle, on_cancel, async {
+            let Some(interface) =
+                crate::session_interface::<SessionData>(connection, &session_handle).await
+            else {
+                return PortalResponse::Other;
+            };
+
+            let (device_types, cursor_mode, multiple, source_types) = {
+                let session_data = interface.get().await;
+                let Some(remote_desktop) = &session_data.remote_desktop else {
+                    log::error!("{} is not a remote desktop session", session_handle);
+                    return PortalResponse::Other;
+                };
+                (
+                    remote_desktop.device_types,
+                    session_data
+                        .cursor_mode
+                        .unwrap_or(screencast::CURSOR_MODE_EMBEDDED),
+                    session_data.multiple,
+                    session_data.source_types,
+                )
+            };
+
+            if !remote_desktop_dialog::show_remote_desktop_prompt(
+                &self.tx,
+                &session_handle,
+                &app_id,
+                device_types,
+            )
+            .await
+            {
+                return PortalResponse::Cancelled;
+            }
+
+            // Sources are only selected if the app also called `ScreenCast.SelectSources`
+            let mut screencast_threads = Vec::new();
+            if !source_types.is_empty() {
+                let Some((capture_sources, _)) = screencast_dialog::show_screencast_prompt(
+                    &self.tx,
+                    &session_handle,
+                    app_id.clone(),
+                    multiple,
+                    source_types,
+                    false,
+                    &self.wayland_helper,
+                )
+                .await
+                else {
+                    return PortalResponse::Cancelled;
+                };
+                let Some(threads) =
+                    screencast::start_streams(&self.wayland_helper, &capture_sources, cursor_mode)
+                        .await
+                else {
+                    return PortalResponse::Other;
+                };
+                screencast_threads = threads;
+            }
+
+            let devices = VirtualDevices::new(&self.wayland_helper, device_types);
+            let mut granted = BitFlags::<DeviceType>::empty();
+            if devices.pointer.is_some() {
+                granted |= DeviceType::Pointer;
+            }
+            if devices.keyboard.is_some() {
+                granted |= DeviceType::Keyboard;
+            }
+
+            let mut session_data = interface.get_mut().await;
+            // Session may have already been cancelled
+            if session_data.closed {
+                for thread in screencast_threads {
+                    thread.stop();
+                }
+                return PortalResponse::Cancelled;
+            }
+            session_data.screencast_threads = screencast_threads;
+            if let Some(remote_desktop) = session_data.remote_desktop.as_mut() {
+                remote_desktop.device_types = granted;
+                remote_desktop.devices = Some(devices);
+            }
+
+            PortalResponse::Success(StartResult {
+                devices: granted.bits(),
+                streams: (!source_types.is_empty()).then(|| session_data.streams()),
+            })
+        })
+        .await
+    }
+
+    async fn notify_pointer_motion(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        dx: f64,
+        dy: f64,
+    ) {
+        self.with_devices(connection, &session_handle, |devices| {
+            if let Some(pointer) = &devices.pointer {
+                pointer.motion(dx, dy);
+            }
+        })
+        .await
+    }
+
+    async fn notify_pointer_motion_absolute(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        stream: u32,
+        x: f64,
+        y: f64,
+    ) {
+        let Some(interface) =
+            crate::session_interface::<SessionData>(connection, &session_handle).await
+        else {
+            log::error!("No remote desktop session {}", session_handle);
+            return;
+        };
+        let stream_output = interface
+            .get()
+            .await
+            .stream_output(&self.wayland_helper, stream);
+        let Some((output, (width, height))) = stream_output else {
+            log::warn!("Absolute pointer motion only supported for monitor streams");
+            return;
+        };
+        self.with_devices(connection, &session_handle, |devices| {
+            let extent = (width.max(1) as u32, height.max(1) as u32);
+            devices.motion_absolute(&self.wayland_helper, output, x, y, extent);
+        })
+        .await
+    }
+
+    async fn notify_pointer_button(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        button: i32,
+        state: u32,
+    ) {
+        self.with_devices(connection, &session_handle, |devices| {
+            if let Some(pointer) = &devices.pointer {
+                pointer.button(button as u32, state == KEY_STATE_PRESSED);
+            }
+        })
+        .await
+    }
+
+    async fn notify_pointer_axis(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: NotifyPointerAxisOptions,
+        dx: f64,
+        dy: f64,
+    ) {
+        self.with_devices(connection, &session_handle, |devices| {
+            if let Some(pointer) = &devices.pointer {
+                pointer.axis(dx, dy, options.finish.unwrap_or(false));
+            }
+        })
+        .await
+    }
+
+    async fn notify_pointer_axis_discrete(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        axis: u32,
+        steps: i32,
+    ) {
+        let axis = match axis {
+            AXIS_VERTICAL_SCROLL => wl_pointer::Axis::VerticalScroll,
+            AXIS_HORIZONTAL_SCROLL => wl_pointer::Axis::HorizontalScroll,
+            _ => {
+                log::error!("Invalid pointer axis {}", axis);
+                return;
+            }
+        };
+        self.with_devices(connection, &session_handle, |devices| {
+            if let Some(pointer) = &devices.pointer {
+                pointer.axis_discrete(axis, steps);
+            }
+        })
+        .await
+    }
+
+    async fn notify_keyboard_keycode(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        keycode: i32,
+        state: u32,
+    ) {
+        self.with_devices(connection, &session_handle, |devices| {
+            if let Some(keyboard) = &mut devices.keyboard {
+                keyboard.key(keycode as u32, state == KEY_STATE_PRESSED);
+            }
+        })
+        .await
+    }
+
+    async fn notify_keyboard_keysym(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        keysym: i32,
+        state: u32,
+    ) {
+        self.with_devices(connection, &session_handle, |devices| {
+            if let Some(keyboard) = &mut devices.keyboard
+                && !keyboard.keysym(keysym as u32, state == KEY_STATE_PRESSED)
+            {
+                log::warn!("No key for keysym {:#x} in keymap", keysym);
+            }
+        })
+        .await
+    }
+
+    /// Touchscreen isn't in `AvailableDeviceTypes`, since there is no Wayland protocol for
+    /// virtual touch input, so sessions never have a touchscreen to send to.
+    async fn notify_touch_down(
+        &self,
+        _session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        _stream: u32,
+        _slot: u32,
+        _x: f64,
+        _y: f64,
+    ) -> fdo::Result<()> {
+        Err(no_touchscreen())
+    }
+
+    async fn notify_touch_motion(
+        &self,
+        _session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        _stream: u32,
+        _slot: u32,
+        _x: f64,
+        _y: f64,
+    ) -> fdo::Result<()> {
+        Err(no_touchscreen())
+    }
+
+    async fn notify_touch_up(
+        &self,
+        _session_handle: zvariant::ObjectPath<'_>,
+        _options: HashMap<String, zvariant::OwnedValue>,
+        _slot: u32,
+    ) -> fdo::Result<()> {
+        Err(no_touchscreen())
+    }
+
+    /// Hands the session's virtual devices to an EIS server run by the portal, returning the
+    /// client end of its socket. The `Notify*` methods can't be used afterwards.
+    #[zbus(name = "ConnectToEIS")]
+    async fn connect_to_eis(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session_handle: zvariant::ObjectPath<'_>,
+        _app_id: String,
+        _options: HashMap<String, zvariant::OwnedValue>,
+    ) -> fdo::Result<zvariant::OwnedFd> {
+        let Some(interface) =
+            crate::session_interface::<SessionData>(connection, &session_handle).await
+        else {
+            return Err(fdo::Error::Failed(format!(
+                "No remote desktop session {}",
+                session_handle
+            )));
+        };
+        let mut session_data = interface.get_mut().await;
+        let Some(remote_desktop) = session_data.remote_desktop.as_mut() else {
+            return Err(fdo::Error::Failed(format!(
+                "{} is not a remote desktop session",
+                session_handle
+            )));
+        };
+        let Some(devices) = remote_desktop.devices.take() else {
+            return Err(fdo::Error::Failed(format!(
+                "Remote desktop session {} not started, or already connected to EIS",
+                session_handle
+            )));
+        };
+        let (client, server) =
+            UnixStream::pair().map_err(|err| fdo::Error::IOError(err.to_string()))?;
+        let (task, abort_handle) = abortable(remote_desktop_eis::run(
+            server,
+            self.wayland_helper.clone(),
+            devices,
+        ));
+        tokio::spawn(task);
+        remote_desktop.eis = Some(abort_handle);
+        Ok(OwnedFd::from(client).into())
+    }
+
+    #[zbus(property)]
+    async fn available_device_types(&self) -> u32 {
+        self.supported_device_types().bits()
+    }
+
+    /// Version 2 adds `ConnectToEIS`, and `persist_mode` and `restore_data` options, which
+    /// are ignored, so the dialog is shown each time a session starts
+    #[zbus(property, name = "version")]
+    async fn version(&self) -> u32 {
+        2
+    }
+}
+
+fn no_touchscreen() -> fdo::Error {
+    fdo::Error::NotSupported("Remote desktop sessions have no touchscreen".to_string())
+}
@@ -0,0 +1,187 @@
+use crate::app::CosmicPortal;
+use crate::fl;
+use crate::screencast_dialog::{get_desktop_entry, load_desktop_entries};
+use crate::widget::keyboard_wrapper::KeyboardWrapper;
+use ashpd::desktop::remote_desktop::DeviceType;
+use ashpd::enumflags2::BitFlags;
+use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::key::Named;
+use cosmic::iced::platform_specific::shell::commands::layer_surface::{
+    KeyboardInteractivity, Layer, destroy_layer_surface, get_layer_surface,
+};
+use cosmic::iced::runtime::platform_specific::wayland::layer_surface::SctkLayerSurfaceSettings;
+use cosmic::iced::{self, window};
+use cosmic::widget;
+use cosmic::widget::autosize;
+use freedesktop_desktop_entry::get_languages_from_env;
+use std::sync::LazyLock;
+use tokio::sync::mpsc;
+use zbus::zvariant;
+
+pub static REMOTE_DESKTOP_ID: LazyLock<window::Id> = LazyLock::new(window::Id::unique);
+pub static REMOTE_DESKTOP_WIDGET_ID: LazyLock<widget::Id> =
+    LazyLock::new(|| widget::Id::new("remote-desktop".to_string()));
+
+pub async fn hide_remote_desktop_prompt(
+    subscription_tx: &mpsc::Sender<crate::subscription::Event>,
+    session_handle: &zvariant::ObjectPath<'_>,
+) {
+    let _ = subscription_tx
+        .send(crate::subscription::Event::CancelRemoteDesktop(
+            session_handle.to_owned(),
+        ))
+        .await;
+}
+
+/// Asks the user whether `app_id` may control the given devices. Returns `true` if allowed.
+pub async fn show_remote_desktop_prompt(
+    subscription_tx: &mpsc::Sender<crate::subscription::Event>,
+    session_handle: &zvariant::ObjectPath<'_>,
+    app_id: &str,
+    device_types: BitFlags<DeviceType>,
+) -> bool {
+    let locales = get_languages_from_env();
+    let desktop_entries = load_desktop_entries(&locales).await;
+    let app_name = get_desktop_entry(&desktop_entries, app_id)
+        .and_then(|x| Some(x.name(&locales)?.into_owned()));
+
+    let (tx, mut rx) = mpsc::channel(1);
+    let args = Args {
+        session_handle: session_handle.to_owned(),
+        device_types,
+        app_name,
+        tx,
+    };
+    subscription_tx
+        .send(crate::subscription::Event::RemoteDesktop(args))
+        .await
+        .unwrap();
+    rx.recv().await.unwrap_or(false)
+}
+
+fn create_dialog() -> cosmic::Task<crate::app::Msg> {
+    get_layer_surface(SctkLayerSurfaceSettings {
+        id: *REMOTE_DESKTOP_ID,
+        keyboard_interactivity: KeyboardInteractivity::Exclusive,
+        namespace: "remote desktop".into(),
+        layer: Layer::Overlay,
+        size: None,
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    session_handle: zvariant::ObjectPath<'static>,
+    device_types: BitFlags<DeviceType>,
+    app_name: Option<String>,
+    // Should be oneshot, but need `Clone` bound
+    tx: mpsc::Sender<bool>,
+}
+
+impl Args {
+    fn send_response(self, allowed: bool) {
+        tokio::spawn(async move {
+            if let Err(err) = self.tx.send(allowed).await {
+                log::error!("Failed to send remote desktop event: {}", err);
+            }
+        });
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Msg {
+    Allow,
+    Cancel,
+}
+
+pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::app::Msg> {
+    let Some(args) = portal.remote_desktop_args.take() else {
+        return cosmic::Task::none();
+    };
+    args.send_response(matches!(msg, Msg::Allow));
+    destroy_layer_surface(*REMOTE_DESKTOP_ID)
+}
+
+pub fn update_args(portal: &mut CosmicPortal, args: Args) -> cosmic::Task<crate::app::Msg> {
+    // If the dialog is already open, cancel previous request, but re-use dialog surface
+    let command = if let Some(args) = portal.remote_desktop_args.take() {
+        args.send_response(false);
+        cosmic::Task::none()
+    } else {
+        create_dialog()
+    };
+    portal.remote_desktop_args = Some(args);
+    command
+}
+
+pub fn cancel(
+    portal: &mut CosmicPortal,
+    session_handle: zvariant::ObjectPath<'static>,
+) -> cosmic::Task<crate::app::Msg> {
+    if portal
+        .remote_desktop_args
+        .as_ref()
+        .is_some_and(|args| args.session_handle == session_handle)
+    {
+        let args = portal.remote_desktop_args.take().unwrap();
+        args.send_response(false);
+        destroy_layer_surface(*REMOTE_DESKTOP_ID)
+    } else {
+        cosmic::Task::none()
+    }
+}
+
+fn device_row(icon: &'static str, label: String) -> cosmic::Element<'static, Msg> {
+    widget::row::with_children(vec![
+        widget::icon::from_name(icon).size(24).icon().into(),
+        widget::text(label).into(),
+    ])
+    .spacing(12)
+    .align_y(iced::Alignment::Center)
+    .into()
+}
+
+pub(crate) fn view(portal: &CosmicPortal) -> cosmic::Element<'_, Msg> {
+    let Some(args) = portal.remote_desktop_args.as_ref() else {
+        return widget::space::horizontal()
+            .width(iced::Length::Fixed(1.0))
+            .into();
+    };
+    let cancel_button = widget::button::standard(fl!("cancel")).on_press(Msg::Cancel);
+    let allow_button = widget::button::standard(fl!("allow"))
+        .class(cosmic::style::Button::Suggested)
+        .on_press(Msg::Allow);
+
+    let mut devices = widget::ListColumn::new();
+    if args.device_types.contains(DeviceType::Keyboard) {
+        devices = devices.add(device_row("input-keyboard-symbolic", fl!("keyboard")));
+    }
+    if args.device_types.contains(DeviceType::Pointer) {
+        devices = devices.add(device_row("input-mouse-symbolic", fl!("pointer")));
+    }
+
+    let unknown = fl!("unknown-application");
+    let app_name = args.app_name.as_deref().unwrap_or(&unknown);
+
+    autosize::autosize(
+        KeyboardWrapper::new(
+            widget::dialog()
+                .title(fl!("remote-desktop"))
+                .body(fl!("remote-desktop", "description", app_name = app_name))
+                .secondary_action(cancel_button)
+                .primary_action(allow_button)
+                .control(devices),
+            |key, _| match key {
+                Key::Named(Named::Enter) => Some(Msg::Allow),
+                Key::Named(Named::Escape) => Some(Msg::Cancel),
+                _ => None,
+            },
+        ),
+        REMOTE_DESKTOP_WIDGET_ID.clone(),
+    )
+    .max_width(572.)
+    .min_width(1.)
+    .min_height(1.)
+    .into()
+}
@@ -0,0 +1,782 @@
+// EIS server for `ConnectToEIS`, so remote desktop clients using libei can send input.
+//
+// Implements the server side of the ei protocol for sender contexts, as described at
+// https://libinput.pages.freedesktop.org/libei/, over one end of a socket pair. The compositor
+// has no EIS server, so input is sent through the session's virtual pointer and keyboard.
+
+use std::collections::HashMap;
+use std::io::{self, IoSlice};
+use std::mem::MaybeUninit;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use tokio::io::{AsyncReadExt, Interest};
+use tokio::net::UnixStream;
+use wayland_client::protocol::{wl_output::WlOutput, wl_pointer};
+
+use crate::remote_desktop::VirtualDevices;
+use crate::wayland::WaylandHelper;
+
+/// Version of every interface implemented here
+const VERSION: u32 = 1;
+
+const HANDSHAKE_ID: u64 = 0;
+/// Objects created by the server have ids from here, objects created by the client below it
+const SERVER_ID_BASE: u64 = 0xff00_0000_0000_0000;
+/// Object id, length and opcode
+const HEADER_SIZE: usize = 16;
+
+const CONTEXT_TYPE_SENDER: u32 = 2;
+const DEVICE_TYPE_VIRTUAL: u32 = 1;
+const KEYMAP_TYPE_XKB: u32 = 1;
+const BUTTON_STATE_PRESS: u32 = 1;
+const KEY_STATE_PRESS: u32 = 1;
+const DISCONNECT_REASON_PROTOCOL: u32 = 3;
+/// `scroll_discrete` value of one wheel click
+const SCROLL_DISCRETE_STEP: i32 = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Interface {
+    Handshake,
+    Connection,
+    Callback,
+    Seat,
+    Device,
+    Pointer,
+    PointerAbsolute,
+    Scroll,
+    Button,
+    Keyboard,
+}
+
+impl Interface {
+    /// Interfaces of devices, in the order they are added to a device
+    const DEVICE_INTERFACES: [Self; 5] = [
+        Self::Pointer,
+        Self::PointerAbsolute,
+        Self::Scroll,
+        Self::Button,
+        Self::Keyboard,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Handshake => "ei_handshake",
+            Self::Connection => "ei_connection",
+            Self::Callback => "ei_callback",
+            Self::Seat => "ei_seat",
+            Self::Device => "ei_device",
+            Self::Pointer => "ei_pointer",
+            Self::PointerAbsolute => "ei_pointer_absolute",
+            Self::Scroll => "ei_scroll",
+            Self::Button => "ei_button",
+            Self::Keyboard => "ei_keyboard",
+        }
+    }
+
+    /// Interfaces whose version is negotiated with `interface_version`, which excludes
+    /// `ei_handshake`
+    fn from_name(name: &str) -> Option<Self> {
+        [Self::Connection, Self::Callback, Self::Seat, Self::Device]
+            .into_iter()
+            .chain(Self::DEVICE_INTERFACES)
+            .find(|interface| interface.name() == name)
+    }
+
+    /// Bit of the interface in the seat's capabilities
+    fn capability(self) -> u64 {
+        match Self::DEVICE_INTERFACES
+            .iter()
+            .position(|interface| *interface == self)
+        {
+            Some(i) => 1 << i,
+            None => 0,
+        }
+    }
+}
+
+/// Output in the absolute pointer's regions
+struct Region {
+    output: WlOutput,
+    /// Logical position, relative to the top left of all outputs, since region offsets can't
+    /// be negative
+    position: (u32, u32),
+    size: (u32, u32),
+    scale: f32,
+}
+
+impl Region {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.position.0 as f64
+            && y >= self.position.1 as f64
+            && x < (self.position.0 + self.size.0) as f64
+            && y < (self.position.1 + self.size.1) as f64
+    }
+}
+
+fn regions(wayland_helper: &WaylandHelper) -> Vec<Region> {
+    let outputs: Vec<_> = wayland_helper
+        .outputs()
+        .into_iter()
+        .filter_map(|output| {
+            let info = wayland_helper.output_info(&output)?;
+            Some((
+                output,
+                info.logical_position?,
+                info.logical_size?,
+                info.scale_factor,
+            ))
+        })
+        .collect();
+    let left = outputs
+        .iter()
+        .map(|(_, (x, _), _, _)| *x)
+        .min()
+        .unwrap_or(0);
+    let top = outputs
+        .iter()
+        .map(|(_, (_, y), _, _)| *y)
+        .min()
+        .unwrap_or(0);
+    outputs
+        .into_iter()
+        .map(|(output, (x, y), (width, height), scale)| Region {
+            output,
+            position: ((x - left) as u32, (y - top) as u32),
+            size: (width.max(1) as u32, height.max(1) as u32),
+            scale: scale as f32,
+        })
+        .collect()
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Arguments of a request
+struct Args<'a>(&'a [u8]);
+
+impl Args<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let (bytes, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or_else(|| protocol_error("message too short"))?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn uint32(&mut self) -> io::Result<u32> {
+        self.take().map(u32::from_ne_bytes)
+    }
+
+    fn int32(&mut self) -> io::Result<i32> {
+        self.take().map(i32::from_ne_bytes)
+    }
+
+    fn uint64(&mut self) -> io::Result<u64> {
+        self.take().map(u64::from_ne_bytes)
+    }
+
+    fn float(&mut self) -> io::Result<f32> {
+        self.take().map(f32::from_ne_bytes)
+    }
+
+    /// A string, with a length including the null terminator, padded to 4 bytes
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.uint32()? as usize;
+        let padded = len.next_multiple_of(4);
+        if self.0.len() < padded {
+            return Err(protocol_error("message too short"));
+        }
+        let bytes = &self.0[..len.saturating_sub(1)];
+        self.0 = &self.0[padded..];
+        String::from_utf8(bytes.to_vec()).map_err(|_| protocol_error("invalid string"))
+    }
+}
+
+/// An event, encoded with its header
+struct Message(Vec<u8>);
+
+impl Message {
+    fn new(object: u64, opcode: u32) -> Self {
+        let mut data = Vec::with_capacity(32);
+        data.extend(object.to_ne_bytes());
+        // Length, set by `Server::send`
+        data.extend(0u32.to_ne_bytes());
+        data.extend(opcode.to_ne_bytes());
+        Self(data)
+    }
+
+    fn uint32(mut self, value: u32) -> Self {
+        self.0.extend(value.to_ne_bytes());
+        self
+    }
+
+    fn uint64(mut self, value: u64) -> Self {
+        self.0.extend(value.to_ne_bytes());
+        self
+    }
+
+    fn float(mut self, value: f32) -> Self {
+        self.0.extend(value.to_ne_bytes());
+        self
+    }
+
+    fn string(mut self, value: &str) -> Self {
+        let len = value.len() + 1;
+        self.0.extend((len as u32).to_ne_bytes());
+        self.0.extend(value.as_bytes());
+        self.0
+            .resize(self.0.len() + len.next_multiple_of(4) - value.len(), 0);
+        self
+    }
+}
+
+/// Sends `data`, with `fds` attached
+fn send_with_fds(stream: &UnixStream, data: &[u8], fds: &[OwnedFd]) -> io::Result<usize> {
+    use rustix::net::{SendAncillaryBuffer, SendAncillaryMessage, SendFlags};
+
+    let fds: Vec<BorrowedFd<'_>> = fds.iter().map(AsFd::as_fd).collect();
+    let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(fds.len()))];
+    let mut control = SendAncillaryBuffer::new(&mut space);
+    if !fds.is_empty() && !control.push(SendAncillaryMessage::ScmRights(&fds)) {
+        return Err(io::Error::other(
+            "file descriptors don't fit in control message",
+        ));
+    }
+    Ok(rustix::net::sendmsg(
+        stream,
+        &[IoSlice::new(data)],
+        &mut control,
+        SendFlags::NOSIGNAL,
+    )?)
+}
+
+struct Server {
+    stream: UnixStream,
+    wayland_helper: WaylandHelper,
+    devices: VirtualDevices,
+    regions: Vec<Region>,
+    /// Name the client gave in the handshake
+    name: Option<String>,
+    context_type: Option<u32>,
+    /// Negotiated version of each interface the client supports
+    versions: HashMap<Interface, u32>,
+    objects: HashMap<u64, Interface>,
+    next_id: u64,
+    serial: u32,
+    connection: Option<u64>,
+    seat: Option<u64>,
+    /// Interface objects of each device
+    seat_devices: HashMap<u64, Vec<u64>>,
+    /// Discrete scroll that doesn't add up to a wheel click yet, on each axis
+    scroll_remainder: (i32, i32),
+    out: Vec<u8>,
+    out_fds: Vec<OwnedFd>,
+}
+
+impl Server {
+    fn send(&mut self, mut message: Message) {
+        let len = message.0.len() as u32;
+        message.0[8..12].copy_from_slice(&len.to_ne_bytes());
+        self.out.extend(message.0);
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        let mut sent = 0;
+        while sent < self.out.len() {
+            // File descriptors go with the first bytes sent
+            let fds = if sent == 0 { &self.out_fds[..] } else { &[] };
+            let len = self
+                .stream
+                .async_io(Interest::WRITABLE, || {
+                    send_with_fds(&self.stream, &self.out[sent..], fds)
+                })
+                .await?;
+            sent += len;
+        }
+        self.out.clear();
+        self.out_fds.clear();
+        Ok(())
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        self.serial = self.serial.wrapping_add(1);
+        self.serial
+    }
+
+    fn new_object(&mut self, interface: Interface) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.objects.insert(id, interface);
+        id
+    }
+
+    /// Sends `destroyed` for an object, which is the first event of every interface that can be
+    /// destroyed, and forgets it
+    fn destroy_object(&mut self, object: u64) {
+        if self.objects.remove(&object).is_some() {
+            let serial = self.next_serial();
+            self.send(Message::new(object, 0).uint32(serial));
+        }
+    }
+
+    fn destroy_device(&mut self, device: u64) {
+        for object in self.seat_devices.remove(&device).unwrap_or_default() {
+            self.destroy_object(object);
+        }
+        self.destroy_object(device);
+    }
+
+    fn destroy_devices(&mut self) {
+        let devices: Vec<_> = self.seat_devices.keys().copied().collect();
+        for device in devices {
+            self.destroy_device(device);
+        }
+    }
+
+    /// Device interfaces the client supports and the session has devices for
+    fn capabilities(&self) -> Vec<Interface> {
+        Interface::DEVICE_INTERFACES
+            .into_iter()
+            .filter(|interface| self.versions.contains_key(interface))
+            .filter(|interface| match interface {
+                Interface::Keyboard => self.devices.keyboard.is_some(),
+                Interface::PointerAbsolute => {
+                    self.devices.pointer.is_some() && !self.regions.is_empty()
+                }
+                _ => self.devices.pointer.is_some(),
+            })
+            .collect()
+    }
+
+    fn finish_handshake(&mut self) -> io::Result<()> {
+        if self.context_type != Some(CONTEXT_TYPE_SENDER) {
+            return Err(protocol_error("only sender contexts are supported"));
+        }
+        let (Some(&connection_version), Some(&seat_version)) = (
+            self.versions.get(&Interface::Connection),
+            self.versions.get(&Interface::Seat),
+        ) else {
+            return Err(protocol_error("ei_connection and ei_seat are required"));
+        };
+        log::info!(
+            "EIS client '{}' connected",
+            self.name.as_deref().unwrap_or_default()
+        );
+
+        let interfaces: Vec<_> = self.versions.keys().copied().collect();
+        for interface in interfaces {
+            // ei_handshake.interface_version
+            self.send(
+                Message::new(HANDSHAKE_ID, 1)
+                    .string(interface.name())
+                    .uint32(VERSION),
+            );
+        }
+        self.objects.remove(&HANDSHAKE_ID);
+        let connection = self.new_object(Interface::Connection);
+        let serial = self.next_serial();
+        // ei_handshake.connection
+        self.send(
+            Message::new(HANDSHAKE_ID, 2)
+                .uint32(serial)
+                .uint64(connection)
+                .uint32(connection_version),
+        );
+        self.connection = Some(connection);
+
+        let seat = self.new_object(Interface::Seat);
+        // ei_connection.seat
+        self.send(
+            Message::new(connection, 1)
+                .uint64(seat)
+                .uint32(seat_version),
+        );
+        // ei_seat.name
+        self.send(Message::new(seat, 1).string("default"));
+        for interface in self.capabilities() {
+            // ei_seat.capability
+            self.send(
+                Message::new(seat, 2)
+                    .uint64(interface.capability())
+                    .string(interface.name()),
+            );
+        }
+        // ei_seat.done
+        self.send(Message::new(seat, 3));
+        self.seat = Some(seat);
+        Ok(())
+    }
+
+    /// Replaces the seat's devices with ones for the bound capabilities
+    fn bind(&mut self, capabilities: u64) {
+        self.destroy_devices();
+        let (keyboard, pointer): (Vec<_>, Vec<_>) = self
+            .capabilities()
+            .into_iter()
+            .filter(|interface| capabilities & interface.capability() != 0)
+            .partition(|interface| *interface == Interface::Keyboard);
+        if !pointer.is_empty() {
+            self.add_device("COSMIC remote pointer", &pointer);
+        }
+        if !keyboard.is_empty() {
+            self.add_device("COSMIC remote keyboard", &keyboard);
+        }
+    }
+
+    fn add_device(&mut self, name: &str, interfaces: &[Interface]) {
+        let (Some(seat), Some(&version)) = (self.seat, self.versions.get(&Interface::Device))
+        else {
+            return;
+        };
+        let device = self.new_object(Interface::Device);
+        // ei_seat.device
+        self.send(Message::new(seat, 4).uint64(device).uint32(version));
+        // ei_device.name
+        self.send(Message::new(device, 1).string(name));
+        // ei_device.device_type
+        self.send(Message::new(device, 2).uint32(DEVICE_TYPE_VIRTUAL));
+        if interfaces.contains(&Interface::PointerAbsolute) {
+            let regions: Vec<_> = self
+                .regions
+                .iter()
+                .map(|region| {
+                    // ei_device.region
+                    Message::new(device, 4)
+                        .uint32(region.position.0)
+                        .uint32(region.position.1)
+                        .uint32(region.size.0)
+                        .uint32(region.size.1)
+                        .float(region.scale)
+                })
+                .collect();
+            for region in regions {
+                self.send(region);
+            }
+        }
+        let mut objects = Vec::new();
+        for &interface in interfaces {
+            let object = self.new_object(interface);
+            let version = self.versions[&interface];
+            // ei_device.interface
+            self.send(
+                Message::new(device, 5)
+                    .uint64(object)
+                    .string(interface.name())
+                    .uint32(version),
+            );
+            if interface == Interface::Keyboard {
+                self.send_keymap(object);
+            }
+            objects.push(object);
+        }
+        // ei_device.done
+        self.send(Message::new(device, 6));
+        let serial = self.next_serial();
+        // ei_device.resumed
+        self.send(Message::new(device, 7).uint32(serial));
+        self.seat_devices.insert(device, objects);
+    }
+
+    fn send_keymap(&mut self, keyboard: u64) {
+        let Some(virtual_keyboard) = &self.devices.keyboard else {
+            return;
+        };
+        let (fd, size) = virtual_keyboard.keymap();
+        match fd.try_clone_to_owned() {
+            Ok(fd) => {
+                self.out_fds.push(fd);
+                // ei_keyboard.keymap, with the keymap's file descriptor
+                self.send(
+                    Message::new(keyboard, 1)
+                        .uint32(KEYMAP_TYPE_XKB)
+                        .uint32(size),
+                );
+            }
+            Err(err) => log::error!("Failed to duplicate keymap for EIS keyboard: {}", err),
+        }
+    }
+
+    fn motion_absolute(&mut self, x: f32, y: f32) {
+        let (x, y) = (x as f64, y as f64);
+        let Some(region) = self.regions.iter().find(|region| region.contains(x, y)) else {
+            return;
+        };
+        self.devices.motion_absolute(
+            &self.wayland_helper,
+            region.output.clone(),
+            x - region.position.0 as f64,
+            y - region.position.1 as f64,
+            region.size,
+        );
+    }
+
+    fn scroll_discrete(&mut self, axis: wl_pointer::Axis, value: i32) {
+        let remainder = match axis {
+            wl_pointer::Axis::HorizontalScroll => &mut self.scroll_remainder.0,
+            _ => &mut self.scroll_remainder.1,
+        };
+        *remainder += value;
+        let steps = *remainder / SCROLL_DISCRETE_STEP;
+        *remainder -= steps * SCROLL_DISCRETE_STEP;
+        if steps != 0
+            && let Some(pointer) = &self.devices.pointer
+        {
+            pointer.axis_discrete(axis, steps);
+        }
+    }
+
+    /// Handles a request. Returns `false` if the client disconnected.
+    fn handle(&mut self, object: u64, opcode: u32, mut args: Args<'_>) -> io::Result<bool> {
+        let Some(&interface) = self.objects.get(&object) else {
+            // The client may use an object before it sees it destroyed
+            if let Some(connection) = self.connection {
+                let serial = self.serial;
+                // ei_connection.invalid_object
+                self.send(Message::new(connection, 2).uint32(serial).uint64(object));
+            }
+            return Ok(true);
+        };
+        let pointer = self.devices.pointer.as_ref();
+        match (interface, opcode) {
+            // ei_handshake.handshake_version
+            (Interface::Handshake, 0) => {
+                if args.uint32()? != VERSION {
+                    return Err(protocol_error("unsupported handshake version"));
+                }
+            }
+            // ei_handshake.finish
+            (Interface::Handshake, 1) => self.finish_handshake()?,
+            // ei_handshake.context_type
+            (Interface::Handshake, 2) => self.context_type = Some(args.uint32()?),
+            // ei_handshake.name
+            (Interface::Handshake, 3) => self.name = Some(args.string()?),
+            // ei_handshake.interface_version
+            (Interface::Handshake, 4) => {
+                let name = args.string()?;
+                let version = args.uint32()?;
+                if let Some(interface) = Interface::from_name(&name)
+                    && version > 0
+                {
+                    self.versions.insert(interface, version.min(VERSION));
+                }
+            }
+            // ei_connection.sync
+            (Interface::Connection, 0) => {
+                let callback = args.uint64()?;
+                // ei_callback.done
+                self.send(Message::new(callback, 0).uint64(0));
+            }
+            // ei_connection.disconnect
+            (Interface::Connection, 1) => return Ok(false),
+            // ei_seat.release
+            (Interface::Seat, 0) => {
+                self.destroy_devices();
+                self.destroy_object(object);
+                self.seat = None;
+            }
+            // ei_seat.bind
+            (Interface::Seat, 1) => self.bind(args.uint64()?),
+            // ei_device.release
+            (Interface::Device, 0) => self.destroy_device(object),
+            // ei_device.start_emulating, stop_emulating and frame. Each event is sent to the
+            // compositor in a frame of its own.
+            (Interface::Device, 1..=3) => {}
+            // release of a device interface
+            (
+                Interface::Pointer
+                | Interface::PointerAbsolute
+                | Interface::Scroll
+                | Interface::Button
+                | Interface::Keyboard,
+                0,
+            ) => self.destroy_object(object),
+            // ei_pointer.motion_relative
+            (Interface::Pointer, 1) => {
+                let (x, y) = (args.float()?, args.float()?);
+                if let Some(pointer) = pointer {
+                    pointer.motion(x as f64, y as f64);
+                }
+            }
+            // ei_pointer_absolute.motion_absolute
+            (Interface::PointerAbsolute, 1) => {
+                let (x, y) = (args.float()?, args.float()?);
+                self.motion_absolute(x, y);
+            }
+            // ei_scroll.scroll
+            (Interface::Scroll, 1) => {
+                let (x, y) = (args.float()?, args.float()?);
+                if let Some(pointer) = pointer {
+                    pointer.axis(x as f64, y as f64, false);
+                }
+            }
+            // ei_scroll.scroll_discrete
+            (Interface::Scroll, 2) => {
+                let (x, y) = (args.int32()?, args.int32()?);
+                self.scroll_discrete(wl_pointer::Axis::HorizontalScroll, x);
+                self.scroll_discrete(wl_pointer::Axis::VerticalScroll, y);
+            }
+            // ei_scroll.scroll_stop
+            (Interface::Scroll, 3) => {
+                let (x, y) = (args.uint32()?, args.uint32()?);
+                if (x != 0 || y != 0)
+                    && let Some(pointer) = pointer
+                {
+                    pointer.axis(0., 0., true);
+                }
+            }
+            // ei_button.button
+            (Interface::Button, 1) => {
+                let (button, state) = (args.uint32()?, args.uint32()?);
+                if let Some(pointer) = pointer {
+                    pointer.button(button, state == BUTTON_STATE_PRESS);
+                }
+            }
+            // ei_keyboard.key
+            (Interface::Keyboard, 1) => {
+                let (key, state) = (args.uint32()?, args.uint32()?);
+                if let Some(keyboard) = &mut self.devices.keyboard {
+                    keyboard.key(key, state == KEY_STATE_PRESS);
+                }
+            }
+            _ => {
+                return Err(protocol_error(format!(
+                    "invalid opcode {} for {}",
+                    opcode,
+                    interface.name()
+                )));
+            }
+        }
+        Ok(true)
+    }
+
+    async fn run(&mut self) -> io::Result<()> {
+        self.objects.insert(HANDSHAKE_ID, Interface::Handshake);
+        // ei_handshake.handshake_version
+        self.send(Message::new(HANDSHAKE_ID, 0).uint32(VERSION));
+        self.flush().await?;
+
+        let mut input = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let len = self.stream.read(&mut buf).await?;
+            if len == 0 {
+                return Ok(());
+            }
+            input.extend_from_slice(&buf[..len]);
+
+            let mut consumed = 0;
+            while let Some(header) = input.get(consumed..consumed + HEADER_SIZE) {
+                let object = u64::from_ne_bytes(header[..8].try_into().unwrap());
+                let len = u32::from_ne_bytes(header[8..12].try_into().unwrap()) as usize;
+                let opcode = u32::from_ne_bytes(header[12..].try_into().unwrap());
+                if len < HEADER_SIZE {
+                    return Err(protocol_error("invalid message length"));
+                }
+                let Some(args) = input.get(consumed + HEADER_SIZE..consumed + len) else {
+                    break;
+                };
+                consumed += len;
+                if !self.handle(object, opcode, Args(args))? {
+                    self.flush().await?;
+                    return Ok(());
+                }
+            }
+            input.drain(..consumed);
+            self.flush().await?;
+        }
+    }
+}
+
+/// Serves a client on `stream` until it disconnects, or the task is aborted when the session
+/// closes
+pub(crate) async fn run(
+    stream: std::os::unix::net::UnixStream,
+    wayland_helper: WaylandHelper,
+    devices: VirtualDevices,
+) {
+    let stream = match stream
+        .set_nonblocking(true)
+        .and_then(|()| UnixStream::from_std(stream))
+    {
+        Ok(stream) => stream,
+        Err(err) => {
+            log::error!("Failed to set up EIS socket: {}", err);
+            return;
+        }
+    };
+    let mut server = Server {
+        stream,
+        regions: regions(&wayland_helper),
+        wayland_helper,
+        devices,
+        name: None,
+        context_type: None,
+        versions: HashMap::new(),
+        objects: HashMap::new(),
+        next_id: SERVER_ID_BASE,
+        serial: 0,
+        connection: None,
+        seat: None,
+        seat_devices: HashMap::new(),
+        scroll_remainder: (0, 0),
+        out: Vec::new(),
+        out_fds: Vec::new(),
+    };
+    if let Err(err) = server.run().await {
+        log::error!("EIS connection failed: {}", err);
+        if err.kind() == io::ErrorKind::InvalidData
+            && let Some(connection) = server.connection
+        {
+            let serial = server.serial;
+            // ei_connection.disconnected
+            server.send(
+                Message::new(connection, 0)
+                    .uint32(serial)
+                    .uint32(DISCONNECT_REASON_PROTOCOL)
+                    .string(&err.to_string()),
+            );
+            let _ = server.flush().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_roundtrip() {
+        for value in ["", "abc", "abcd", "ei_pointer_absolute"] {
+            let message = Message::new(1, 0).string(value).uint32(7);
+            assert_eq!(message.0.len() % 4, 0);
+            let mut args = Args(&message.0[HEADER_SIZE..]);
+            assert_eq!(args.string().unwrap(), value);
+            assert_eq!(args.uint32().unwrap(), 7);
+            assert!(args.0.is_empty());
+        }
+    }
+
+    #[test]
+    fn short_message() {
+        let message = Message::new(1, 0).uint32(5);
+        let mut args = Args(&message.0[HEADER_SIZE..]);
+        assert!(args.uint64().is_err());
+        // Length past the end of the message
+        let message = Message::new(1, 0).uint32(8);
+        let mut args = Args(&message.0[HEADER_SIZE..]);
+        assert!(args.string().is_err());
+    }
+
+    #[test]
+    fn capabilities() {
+        let capabilities: Vec<_> = Interface::DEVICE_INTERFACES
+            .into_iter()
+            .map(Interface::capability)
+            .collect();
+        assert_eq!(capabilities, [1, 2, 4, 8, 16]);
+        assert_eq!(Interface::Seat.capability(), 0);
+        assert_eq!(Interface::from_name("ei_scroll"), Some(Interface::Scroll));
+        assert_eq!(Interface::from_name("ei_handshake"), None);
+    }
+}
@@ -5,22 +5,45 @@ use ashpd::enumflags2::BitFlags;
 use futures::stream::{FuturesOrdered, StreamExt};
 use std::collections::HashMap;
 use std::mem;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::Sender;
+use wayland_client::protocol::wl_output::WlOutput;
 use zbus::zvariant;
 
+use crate::permission_store::PermissionStoreProxy;
+use crate::remote_desktop::RemoteDesktopData;
 use crate::screencast_dialog::{self, CaptureSources};
 use crate::screencast_thread::ScreencastThread;
 use crate::wayland::{CaptureSource, WaylandHelper};
 use crate::{PortalResponse, Request, subscription};
 
 const CURSOR_MODE_HIDDEN: u32 = 1;
-const CURSOR_MODE_EMBEDDED: u32 = 2;
+pub(crate) const CURSOR_MODE_EMBEDDED: u32 = 2;
 const CURSOR_MODE_METADATA: u32 = 4;
 
 const SOURCE_TYPE_MONITOR: u32 = 1;
 const SOURCE_TYPE_WINDOW: u32 = 2;
+/// Not in `AvailableSourceTypes`: streaming a virtual monitor needs the compositor to create a
+/// headless output of the requested size, and cosmic-comp has no protocol for that.
 const SOURCE_TYPE_VIRTUAL: u32 = 4;
 
+const PERSIST_MODE_NONE: u32 = 0;
+const PERSIST_MODE_TRANSIENT: u32 = 1;
+const PERSIST_MODE_PERSISTENT: u32 = 2;
+
+const RESTORE_DATA_VENDOR: &str = "COSMIC";
+const RESTORE_DATA_VERSION: u32 = 2;
+/// Restore data holding the sources themselves, from before grants were kept by the portal
+const RESTORE_DATA_VERSION_SOURCES: u32 = 1;
+
+/// Permission store table for grants with `persist_mode` 2, keyed by restore token.
+///
+/// A grant is revoked by turning off "Remember this selection" the next time the dialog is
+/// shown, or with `flatpak permission-remove cosmic-screencast <token>`, or for all tokens of
+/// an app with `flatpak permission-reset <app-id>`.
+const PERMISSION_TABLE: &str = "cosmic-screencast";
+const PERMISSION_GRANTED: &str = "yes";
+
 #[derive(zvariant::SerializeDict, zvariant::Type)]
 #[zvariant(signature = "a{sv}")]
 struct CreateSessionResult {
@@ -73,6 +96,28 @@ impl PersistedCaptureSources {
 
         Some(CaptureSources { outputs, toplevels })
     }
+
+    fn to_value(&self) -> zvariant::Value<'static> {
+        zvariant::Value::from(zvariant::Structure::from((
+            self.outputs.clone(),
+            self.toplevels.clone(),
+        )))
+    }
+}
+
+impl TryFrom<&zvariant::Value<'_>> for PersistedCaptureSources {
+    type Error = ();
+    fn try_from(value: &zvariant::Value<'_>) -> Result<Self, ()> {
+        // Permission store data may be wrapped in an additional variant
+        let value = match value {
+            zvariant::Value::Value(value) => &**value,
+            value => value,
+        };
+        let structure =
+            zvariant::Structure::try_from(value.try_clone().map_err(|_| ())?).map_err(|_| ())?;
+        let (outputs, toplevels) = structure.try_into().map_err(|_| ())?;
+        Ok(PersistedCaptureSources { outputs, toplevels })
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, zvariant::Type)]
@@ -83,34 +128,145 @@ struct RestoreData {
     data: zvariant::OwnedValue,
 }
 
-impl From<PersistedCaptureSources> for RestoreData {
-    fn from(sources: PersistedCaptureSources) -> RestoreData {
+impl RestoreData {
+    fn new(token: String) -> Self {
         RestoreData {
-            vendor: "COSMIC".to_string(),
-            version: 1,
-            data: zvariant::Value::from(zvariant::Structure::from((
-                sources.outputs,
-                sources.toplevels,
-            )))
-            .try_to_owned()
-            .unwrap(),
+            vendor: RESTORE_DATA_VENDOR.to_string(),
+            version: RESTORE_DATA_VERSION,
+            data: zvariant::Value::from(token).try_to_owned().unwrap(),
+        }
+    }
+
+    fn restore(&self) -> Option<Restore> {
+        match (&*self.vendor, self.version) {
+            (RESTORE_DATA_VENDOR, RESTORE_DATA_VERSION) => Some(Restore::Token(
+                String::try_from(self.data.try_clone().ok()?).ok()?,
+            )),
+            (RESTORE_DATA_VENDOR, RESTORE_DATA_VERSION_SOURCES) => {
+                PersistedCaptureSources::try_from(&*self.data)
+                    .ok()
+                    .map(Restore::Sources)
+            }
+            _ => None,
         }
     }
 }
 
-impl TryFrom<&RestoreData> for PersistedCaptureSources {
-    type Error = ();
-    fn try_from(restore_data: &RestoreData) -> Result<Self, ()> {
-        if (&*restore_data.vendor, restore_data.version) != ("COSMIC", 1) {
-            return Err(());
+/// What an app's restore data refers to
+#[derive(Clone)]
+enum Restore {
+    /// Restore token of a grant, looked up in `Grants`
+    Token(String),
+    /// Sources from version 1 restore data, which is replaced with a grant once the session
+    /// starts
+    Sources(PersistedCaptureSources),
+}
+
+/// Capture sources the user granted to an app, by restore token
+#[derive(Clone, Default)]
+struct Grants {
+    /// Grants with `persist_mode` 1, kept while the portal is running
+    transient: Arc<Mutex<HashMap<String, (String, PersistedCaptureSources)>>>,
+}
+
+impl Grants {
+    async fn lookup(
+        &self,
+        connection: &zbus::Connection,
+        app_id: &str,
+        token: &str,
+    ) -> Option<PersistedCaptureSources> {
+        if let Some((granted_app_id, sources)) = self.transient.lock().unwrap().get(token) {
+            return (granted_app_id == app_id).then(|| sources.clone());
         }
-        let structure = zvariant::Structure::try_from(&*restore_data.data).map_err(|_| ())?;
-        let (outputs, toplevels) = structure.try_into().map_err(|_| ())?;
-        Ok(PersistedCaptureSources { outputs, toplevels })
+
+        let permission_store = PermissionStoreProxy::new(connection)
+            .await
+            .inspect_err(|err| log::error!("Failed to connect to permission store: {}", err))
+            .ok()?;
+        let (permissions, data) = permission_store
+            .lookup(PERMISSION_TABLE, token)
+            .await
+            .inspect_err(|err| log::info!("No screencast grant for restore data: {}", err))
+            .ok()?;
+        if !permissions
+            .get(app_id)
+            .is_some_and(|x| x.iter().any(|x| x == PERMISSION_GRANTED))
+        {
+            log::info!("Screencast grant not valid for '{}'", app_id);
+            return None;
+        }
+        PersistedCaptureSources::try_from(&*data)
+            .inspect_err(|_| log::warn!("Invalid screencast grant in permission store"))
+            .ok()
+    }
+
+    /// Removes a grant, so the app is prompted again
+    async fn revoke(&self, connection: &zbus::Connection, token: &str) {
+        if self.transient.lock().unwrap().remove(token).is_some() {
+            return;
+        }
+        let result = async {
+            PermissionStoreProxy::new(connection)
+                .await?
+                .delete(PERMISSION_TABLE, token)
+                .await
+        };
+        if let Err(err) = result.await {
+            log::info!("No screencast grant to revoke: {}", err);
+        }
+    }
+
+    /// Stores a grant, returning the restore token to give to the app
+    async fn store(
+        &self,
+        connection: &zbus::Connection,
+        app_id: &str,
+        persist_mode: u32,
+        token: Option<String>,
+        sources: PersistedCaptureSources,
+    ) -> Option<String> {
+        let token = token.or_else(new_token)?;
+        match persist_mode {
+            PERSIST_MODE_TRANSIENT => {
+                self.transient
+                    .lock()
+                    .unwrap()
+                    .insert(token.clone(), (app_id.to_string(), sources));
+            }
+            PERSIST_MODE_PERSISTENT => {
+                self.transient.lock().unwrap().remove(&token);
+                let permission_store = PermissionStoreProxy::new(connection)
+                    .await
+                    .inspect_err(|err| {
+                        log::error!("Failed to connect to permission store: {}", err)
+                    })
+                    .ok()?;
+                permission_store
+                    .set(
+                        PERMISSION_TABLE,
+                        true,
+                        &token,
+                        HashMap::from([(app_id, vec![PERMISSION_GRANTED])]),
+                        &sources.to_value(),
+                    )
+                    .await
+                    .inspect_err(|err| log::error!("Failed to store screencast grant: {}", err))
+                    .ok()?;
+            }
+            _ => return None,
+        }
+        Some(token)
     }
 }
 
-// TODO TryFrom
+fn new_token() -> Option<String> {
+    let mut bytes = [0u8; 16];
+    rustix::rand::getrandom(&mut bytes, rustix::rand::GetRandomFlags::empty())
+        .inspect_err(|err| log::error!("Failed to generate restore token: {}", err))
+        .ok()?;
+    Some(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
 
 #[derive(zvariant::DeserializeDict, zvariant::Type)]
 #[zvariant(signature = "a{sv}")]
@@ -131,7 +287,8 @@ pub struct StreamProps {
     position: Option<(i32, i32)>,
     size: (i32, i32),
     source_type: u32,
-    // TODO: Add when remote desktop portal is implemented
+    /// Output name or toplevel identifier, used by remote desktop sessions to
+    /// map absolute pointer motion to the stream's output
     mapping_id: Option<String>,
 }
 
@@ -143,33 +300,69 @@ struct StartResult {
     restore_data: Option<RestoreData>,
 }
 
+/// Session data shared by ScreenCast and RemoteDesktop sessions, since a
+/// remote desktop session can also select sources to screencast.
 #[derive(Default)]
-struct SessionData {
-    screencast_threads: Vec<ScreencastThread>,
-    cursor_mode: Option<u32>,
-    multiple: bool,
-    source_types: BitFlags<SourceType>,
-    persisted_capture_sources: Option<PersistedCaptureSources>,
-    closed: bool,
+pub(crate) struct SessionData {
+    pub screencast_threads: Vec<ScreencastThread>,
+    pub cursor_mode: Option<u32>,
+    pub multiple: bool,
+    pub source_types: BitFlags<SourceType>,
+    restore: Option<Restore>,
+    persist_mode: u32,
+    pub remote_desktop: Option<RemoteDesktopData>,
+    pub closed: bool,
 }
 
 impl SessionData {
-    fn close(&mut self) {
+    pub fn close(&mut self) {
         for thread in mem::take(&mut self.screencast_threads) {
             thread.stop();
         }
+        // Destroys virtual input devices
+        self.remote_desktop = None;
         self.closed = true
     }
+
+    /// Output captured by the stream with PipeWire node `node_id`, if it is a monitor stream
+    pub fn stream_output(
+        &self,
+        wayland_helper: &WaylandHelper,
+        node_id: u32,
+    ) -> Option<(WlOutput, (i32, i32))> {
+        let props = self
+            .screencast_threads
+            .iter()
+            .find(|thread| thread.node_id() == node_id)?
+            .stream_props();
+        if props.source_type != SOURCE_TYPE_MONITOR {
+            return None;
+        }
+        let output = wayland_helper.output_for_name(props.mapping_id.as_deref()?)?;
+        Some((output, props.size))
+    }
+
+    pub fn streams(&self) -> Vec<(u32, StreamProps)> {
+        self.screencast_threads
+            .iter()
+            .map(|thread| (thread.node_id(), thread.stream_props()))
+            .collect()
+    }
 }
 
 pub struct ScreenCast {
     wayland_helper: WaylandHelper,
     tx: Sender<subscription::Event>,
+    grants: Grants,
 }
 
 impl ScreenCast {
     pub fn new(wayland_helper: WaylandHelper, tx: Sender<subscription::Event>) -> Self {
-        Self { wayland_helper, tx }
+        Self {
+            wayland_helper,
+            tx,
+            grants: Grants::default(),
+        }
     }
 }
 
@@ -217,9 +410,13 @@ impl ScreenCast {
                 if session_data.source_types.is_empty() {
                     session_data.source_types = SourceType::Monitor.into();
                 }
+                session_data.persist_mode = options
+                    .persist_mode
+                    .unwrap_or(PERSIST_MODE_NONE)
+                    .min(PERSIST_MODE_PERSISTENT);
                 if let Some(restore_data) = &options.restore_data {
-                    if let Ok(persisted_capture_sources) = restore_data.try_into() {
-                        session_data.persisted_capture_sources = Some(persisted_capture_sources);
+                    if let Some(restore) = restore_data.restore() {
+                        session_data.restore = Some(restore);
                     } else {
                         log::warn!("unrecognized screencopy restore data: {:?}", restore_data);
                     }
@@ -247,18 +444,24 @@ impl ScreenCast {
                 return PortalResponse::Other;
             };
 
-            let (cursor_mode, multiple, source_types, persisted_capture_sources) = {
+            let (cursor_mode, multiple, source_types, restore, persist_mode) = {
                 let session_data = interface.get_mut().await;
                 let cursor_mode = session_data.cursor_mode.unwrap_or(CURSOR_MODE_EMBEDDED);
                 let multiple = session_data.multiple;
                 let source_types = session_data.source_types;
-                let persisted_capture_sources = session_data.persisted_capture_sources.clone();
-                (
-                    cursor_mode,
-                    multiple,
-                    source_types,
-                    persisted_capture_sources,
-                )
+                let restore = session_data.restore.clone();
+                let persist_mode = session_data.persist_mode;
+                (cursor_mode, multiple, source_types, restore, persist_mode)
+            };
+
+            let (restore_token, persisted_capture_sources) = match restore {
+                Some(Restore::Token(token)) => {
+                    let sources = self.grants.lookup(connection, &app_id, &token).await;
+                    (Some(token), sources)
+                }
+                // Issues a token for the sources, migrating to a grant
+                Some(Restore::Sources(sources)) => (None, Some(sources)),
+                None => (None, None),
             };
 
             // XXX
@@ -268,6 +471,7 @@ impl ScreenCast {
                 return PortalResponse::Other;
             }
 
+            let mut remember = persist_mode != PERSIST_MODE_NONE;
             let capture_sources = if let Some(capture_sources) =
                 persisted_capture_sources.and_then(|x| x.to_capture_sources(&self.wayland_helper))
             {
@@ -277,87 +481,25 @@ impl ScreenCast {
                 let resp = screencast_dialog::show_screencast_prompt(
                     &self.tx,
                     &session_handle,
-                    app_id,
+                    app_id.clone(),
                     multiple,
                     source_types,
+                    remember,
                     &self.wayland_helper,
                 )
                 .await;
-                let Some(capture_sources) = resp else {
+                let Some((capture_sources, remember_selection)) = resp else {
                     return PortalResponse::Cancelled;
                 };
+                remember = remember_selection;
                 capture_sources
             };
 
-            let overlay_cursor = cursor_mode == CURSOR_MODE_EMBEDDED;
-            // Use `FuturesOrdered` so streams are in consistent order
-            let mut res_futures = FuturesOrdered::new();
-            for output in &capture_sources.outputs {
-                let info = self.wayland_helper.output_info(output);
-                let (position, size) = if let Some(info) = info {
-                    (info.logical_position, info.logical_size.unwrap_or((0, 0)))
-                } else {
-                    (Some((0, 0)), (0, 0))
-                };
-                res_futures.push_back(ScreencastThread::new(
-                    self.wayland_helper.clone(),
-                    CaptureSource::Output(output.clone()),
-                    overlay_cursor,
-                    StreamProps {
-                        position,
-                        size,
-                        source_type: SOURCE_TYPE_MONITOR,
-                        mapping_id: None,
-                    },
-                ));
-            }
-            let toplevel_infos = self.wayland_helper.toplevels();
-            for foreign_toplevel in &capture_sources.toplevels {
-                let info = toplevel_infos
-                    .iter()
-                    .find(|info| info.foreign_toplevel == *foreign_toplevel);
-                let size = if let Some(info) = info {
-                    // Use size on output with greatest area
-                    // XXX: No way to get size of whole toplevel?
-                    info.geometry
-                        .values()
-                        .max_by_key(|info| info.width * info.height)
-                        .map_or((0, 0), |info| (info.width, info.height))
-                } else {
-                    (0, 0)
-                };
-                res_futures.push_back(ScreencastThread::new(
-                    self.wayland_helper.clone(),
-                    CaptureSource::Toplevel(foreign_toplevel.clone()),
-                    overlay_cursor,
-                    StreamProps {
-                        position: None,
-                        size,
-                        source_type: SOURCE_TYPE_WINDOW,
-                        mapping_id: None,
-                    },
-                ));
-            }
-
-            let mut failed = false;
-            let mut screencast_threads = Vec::new();
-            while let Some(res) = res_futures.next().await {
-                match res {
-                    Ok(thread) => screencast_threads.push(thread),
-                    Err(err) => {
-                        log::error!("Screencast thread failed: {}", err);
-                        failed = true;
-                    }
-                }
-            }
-
-            // Stop any thread that didn't fail
-            if failed {
-                for thread in screencast_threads {
-                    thread.stop();
-                }
+            let Some(screencast_threads) =
+                start_streams(&self.wayland_helper, &capture_sources, cursor_mode).await
+            else {
                 return PortalResponse::Other;
-            }
+            };
 
             // Session may have already been cancelled
             if interface.get().await.closed {
@@ -367,21 +509,33 @@ impl ScreenCast {
                 return PortalResponse::Cancelled;
             }
 
-            let streams = screencast_threads
-                .iter()
-                .map(|thread| (thread.node_id(), thread.stream_props()))
-                .collect();
-            interface.get_mut().await.screencast_threads = screencast_threads;
+            let streams = {
+                let mut session_data = interface.get_mut().await;
+                session_data.screencast_threads = screencast_threads;
+                session_data.streams()
+            };
 
-            let persisted_capture_sources = PersistedCaptureSources::from_capture_sources(
+            // Remember the selection, so the app can skip the prompt next time
+            let restore_token = if !remember {
+                if let Some(token) = restore_token {
+                    self.grants.revoke(connection, &token).await;
+                }
+                None
+            } else if let Some(sources) = PersistedCaptureSources::from_capture_sources(
                 &self.wayland_helper,
                 &capture_sources,
-            );
+            ) {
+                self.grants
+                    .store(connection, &app_id, persist_mode, restore_token, sources)
+                    .await
+            } else {
+                None
+            };
 
             PortalResponse::Success(StartResult {
                 streams,
-                persist_mode: None,
-                restore_data: persisted_capture_sources.map(|x| x.into()),
+                persist_mode: restore_token.is_some().then_some(persist_mode),
+                restore_data: restore_token.map(RestoreData::new),
             })
         })
         .await
@@ -394,8 +548,7 @@ impl ScreenCast {
 
     #[zbus(property)]
     async fn available_cursor_modes(&self) -> u32 {
-        // TODO: Support metadata?
-        CURSOR_MODE_HIDDEN | CURSOR_MODE_EMBEDDED
+        CURSOR_MODE_HIDDEN | CURSOR_MODE_EMBEDDED | CURSOR_MODE_METADATA
     }
 
     #[zbus(property, name = "version")]
@@ -403,3 +556,90 @@ impl ScreenCast {
         4
     }
 }
+
+/// Starts a PipeWire stream for each capture source. If any stream fails, the
+/// others are stopped and `None` is returned.
+pub(crate) async fn start_streams(
+    wayland_helper: &WaylandHelper,
+    capture_sources: &CaptureSources,
+    cursor_mode: u32,
+) -> Option<Vec<ScreencastThread>> {
+    let overlay_cursor = cursor_mode == CURSOR_MODE_EMBEDDED;
+    let cursor_metadata = cursor_mode == CURSOR_MODE_METADATA;
+    // Use `FuturesOrdered` so streams are in consistent order
+    let mut res_futures = FuturesOrdered::new();
+    for output in &capture_sources.outputs {
+        let info = wayland_helper.output_info(output);
+        let (position, size, name) = if let Some(info) = info {
+            (
+                info.logical_position,
+                info.logical_size.unwrap_or((0, 0)),
+                info.name,
+            )
+        } else {
+            (Some((0, 0)), (0, 0), None)
+        };
+        res_futures.push_back(ScreencastThread::new(
+            wayland_helper.clone(),
+            CaptureSource::Output(output.clone()),
+            overlay_cursor,
+            cursor_metadata,
+            StreamProps {
+                position,
+                size,
+                source_type: SOURCE_TYPE_MONITOR,
+                mapping_id: name,
+            },
+        ));
+    }
+    let toplevel_infos = wayland_helper.toplevels();
+    for foreign_toplevel in &capture_sources.toplevels {
+        let info = toplevel_infos
+            .iter()
+            .find(|info| info.foreign_toplevel == *foreign_toplevel);
+        let size = if let Some(info) = info {
+            // Use size on output with greatest area
+            // XXX: No way to get size of whole toplevel?
+            info.geometry
+                .values()
+                .max_by_key(|info| info.width * info.height)
+                .map_or((0, 0), |info| (info.width, info.height))
+        } else {
+            (0, 0)
+        };
+        let identifier = info.map(|info| info.identifier.clone());
+        res_futures.push_back(ScreencastThread::new(
+            wayland_helper.clone(),
+            CaptureSource::Toplevel(foreign_toplevel.clone()),
+            overlay_cursor,
+            cursor_metadata,
+            StreamProps {
+                position: None,
+                size,
+                source_type: SOURCE_TYPE_WINDOW,
+                mapping_id: identifier,
+            },
+        ));
+    }
+
+    let mut failed = false;
+    let mut screencast_threads = Vec::new();
+    while let Some(res) = res_futures.next().await {
+        match res {
+            Ok(thread) => screencast_threads.push(thread),
+            Err(err) => {
+                log::error!("Screencast thread failed: {}", err);
+                failed = true;
+            }
+        }
+    }
+
+    // Stop any thread that didn't fail
+    if failed {
+        for thread in screencast_threads {
+            thread.stop();
+        }
+        return None;
+    }
+    Some(screencast_threads)
+}
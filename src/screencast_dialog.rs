@@ -43,14 +43,17 @@ pub async fn hide_screencast_prompt(
         .await;
 }
 
+/// Asks which sources to share. With `persist`, the dialog also offers to remember the
+/// selection, and returns whether the user kept that on.
 pub async fn show_screencast_prompt(
     subscription_tx: &mpsc::Sender<crate::subscription::Event>,
     session_handle: &zvariant::ObjectPath<'_>,
     app_id: String,
     multiple: bool,
     source_types: BitFlags<SourceType>,
+    persist: bool,
     wayland_helper: &WaylandHelper,
-) -> Option<CaptureSources> {
+) -> Option<(CaptureSources, bool)> {
     let locales = get_languages_from_env();
     let desktop_entries = load_desktop_entries(&locales).await;
 
@@ -96,6 +99,7 @@ pub async fn show_screencast_prompt(
         app_name,
         tx,
         capture_sources: Default::default(),
+        remember: persist.then_some(true),
     };
     subscription_tx
         .send(crate::subscription::Event::Screencast(args))
@@ -104,7 +108,7 @@ pub async fn show_screencast_prompt(
     rx.recv().await.unwrap()
 }
 
-async fn load_desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
+pub(crate) async fn load_desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
     let mut entries = Vec::new();
     for p in fde::Iter::new(fde::default_paths()) {
         if let Ok(data) = tokio::fs::read_to_string(&p).await
@@ -116,7 +120,10 @@ async fn load_desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
     entries
 }
 
-fn get_desktop_entry<'a>(entries: &'a [DesktopEntry], id: &str) -> Option<&'a DesktopEntry> {
+pub(crate) fn get_desktop_entry<'a>(
+    entries: &'a [DesktopEntry],
+    id: &str,
+) -> Option<&'a DesktopEntry> {
     fde::find_app_by_id(entries, Ascii::new(id))
 }
 
@@ -146,12 +153,14 @@ pub struct Args {
     toplevels: Vec<(ToplevelInfo, Option<String>)>,
     app_name: Option<String>,
     // Should be oneshot, but need `Clone` bound
-    tx: mpsc::Sender<Option<CaptureSources>>,
+    tx: mpsc::Sender<Option<(CaptureSources, bool)>>,
     capture_sources: CaptureSources,
+    /// Whether to remember the selection, if the app asked to
+    remember: Option<bool>,
 }
 
 impl Args {
-    fn send_response(self, response: Option<CaptureSources>) {
+    fn send_response(self, response: Option<(CaptureSources, bool)>) {
         tokio::spawn(async move {
             if let Err(err) = self.tx.send(response).await {
                 log::error!("Failed to send screencast event: {}", err);
@@ -183,6 +192,7 @@ pub enum Msg {
     ActivateTab(widget::segmented_button::Entity),
     SelectOutput(WlOutput),
     SelectToplevel(ExtForeignToplevelHandleV1),
+    Remember(bool),
     Share,
     Cancel,
 }
@@ -230,10 +240,14 @@ pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::ap
                 args.capture_sources.toplevels.push(toplevel);
             }
         }
+        Msg::Remember(remember) => {
+            args.remember = Some(remember);
+        }
         Msg::Share => {
             if let Some(mut args) = portal.screencast_args.take() {
                 let response = mem::take(&mut args.capture_sources);
-                args.send_response(Some(response));
+                let remember = args.remember.unwrap_or(false);
+                args.send_response(Some((response, remember)));
                 return destroy_layer_surface(*SCREENCAST_ID);
             }
         }
@@ -490,7 +504,12 @@ pub(crate) fn view(portal: &CosmicPortal) -> cosmic::Element<'_, Msg> {
     let unknown = fl!("unknown-application");
     let app_name = args.app_name.as_deref().unwrap_or(&unknown);
 
-    let control = widget::column::with_children(vec![tabs.into(), list]).spacing(8);
+    let mut control = widget::column::with_children(vec![tabs.into(), list]).spacing(8);
+    if let Some(remember) = args.remember {
+        control = control.push(
+            widget::checkbox(fl!("share-screen", "remember"), remember).on_toggle(Msg::Remember),
+        );
+    }
     autosize::autosize(
         KeyboardWrapper::new(
             widget::dialog()
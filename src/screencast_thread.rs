@@ -6,6 +6,7 @@
 
 use cosmic_client_toolkit::screencopy::{FailureReason, Formats, Rect};
 use futures::executor::block_on;
+use futures::future::{Either, select};
 use pipewire::spa::pod::deserialize::PodDeserializer;
 use pipewire::spa::pod::serialize::PodSerializer;
 use pipewire::spa::pod::{self, Pod};
@@ -16,6 +17,7 @@ use pipewire::sys::pw_buffer;
 use std::collections::HashMap;
 use std::ffi::c_void;
 use std::os::fd::IntoRawFd;
+use std::pin::pin;
 use std::{io, iter, slice};
 use tokio::sync::oneshot;
 use wayland_client::WEnum;
@@ -23,7 +25,7 @@ use wayland_client::protocol::{wl_buffer, wl_output, wl_shm};
 
 use crate::buffer;
 use crate::screencast::StreamProps;
-use crate::wayland::{CaptureSource, DmabufHelper, Session, WaylandHelper};
+use crate::wayland::{CaptureSource, CursorSession, DmabufHelper, Session, WaylandHelper};
 
 static FORMAT_MAP: &[(gbm::Format, Id)] = &[
     (gbm::Format::Abgr8888, Id(spa_sys::SPA_VIDEO_FORMAT_RGBA)),
@@ -65,6 +67,7 @@ impl ScreencastThread {
         wayland_helper: WaylandHelper,
         capture_source: CaptureSource,
         overlay_cursor: bool,
+        cursor_metadata: bool,
         stream_props: StreamProps,
     ) -> anyhow::Result<Self> {
         let (tx, rx) = oneshot::channel();
@@ -75,6 +78,7 @@ impl ScreencastThread {
                 wayland_helper,
                 capture_source,
                 overlay_cursor,
+                cursor_metadata,
                 thread_stop_tx_clone,
             ) {
                 Ok((loop_, _stream, _listener, _context, node_id_rx)) => {
@@ -115,6 +119,8 @@ struct StreamData {
     format: gbm::Format,
     modifier: Option<gbm::Modifier>,
     session: Session,
+    /// Set if cursor is sent as metadata, rather than drawn into frames
+    cursor_session: Option<CursorSession>,
     formats: Formats,
     node_id_tx: Option<oneshot::Sender<Result<u32, anyhow::Error>>>,
     buffer_damage: HashMap<wl_buffer::WlBuffer, Vec<Rect>>,
@@ -336,7 +342,13 @@ impl StreamData {
             .modifier
             .and_then(|m| self.plane_count(self.format, m))
             .unwrap_or(1);
-        let params = other_params(self.width(), self.height(), blocks, self.modifier.is_some());
+        let params = other_params(
+            self.width(),
+            self.height(),
+            blocks,
+            self.modifier.is_some(),
+            self.cursor_session.is_some(),
+        );
         let mut params: Vec<_> = params.iter().map(|x| &**x).collect();
         if let Err(err) = stream.update_params(&mut params) {
             log::error!("failed to update pipewire params: {}", err);
@@ -450,7 +462,23 @@ impl StreamData {
                 .get(wl_buffer)
                 .map(Vec::as_slice)
                 .unwrap_or(full_damage);
-            match block_on(self.session.capture_wl_buffer(wl_buffer, damage)) {
+            // Frames only arrive when the source is damaged, which moving a cursor that isn't
+            // drawn into it doesn't do, so cursor changes are sent in buffers of their own
+            let res = {
+                let mut capture = pin!(self.session.capture_wl_buffer(wl_buffer, damage));
+                loop {
+                    let Some(cursor_session) = &self.cursor_session else {
+                        break block_on(capture.as_mut());
+                    };
+                    match block_on(select(capture.as_mut(), pin!(cursor_session.changed()))) {
+                        Either::Left((res, _)) => break res,
+                        Either::Right(((), _)) => unsafe {
+                            queue_cursor_buffer(stream, cursor_session)
+                        },
+                    }
+                }
+            };
+            match res {
                 Ok(frame) => {
                     self.buffer_damage
                         .entry(wl_buffer.clone())
@@ -469,6 +497,12 @@ impl StreamData {
                     } {
                         video_transform.transform = convert_transform(frame.transform);
                     }
+                    if let Some(cursor_session) = &self.cursor_session {
+                        unsafe {
+                            set_chunk_sizes(buffer, Some(self.height()));
+                            write_cursor_meta(buffer, cursor_session);
+                        }
+                    }
                 }
                 Err(err) => {
                     if err == WEnum::Value(FailureReason::BufferConstraints) {
@@ -492,6 +526,7 @@ fn start_stream(
     wayland_helper: WaylandHelper,
     capture_source: CaptureSource,
     overlay_cursor: bool,
+    cursor_metadata: bool,
     thread_stop_tx: pipewire::channel::Sender<()>,
 ) -> anyhow::Result<(
     pipewire::main_loop::MainLoopRc,
@@ -508,6 +543,11 @@ fn start_stream(
 
     let (node_id_tx, node_id_rx) = oneshot::channel();
 
+    let cursor_session = if cursor_metadata {
+        wayland_helper.capture_cursor_session(&capture_source)
+    } else {
+        None
+    };
     let session = wayland_helper.capture_source_session(capture_source, overlay_cursor);
 
     let Some(formats) = block_on(session.wait_for_formats(|formats| formats.clone())) else {
@@ -543,6 +583,7 @@ fn start_stream(
         wayland_helper,
         dmabuf_helper,
         session,
+        cursor_session,
         formats,
         format: gbm::Format::Abgr8888,
         modifier: None,
@@ -592,6 +633,90 @@ unsafe fn buffer_find_meta_data<'a, T>(
     }
 }
 
+/// Sets the size of each plane to `height` rows, or to 0 for a buffer that only updates
+/// metadata
+///
+/// SAFETY: buffer must be non-null and valid
+unsafe fn set_chunk_sizes(buffer: *const pipewire_sys::pw_buffer, height: Option<u32>) {
+    let buf = unsafe { &mut *(*buffer).buffer };
+    let datas = unsafe { slice::from_raw_parts_mut(buf.datas, buf.n_datas as usize) };
+    for data in datas {
+        let chunk = unsafe { &mut *data.chunk };
+        chunk.size = height.map_or(0, |height| height * chunk.stride as u32);
+    }
+}
+
+/// Queues a free buffer with only the cursor metadata, if there is one
+///
+/// SAFETY: must be called from the stream's thread
+unsafe fn queue_cursor_buffer(stream: &Stream, cursor_session: &CursorSession) {
+    let buffer = unsafe { stream.dequeue_raw_buffer() };
+    if buffer.is_null() {
+        return;
+    }
+    unsafe {
+        set_chunk_sizes(buffer, None);
+        write_cursor_meta(buffer, cursor_session);
+        stream.queue_raw_buffer(buffer);
+    }
+}
+
+// SAFETY: buffer must be non-null and valid
+unsafe fn write_cursor_meta(
+    buffer: *const pipewire_sys::pw_buffer,
+    cursor_session: &CursorSession,
+) {
+    let meta = unsafe { spa_sys::spa_buffer_find_meta((*buffer).buffer, spa_sys::SPA_META_Cursor) };
+    let Some(meta) = (unsafe { meta.as_mut() }) else {
+        return;
+    };
+    if (meta.size as usize) < size_of::<spa_sys::spa_meta_cursor>() {
+        return;
+    }
+    let cursor = unsafe { &mut *(meta.data as *mut spa_sys::spa_meta_cursor) };
+
+    let state = cursor_session.state();
+    let Some((x, y)) = state.position else {
+        // An id of 0 means the cursor isn't visible
+        cursor.id = 0;
+        return;
+    };
+    // Changes when the image does, so consumers know to update it
+    cursor.id = state.serial.max(1);
+    cursor.flags = 0;
+    cursor.position.x = x;
+    cursor.position.y = y;
+    cursor.hotspot.x = state.hotspot.0;
+    cursor.hotspot.y = state.hotspot.1;
+    cursor.bitmap_offset = 0;
+
+    // Include the bitmap if it fits in the negotiated meta size
+    let Some(image) = &state.image else {
+        return;
+    };
+    let bitmap_offset = size_of::<spa_sys::spa_meta_cursor>();
+    let pixels_offset = size_of::<spa_sys::spa_meta_bitmap>();
+    let pixels_len = image.as_raw().len();
+    if bitmap_offset + pixels_offset + pixels_len > meta.size as usize {
+        return;
+    }
+    cursor.bitmap_offset = bitmap_offset as u32;
+    unsafe {
+        let bitmap_ptr = (meta.data as *mut u8).add(bitmap_offset);
+        let bitmap = &mut *(bitmap_ptr as *mut spa_sys::spa_meta_bitmap);
+        bitmap.format = spa_sys::SPA_VIDEO_FORMAT_RGBA;
+        bitmap.size.width = image.width();
+        bitmap.size.height = image.height();
+        bitmap.stride = image.width() as i32 * 4;
+        bitmap.offset = pixels_offset as u32;
+        std::ptr::copy_nonoverlapping(
+            image.as_raw().as_ptr(),
+            bitmap_ptr.add(pixels_offset),
+            pixels_len,
+        );
+    }
+}
+
 struct OwnedPod(Vec<u8>);
 
 impl OwnedPod {
@@ -637,6 +762,39 @@ fn meta() -> OwnedPod {
     // TODO: header, video damage
 }
 
+/// Size of cursor metadata with a `width` by `height` RGBA bitmap
+const fn cursor_meta_size(width: usize, height: usize) -> i32 {
+    (size_of::<spa_sys::spa_meta_cursor>()
+        + size_of::<spa_sys::spa_meta_bitmap>()
+        + width * height * 4) as i32
+}
+
+fn cursor_meta() -> OwnedPod {
+    OwnedPod::serialize(&pod::Value::Object(pod::Object {
+        type_: spa_sys::SPA_TYPE_OBJECT_ParamMeta,
+        id: spa_sys::SPA_PARAM_Meta,
+        properties: vec![
+            pod::Property {
+                key: spa_sys::SPA_PARAM_META_type,
+                flags: pod::PropertyFlags::empty(),
+                value: pod::Value::Id(spa::utils::Id(spa_sys::SPA_META_Cursor)),
+            },
+            pod::Property {
+                key: spa_sys::SPA_PARAM_META_size,
+                flags: pod::PropertyFlags::empty(),
+                value: pod::Value::Choice(pod::ChoiceValue::Int(spa::utils::Choice(
+                    spa::utils::ChoiceFlags::empty(),
+                    spa::utils::ChoiceEnum::Range {
+                        default: cursor_meta_size(64, 64),
+                        min: cursor_meta_size(1, 1),
+                        max: cursor_meta_size(256, 256),
+                    },
+                ))),
+            },
+        ],
+    }))
+}
+
 fn format_params(
     dmabuf: Option<&DmabufHelper>,
     fixated: Option<(gbm::Format, gbm::Modifier)>,
@@ -678,10 +836,17 @@ fn format_params(
     pods
 }
 
-fn other_params(width: u32, height: u32, blocks: u32, allow_dmabuf: bool) -> Vec<OwnedPod> {
+fn other_params(
+    width: u32,
+    height: u32,
+    blocks: u32,
+    allow_dmabuf: bool,
+    cursor_metadata: bool,
+) -> Vec<OwnedPod> {
     [
         Some(buffers(width, height, blocks, allow_dmabuf)),
         Some(meta()),
+        cursor_metadata.then(cursor_meta),
     ]
     .into_iter()
     .flatten()
@@ -11,45 +11,78 @@ use cosmic::iced::runtime::clipboard;
 use cosmic::iced::runtime::platform_specific::wayland::layer_surface::{
     IcedOutput, SctkLayerSurfaceSettings,
 };
-use cosmic::iced::{Length, Limits, window};
+use cosmic::iced::{Background, Border, Length, Limits, window};
 use cosmic::widget::space;
 use cosmic_client_toolkit::sctk::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
-use futures::stream::{FuturesUnordered, StreamExt};
-use image::RgbaImage;
+use futures::stream::StreamExt;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::qoi::QoiEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder, RgbaImage};
 use rustix::fd::AsFd;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io;
 use std::num::NonZeroU32;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 
 use wayland_client::protocol::wl_output::WlOutput;
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1;
 use zbus::zvariant;
 
 use crate::app::{CosmicPortal, OutputState};
-use crate::config::screenshot::ImageSaveLocation;
+use crate::color_picker::{self, PickColorResult};
+use crate::config::screenshot::{ImageFormat, ImageSaveLocation, Stitching};
 use crate::config::{self};
+use crate::file_chooser::{self, FileChooserOptions, OpenFileOptions};
+use crate::screenshot_editor::{self, Editor};
+use crate::screenshot_history::{self, History};
+use crate::scroll_capture::Stitcher;
 use crate::wayland::{CaptureSource, ShmImage, WaylandHelper};
 use crate::widget::keyboard_wrapper::KeyboardWrapper;
 use crate::widget::rectangle_selection::DragState;
-use crate::{PortalResponse, fl, subscription};
+use crate::{PortalResponse, fl, ocr, subscription};
 
 #[derive(Clone, Debug)]
 pub struct ScreenshotImage {
     pub rgba: RgbaImage,
     pub handle: cosmic::widget::image::Handle,
+    /// Window the image was captured from, if it's a toplevel capture
+    pub window: Option<WindowInfo>,
+}
+
+#[derive(Clone, Debug)]
+pub struct WindowInfo {
+    pub title: String,
+    pub app_id: String,
+    pub foreign_toplevel: ExtForeignToplevelHandleV1,
+    /// Logical position and size of the window, if the compositor sent it
+    pub geometry: Option<Rect>,
 }
 
 impl ScreenshotImage {
     fn new<T: AsFd>(img: ShmImage<T>) -> anyhow::Result<Self> {
-        let rgba = img.image_transformed()?;
+        Ok(Self::from_rgba(img.image_transformed()?))
+    }
+
+    fn new_toplevel<T: AsFd>(img: ShmImage<T>) -> anyhow::Result<Self> {
+        Ok(Self::from_rgba(toplevel_rgba(img)?))
+    }
+
+    fn from_rgba(rgba: RgbaImage) -> Self {
         let handle = cosmic::widget::image::Handle::from_rgba(
             rgba.width(),
             rgba.height(),
             rgba.clone().into_vec(),
         );
-        Ok(Self { rgba, handle })
+        Self {
+            rgba,
+            handle,
+            window: None,
+        }
     }
 
     pub fn width(&self) -> u32 {
@@ -61,6 +94,20 @@ impl ScreenshotImage {
     }
 }
 
+/// Window captures keep their alpha channel, so rounded corners stay transparent
+fn toplevel_rgba<T: AsFd>(img: ShmImage<T>) -> anyhow::Result<RgbaImage> {
+    let mut rgba = img.image_transformed()?;
+    // Wayland buffers are premultiplied
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        if a != 0 && a != 255 {
+            let unpremultiply = |c: u8| (c as u16 * 255 / a as u16).min(255) as u8;
+            pixel.0 = [unpremultiply(r), unpremultiply(g), unpremultiply(b), a];
+        }
+    }
+    Ok(rgba)
+}
+
 #[derive(zvariant::DeserializeDict, zvariant::Type, Clone, Debug)]
 #[zvariant(signature = "a{sv}")]
 pub struct ScreenshotOptions {
@@ -70,6 +117,10 @@ pub struct ScreenshotOptions {
     ///
     /// Defaults to false
     choose_destination: Option<bool>,
+    /// Custom value for a delay in seconds before capturing, with a countdown on screen.
+    ///
+    /// Defaults to 0
+    delay: Option<u32>,
 }
 
 #[derive(zvariant::SerializeDict, zvariant::Type)]
@@ -78,34 +129,54 @@ pub struct ScreenshotResult {
     uri: String,
 }
 
-struct ScreenshotBytes {
-    bytes: Vec<u8>,
+/// Screenshot offered on the clipboard, encoded when a client asks for one of its types
+struct ScreenshotClipboard {
+    image: RgbaImage,
+    format: ImageFormat,
+    quality: u8,
 }
 
-impl ScreenshotBytes {
-    fn new(bytes: Vec<u8>) -> Self {
-        Self { bytes }
+impl ScreenshotClipboard {
+    fn new(image: RgbaImage, format: ImageFormat, quality: u8) -> Self {
+        Self {
+            image,
+            format,
+            quality,
+        }
+    }
+
+    /// The configured format first, then widely supported ones
+    fn formats(&self) -> impl Iterator<Item = ImageFormat> + '_ {
+        std::iter::once(self.format).chain(
+            [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP]
+                .into_iter()
+                .filter(|format| *format != self.format),
+        )
     }
 }
 
-impl AsMimeTypes for ScreenshotBytes {
+impl AsMimeTypes for ScreenshotClipboard {
     fn available(&self) -> std::borrow::Cow<'static, [String]> {
-        Cow::Owned(vec!["image/png".to_string()])
+        Cow::Owned(
+            self.formats()
+                .map(|format| mime_type(format).to_string())
+                .collect(),
+        )
     }
 
-    fn as_bytes(&self, mime_type: &str) -> Option<std::borrow::Cow<'static, [u8]>> {
-        Some(Cow::Owned(self.bytes.clone()))
+    fn as_bytes(&self, mime: &str) -> Option<std::borrow::Cow<'static, [u8]>> {
+        let format = self.formats().find(|format| mime_type(*format) == mime)?;
+        let mut buffer = Vec::new();
+        if let Err(err) = write_image(&mut buffer, &self.image, format, self.quality) {
+            log::error!("Failed to encode screenshot as {}: {:?}", mime, err);
+            return None;
+        }
+        Some(Cow::Owned(buffer))
     }
 }
 
-#[derive(zvariant::SerializeDict, zvariant::Type)]
-#[zvariant(signature = "a{sv}")]
-struct PickColorResult {
-    color: (f64, f64, f64), // (ddd)
-}
-
 /// Logical Size and Position of a rectangle
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Rect {
     pub left: i32,
     pub top: i32,
@@ -140,14 +211,24 @@ impl Rect {
         }
     }
 
-    fn width(&self) -> i32 {
+    pub fn width(&self) -> i32 {
         self.right - self.left
     }
 
-    fn height(&self) -> i32 {
+    pub fn height(&self) -> i32 {
         self.bottom - self.top
     }
 
+    /// The same rectangle with `left <= right` and `top <= bottom`
+    fn normalized(&self) -> Rect {
+        Rect {
+            left: self.left.min(self.right),
+            top: self.top.min(self.bottom),
+            right: self.left.max(self.right),
+            bottom: self.top.max(self.bottom),
+        }
+    }
+
     pub fn dimensions(self) -> Option<RectDimension> {
         let width = NonZeroU32::new((self.width()).unsigned_abs())?;
         let height = NonZeroU32::new((self.height()).unsigned_abs())?;
@@ -171,27 +252,100 @@ impl Screenshot {
         Self { wayland_helper, tx }
     }
 
+    fn outputs(&self) -> Vec<Output> {
+        let mut outputs = Vec::new();
+        for output in self.wayland_helper.outputs() {
+            let Some(info) = self.wayland_helper.output_info(&output) else {
+                log::warn!("Output {:?} has no info", output);
+                continue;
+            };
+            let Some(name) = info.name.clone() else {
+                log::warn!("Output {:?} has no name", output);
+                continue;
+            };
+            let Some(logical_position) = info.logical_position else {
+                log::warn!("Output {:?} has no position", output);
+                continue;
+            };
+            let Some(logical_size) = info.logical_size else {
+                log::warn!("Output {:?} has no size", output);
+                continue;
+            };
+            outputs.push(Output {
+                output,
+                logical_position,
+                logical_size,
+                name,
+            });
+        }
+        outputs
+    }
+
     async fn interactive_toplevel_images(
         &self,
         outputs: &[Output],
     ) -> anyhow::Result<HashMap<String, Vec<ScreenshotImage>>> {
-        let wayland_helper = self.wayland_helper.clone();
-        Ok(outputs
+        let output_toplevels = self.wayland_helper.output_toplevels();
+        let toplevel_infos = self.wayland_helper.toplevels();
+
+        // Capture each toplevel once, even when it spans several outputs
+        let mut toplevels = Vec::new();
+        for toplevel in outputs
             .iter()
-            .map(move |Output { output, name, .. }| {
-                let wayland_helper = wayland_helper.clone();
+            .filter_map(|Output { output, .. }| output_toplevels.get(output))
+            .flatten()
+        {
+            if !toplevels.contains(toplevel) {
+                toplevels.push(toplevel.clone());
+            }
+        }
+        let images = self
+            .wayland_helper
+            .capture_toplevels_shm(toplevels, false)
+            .filter_map(|(foreign_toplevel, img)| {
+                let window = toplevel_infos
+                    .iter()
+                    .find(|info| info.foreign_toplevel == foreign_toplevel)
+                    .map(|info| WindowInfo {
+                        title: info.title.clone(),
+                        app_id: info.app_id.clone(),
+                        foreign_toplevel: info.foreign_toplevel.clone(),
+                        // Geometry is relative to each output the window is on
+                        geometry: info.geometry.iter().find_map(|(wl_output, geometry)| {
+                            let output = outputs.iter().find(|o| o.output == *wl_output)?;
+                            let (x, y) = output.logical_position;
+                            Some(Rect {
+                                left: x + geometry.x,
+                                top: y + geometry.y,
+                                right: x + geometry.x + geometry.width,
+                                bottom: y + geometry.y + geometry.height,
+                            })
+                        }),
+                    });
                 async move {
-                    let frame = wayland_helper
-                        .capture_output_toplevels_shm(output, false)
-                        .filter_map(|img| async { ScreenshotImage::new(img).ok() })
-                        .collect()
-                        .await;
-                    (name.clone(), frame)
+                    let mut image = ScreenshotImage::new_toplevel(img).ok()?;
+                    image.window = window;
+                    Some((foreign_toplevel, image))
                 }
             })
-            .collect::<FuturesUnordered<_>>()
-            .collect::<HashMap<String, _>>()
-            .await)
+            .collect::<Vec<_>>()
+            .await;
+
+        Ok(outputs
+            .iter()
+            .map(|Output { output, name, .. }| {
+                let output_images = output_toplevels
+                    .get(output)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|toplevel| {
+                        let (_, image) = images.iter().find(|(t, _)| t == toplevel)?;
+                        Some(image.clone())
+                    })
+                    .collect();
+                (name.clone(), output_images)
+            })
+            .collect())
     }
 
     async fn interactive_output_images(
@@ -199,43 +353,31 @@ impl Screenshot {
         outputs: &[Output],
         app_id: &str,
     ) -> anyhow::Result<HashMap<String, ScreenshotImage>> {
-        // collect screenshots from each output
-
-        let wayland_helper = self.wayland_helper.clone();
-
-        let mut map = HashMap::with_capacity(outputs.len());
-        for Output {
-            output,
-            logical_position: (output_x, output_y),
-            name,
-            ..
-        } in outputs
-        {
-            let frame = wayland_helper
-                .capture_source_shm(CaptureSource::Output(output.clone()), false)
-                .await
-                .ok_or_else(|| anyhow::anyhow!("shm screencopy failed"))?;
-            map.insert(name.clone(), ScreenshotImage::new(frame)?);
-        }
-
-        Ok(map)
+        capture_output_images(
+            &self.wayland_helper,
+            outputs
+                .iter()
+                .map(|Output { output, name, .. }| (name.clone(), output.clone())),
+        )
+        .await
     }
 
-    pub fn save_rgba(img: &RgbaImage, path: Option<&Path>) -> anyhow::Result<Vec<u8>> {
-        // Write to the buffer first since the image data will always be copied to the clipboard.
-        // This skips encoding the PNG twice.
-        let mut buffer = Vec::new();
-        write_png(&mut buffer, img)?;
-
-        if let Some(path) = path {
-            std::fs::write(path, &buffer)?;
-        }
-
-        Ok(buffer)
+    pub fn save_image(
+        img: &RgbaImage,
+        path: &Path,
+        format: ImageFormat,
+        quality: u8,
+    ) -> anyhow::Result<()> {
+        let file = std::fs::File::create_new(path)?;
+        write_image(io::BufWriter::new(file), img, format, quality)
     }
 
-    pub fn get_img_path(location: ImageSaveLocation) -> Option<PathBuf> {
-        let mut path = match location {
+    pub fn get_img_path(
+        location: &ImageSaveLocation,
+        file_name: &str,
+        extension: &str,
+    ) -> Option<PathBuf> {
+        let path = match location {
             ImageSaveLocation::Pictures => {
                 // First check for XDG_SCREENSHOTS_DIR environment variable
                 std::env::var_os("XDG_SCREENSHOTS_DIR")
@@ -251,6 +393,7 @@ impl Screenshot {
             ImageSaveLocation::Documents => {
                 dirs::document_dir().or_else(|| dirs::home_dir().map(|h| h.join("Documents")))
             }
+            ImageSaveLocation::Custom(path) => Some(path.clone()),
             ImageSaveLocation::Clipboard => None,
         }?;
 
@@ -260,15 +403,24 @@ impl Screenshot {
             return None;
         }
 
-        let name = jiff::Zoned::now()
-            .strftime("Screenshot_%Y-%m-%d_%H-%M-%S.png")
-            .to_string();
-        path.push(name);
+        // Don't overwrite an earlier screenshot with the same name
+        let mut img_path = path.join(format!("{file_name}.{extension}"));
+        let mut n = 1;
+        while img_path.exists() {
+            img_path = path.join(format!("{file_name}-{n}.{extension}"));
+            n += 1;
+        }
 
-        Some(path)
+        Some(img_path)
     }
 
-    async fn screenshot_inner(&self, outputs: &[Output], app_id: &str) -> anyhow::Result<PathBuf> {
+    async fn screenshot_inner(
+        &self,
+        outputs: &[Output],
+        app_id: &str,
+        format: ImageFormat,
+        quality: u8,
+    ) -> anyhow::Result<PathBuf> {
         let wayland_helper = self.wayland_helper.clone();
 
         let mut bounds_opt: Option<Rect> = None;
@@ -303,16 +455,19 @@ impl Screenshot {
             frames.push((frame_image, rect));
         }
 
+        // There's a single file to return, so this always stitches
         let (file, path) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
-            let image = combined_image(bounds_opt.unwrap_or_default(), frames);
+            let frames = frames
+                .iter()
+                .map(|(frame, rect)| (frame, *rect))
+                .collect::<Vec<_>>();
+            let image = combined_image(bounds_opt.unwrap_or_default(), &frames);
 
             let mut file = tempfile::Builder::new()
                 .prefix("screenshot-")
-                .suffix(".png")
+                .suffix(&format!(".{}", extension(format)))
                 .tempfile()?;
-            {
-                write_png(&mut file, &image)?;
-            }
+            write_image(io::BufWriter::new(&mut file), &image, format, quality)?;
             Ok(file.keep()?)
         })
         .await??;
@@ -321,52 +476,132 @@ impl Screenshot {
     }
 }
 
-fn combined_image(bounds: Rect, frames: Vec<(RgbaImage, Rect)>) -> RgbaImage {
-    // If we have only one image, crop without scaling
-    if frames.len() == 1 {
-        let (frame_image, rect) = &frames[0];
-
-        // TODO Don't have explicit scale factor; how to ensure pixel perfect scaling?
-        let width_scale = frame_image.width() as f64 / rect.width() as f64;
-        let height_scale = frame_image.height() as f64 / rect.height() as f64;
-
-        let width = (bounds.width() as f64 * width_scale).max(0.) as u32;
-        let height = (bounds.height() as f64 * height_scale).max(0.) as u32;
-        let x = ((bounds.left - rect.left) as f64 * width_scale).max(0.) as u32;
-        let y = ((bounds.top - rect.top) as f64 * height_scale).max(0.) as u32;
-
-        return image::imageops::crop_imm(frame_image, x, y, width, height).to_image();
-    }
-
-    let width = bounds
-        .right
-        .saturating_sub(bounds.left)
-        .try_into()
-        .unwrap_or_default();
-    let height = bounds
-        .bottom
-        .saturating_sub(bounds.top)
-        .try_into()
-        .unwrap_or_default();
-    let mut image = image::RgbaImage::new(width, height);
-    for (mut frame_image, rect) in frames {
-        let width = rect.width() as u32;
-        let height = rect.height() as u32;
-        if frame_image.dimensions() != (width, height) {
-            frame_image = image::imageops::resize(
-                &frame_image,
-                width,
-                height,
-                image::imageops::FilterType::Lanczos3,
-            );
+/// Margin around a window screenshot for its shadow, in pixels
+const SHADOW_MARGIN: u32 = 48;
+const SHADOW_BLUR: f32 = 14.0;
+const SHADOW_OFFSET_Y: i64 = 8;
+const SHADOW_OPACITY: f32 = 0.45;
+
+/// Puts a window screenshot on a transparent canvas with a soft drop shadow
+fn window_shadow(window: &RgbaImage) -> RgbaImage {
+    let (width, height) = (
+        window.width() + 2 * SHADOW_MARGIN,
+        window.height() + 2 * SHADOW_MARGIN,
+    );
+    let margin = i64::from(SHADOW_MARGIN);
+
+    // Black in the shape of the window, blurred
+    let mut shadow = RgbaImage::new(width, height);
+    let silhouette = RgbaImage::from_fn(window.width(), window.height(), |x, y| {
+        let alpha = window.get_pixel(x, y)[3] as f32 * SHADOW_OPACITY;
+        image::Rgba([0, 0, 0, alpha as u8])
+    });
+    image::imageops::overlay(&mut shadow, &silhouette, margin, margin + SHADOW_OFFSET_Y);
+    let mut image = image::imageops::blur(&shadow, SHADOW_BLUR);
+
+    image::imageops::overlay(&mut image, window, margin, margin);
+    image
+}
+
+/// Captures each output, keyed by output name
+async fn capture_output_images(
+    wayland_helper: &WaylandHelper,
+    outputs: impl Iterator<Item = (String, WlOutput)>,
+) -> anyhow::Result<HashMap<String, ScreenshotImage>> {
+    let mut map = HashMap::new();
+    for (name, output) in outputs {
+        let frame = wayland_helper
+            .capture_source_shm(CaptureSource::Output(output), false)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("shm screencopy failed"))?;
+        map.insert(name, ScreenshotImage::new(frame)?);
+    }
+    Ok(map)
+}
+
+/// Pixels per logical pixel of a frame captured from an output at `rect`. Frames are already
+/// transformed, so this also covers rotated outputs and fractional scales.
+fn frame_scale(frame: &RgbaImage, rect: Rect) -> f64 {
+    frame.width() as f64 / rect.width() as f64
+}
+
+/// Pixel range covering the logical range `from..to`, relative to `origin`.
+///
+/// Positions are rounded on a grid anchored at logical 0, so adjacent ranges at the same scale
+/// share their edge, and a crop matches its destination exactly.
+fn pixel_span(from: i32, to: i32, origin: i32, scale: f64) -> (u32, u32) {
+    let pixel = |x: i32| (x as f64 * scale).round() as i64;
+    let origin = pixel(origin);
+    let start = (pixel(from) - origin).max(0) as u32;
+    let end = (pixel(to) - origin).max(0) as u32;
+    (start, end.max(start))
+}
+
+/// Part of an output frame inside `bounds`, at the frame's own scale
+fn crop_frame(frame: &RgbaImage, rect: Rect, bounds: Rect) -> Option<(RgbaImage, Rect)> {
+    let intersect = rect.intersect(bounds)?;
+    let scale = frame_scale(frame, rect);
+    let (x0, x1) = pixel_span(intersect.left, intersect.right, rect.left, scale);
+    let (y0, y1) = pixel_span(intersect.top, intersect.bottom, rect.top, scale);
+    let (x1, y1) = (x1.min(frame.width()), y1.min(frame.height()));
+    let crop = image::imageops::crop_imm(frame, x0, y0, x1 - x0.min(x1), y1 - y0.min(y1));
+    Some((crop.to_image(), intersect))
+}
+
+/// Stitches the parts of `frames` inside `bounds` at the highest scale among them, so no pixels
+/// of the sharpest output are lost. Frames at a lower scale are upscaled.
+fn combined_image(bounds: Rect, frames: &[(&RgbaImage, Rect)]) -> RgbaImage {
+    let scale = frames
+        .iter()
+        .filter(|(_, rect)| rect.intersect(bounds).is_some())
+        .map(|(frame, rect)| frame_scale(frame, *rect))
+        .reduce(f64::max)
+        .unwrap_or(1.0);
+
+    let (_, width) = pixel_span(bounds.left, bounds.right, bounds.left, scale);
+    let (_, height) = pixel_span(bounds.top, bounds.bottom, bounds.top, scale);
+    let mut image = RgbaImage::new(width, height);
+    for (frame, rect) in frames {
+        let Some((crop, intersect)) = crop_frame(frame, *rect, bounds) else {
+            continue;
+        };
+        let (x0, x1) = pixel_span(intersect.left, intersect.right, bounds.left, scale);
+        let (y0, y1) = pixel_span(intersect.top, intersect.bottom, bounds.top, scale);
+        let ratio = scale / frame_scale(frame, *rect);
+        let crop = if ratio == 1.0 {
+            crop
+        } else if ratio.fract() == 0.0 {
+            // Repeat pixels for whole multiples, which keeps them sharp
+            let (width, height) = (x1 - x0, y1 - y0);
+            let (crop_width, crop_height) = crop.dimensions();
+            RgbaImage::from_fn(width, height, |x, y| {
+                let x = (u64::from(x) * u64::from(crop_width) / u64::from(width)) as u32;
+                let y = (u64::from(y) * u64::from(crop_height) / u64::from(height)) as u32;
+                *crop.get_pixel(x, y)
+            })
+        } else {
+            image::imageops::resize(
+                &crop,
+                x1 - x0,
+                y1 - y0,
+                image::imageops::FilterType::Triangle,
+            )
         };
-        let x = i64::from(rect.left) - i64::from(bounds.left);
-        let y = i64::from(rect.top) - i64::from(bounds.top);
-        image::imageops::overlay(&mut image, &frame_image, x, y);
+        image::imageops::replace(&mut image, &crop, i64::from(x0), i64::from(y0));
     }
     image
 }
 
+/// The parts of `frames` inside `bounds`, each at its output's native scale, from the top left
+fn per_output_images(bounds: Rect, frames: &[(&RgbaImage, Rect)]) -> Vec<RgbaImage> {
+    let mut crops = frames
+        .iter()
+        .filter_map(|(frame, rect)| crop_frame(frame, *rect, bounds))
+        .collect::<Vec<_>>();
+    crops.sort_by_key(|(_, rect)| (rect.top, rect.left));
+    crops.into_iter().map(|(crop, _)| crop).collect()
+}
+
 fn write_png<W: io::Write>(w: W, image: &RgbaImage) -> Result<(), png::EncodingError> {
     let mut encoder = png::Encoder::new(w, image.width(), image.height());
     encoder.set_color(png::ColorType::Rgba);
@@ -375,6 +610,71 @@ fn write_png<W: io::Write>(w: W, image: &RgbaImage) -> Result<(), png::EncodingE
     writer.write_image_data(image.as_raw())
 }
 
+/// Speed from 1 to 10; slower speeds barely shrink screenshots further
+const AVIF_SPEED: u8 = 8;
+
+fn write_image<W: io::Write>(
+    w: W,
+    image: &RgbaImage,
+    format: ImageFormat,
+    quality: u8,
+) -> anyhow::Result<()> {
+    let (width, height) = image.dimensions();
+    let rgba = image.as_raw();
+    match format {
+        ImageFormat::Png => write_png(w, image)?,
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel, so transparent parts, like the margin
+            // of a window's shadow, go on white instead of turning black
+            let rgb = image::RgbImage::from_fn(width, height, |x, y| {
+                let [r, g, b, a] = image.get_pixel(x, y).0;
+                let over_white = |c: u8| {
+                    ((u16::from(c) * u16::from(a) + 255 * (255 - u16::from(a))) / 255) as u8
+                };
+                image::Rgb([over_white(r), over_white(g), over_white(b)])
+            });
+            JpegEncoder::new_with_quality(w, quality).write_image(
+                rgb.as_raw(),
+                width,
+                height,
+                ExtendedColorType::Rgb8,
+            )?
+        }
+        ImageFormat::WebP => WebPEncoder::new_lossless(w).write_image(
+            rgba,
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        )?,
+        ImageFormat::Avif => AvifEncoder::new_with_speed_quality(w, AVIF_SPEED, quality)
+            .write_image(rgba, width, height, ExtendedColorType::Rgba8)?,
+        ImageFormat::Qoi => {
+            QoiEncoder::new(w).write_image(rgba, width, height, ExtendedColorType::Rgba8)?
+        }
+    }
+    Ok(())
+}
+
+fn extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpg",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Avif => "avif",
+        ImageFormat::Qoi => "qoi",
+    }
+}
+
+fn mime_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Avif => "image/avif",
+        ImageFormat::Qoi => "image/qoi",
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Msg {
     Capture,
@@ -384,6 +684,47 @@ pub enum Msg {
     OutputChanged(WlOutput),
     WindowChosen(String, usize),
     Location(usize),
+    /// Index into `DELAYS`
+    Delay(usize),
+    /// Whether window screenshots get a drop shadow
+    WindowShadow(bool),
+    Stitching(Stitching),
+    CountdownTick,
+    /// The countdown surface is gone, so the screen can be captured
+    CountdownDone,
+    /// Outputs captured again after a delay, or `None` if that failed
+    Recaptured(Option<HashMap<String, ScreenshotImage>>, Box<Args>),
+    /// Folder chosen to save in after capturing, or `None` if the chooser was cancelled
+    FolderChosen(Option<PathBuf>, Box<Args>),
+    /// Annotate the selection, in an editor on this surface
+    Edit(window::Id),
+    Editor(screenshot_editor::Msg),
+    /// Copy the text in the selection instead of an image
+    CopyText,
+    /// Text recognized in the selection, or `None` if recognition failed
+    TextRecognized(Option<String>, Sender<PortalResponse<ScreenshotResult>>),
+    /// Show recent screenshots on this surface
+    ShowHistory(window::Id),
+    History(screenshot_history::Msg),
+    Nudge(Nudge),
+    ScrollTick,
+    /// The stitcher back from joining a frame, and whether capturing the frame succeeded
+    ScrollFrame(Stitcher, bool),
+    /// Save the frames of the scrolling capture, joined into one image
+    ScrollDone,
+    ScrollCancel,
+}
+
+/// Arrow key press on the rectangle selection, with a direction or distance in logical pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nudge {
+    Move(i32, i32),
+    /// Move the bottom right corner
+    Resize(i32, i32),
+    /// Move until an edge of the selection meets a window or output edge
+    SnapMove(i32, i32),
+    /// Move the bottom right corner to the next window or output edge
+    SnapResize(i32, i32),
 }
 
 #[derive(Debug, Clone)]
@@ -391,6 +732,8 @@ pub enum Choice {
     Output(String),
     Rectangle(Rect, DragState),
     Window(String, Option<usize>),
+    /// Window to capture while it's scrolled
+    Scrolling(String, Option<usize>),
 }
 
 impl From<&Choice> for config::screenshot::Choice {
@@ -398,6 +741,7 @@ impl From<&Choice> for config::screenshot::Choice {
     fn from(value: &Choice) -> Self {
         match value {
             Choice::Window(..) => config::screenshot::Choice::Window,
+            Choice::Scrolling(..) => config::screenshot::Choice::Scrolling,
             Choice::Rectangle(..) => config::screenshot::Choice::Rectangle,
             Choice::Output(output) => config::screenshot::Choice::Output(Some(output.clone())),
         }
@@ -411,7 +755,8 @@ pub enum Action {
     SaveToClipboard,
     SaveToPictures,
     SaveToDocuments,
-    ChooseFolder, // TODO use document portal to choose folder
+    /// Ask for a folder to save in after capturing
+    ChooseFolder,
     Choice(Choice),
 }
 
@@ -426,7 +771,63 @@ pub struct Args {
     pub tx: Sender<PortalResponse<ScreenshotResult>>,
     pub choice: Choice,
     pub location: ImageSaveLocation,
+    /// Last folder chosen in the folder chooser, offered as a save location
+    pub custom_folder: Option<PathBuf>,
+    /// Seconds to wait before capturing, after the selection is made
+    pub delay: u32,
     pub action: Action,
+    pub editor: Option<Editor>,
+    pub history: Option<History>,
+    /// Image joined from the frames of a scrolling capture
+    pub scrolled: Option<RgbaImage>,
+    /// Corner or edge of the rectangle selection last moved with the keyboard
+    pub nudged: DragState,
+    /// Whether the window picked next is annotated instead of captured
+    pub edit_window: bool,
+}
+
+/// Logical pixels an arrow key moves the selection by with Alt held
+const NUDGE_STEP_LARGE: i32 = 10;
+
+/// Logical pixels from a window or output edge within which a dragged selection snaps to it
+const SNAP_DISTANCE: i32 = 8;
+
+/// Seconds offered by the delay dropdown
+const DELAYS: [u32; 4] = [0, 3, 5, 10];
+
+/// Time for the compositor to unmap the countdown before capturing
+const COUNTDOWN_HIDE_DELAY: Duration = Duration::from_millis(100);
+
+const COUNTDOWN_SIZE: u32 = 120;
+
+pub struct Countdown {
+    pub surface: window::Id,
+    remaining: u32,
+    then: AfterCountdown,
+}
+
+pub enum AfterCountdown {
+    /// Wake the D-Bus request, which captures by itself
+    Notify(Sender<()>),
+    /// Capture the outputs again, and save the selection from them
+    Capture(Box<Args>),
+}
+
+/// Time between frames of a scrolling capture
+const SCROLL_INTERVAL: Duration = Duration::from_millis(200);
+
+const SCROLL_PANEL_SIZE: (u32, u32) = (560, 64);
+
+pub struct ScrollCapture {
+    pub surface: window::Id,
+    foreign_toplevel: ExtForeignToplevelHandleV1,
+    /// Taken while a frame is joined to it on a blocking thread
+    stitcher: Option<Stitcher>,
+    /// Frames joined so far
+    frames: usize,
+    /// Whether "Done" was pressed while a frame was being joined
+    done: bool,
+    args: Box<Args>,
 }
 
 struct Output {
@@ -456,36 +857,28 @@ impl Screenshot {
         let config = config::Config::load().0.screenshot;
 
         // TODO create handle, show dialog
-        let mut outputs = Vec::new();
-        for output in self.wayland_helper.outputs() {
-            let Some(info) = self.wayland_helper.output_info(&output) else {
-                log::warn!("Output {:?} has no info", output);
-                continue;
-            };
-            let Some(name) = info.name.clone() else {
-                log::warn!("Output {:?} has no name", output);
-                continue;
-            };
-            let Some(logical_position) = info.logical_position else {
-                log::warn!("Output {:?} has no position", output);
-                continue;
-            };
-            let Some(logical_size) = info.logical_size else {
-                log::warn!("Output {:?} has no size", output);
-                continue;
-            };
-            outputs.push(Output {
-                output,
-                logical_position,
-                logical_size,
-                name,
-            });
-        }
+        let outputs = self.outputs();
         if outputs.is_empty() {
             log::error!("No output");
             return PortalResponse::Other;
         };
 
+        let delay = options.delay.unwrap_or_default();
+        if delay > 0 {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+            if let Err(err) = self
+                .tx
+                .send(subscription::Event::ScreenshotCountdown(delay, tx))
+                .await
+            {
+                log::error!("Failed to send screenshot countdown event, {}", err);
+                return PortalResponse::Other;
+            }
+            if rx.recv().await.is_none() {
+                return PortalResponse::Cancelled;
+            }
+        }
+
         // if interactive, send image to be used by screenshot editor & await response via channel
         if options.interactive.unwrap_or_default() {
             let (tx, mut rx) = tokio::sync::mpsc::channel(1);
@@ -520,6 +913,9 @@ impl Screenshot {
                     Choice::Rectangle(rect, DragState::default())
                 }
                 config::screenshot::Choice::Window => Choice::Window(first_output.into(), None),
+                config::screenshot::Choice::Scrolling => {
+                    Choice::Scrolling(first_output.into(), None)
+                }
             };
             if let Err(err) = self
                 .tx
@@ -536,9 +932,19 @@ impl Screenshot {
                     output_images,
                     toplevel_images,
                     tx,
+                    custom_folder: match &config.save_location {
+                        ImageSaveLocation::Custom(folder) => Some(folder.clone()),
+                        _ => None,
+                    },
                     location: config.save_location,
+                    delay: 0,
                     // TODO cover all outputs at start of rectangle?
                     choice,
+                    editor: None,
+                    history: None,
+                    scrolled: None,
+                    nudged: DragState::None,
+                    edit_window: false,
                     // will be updated
                 }))
                 .await
@@ -553,7 +959,10 @@ impl Screenshot {
             }
         }
 
-        let doc_path = match self.screenshot_inner(&outputs, app_id).await {
+        let doc_path = match self
+            .screenshot_inner(&outputs, app_id, config.format, config.quality())
+            .await
+        {
             Ok(res) => res,
             Err(err) => {
                 log::error!("Failed to capture screenshot: {}", err);
@@ -574,9 +983,42 @@ impl Screenshot {
         parent_window: &str,
         option: HashMap<String, zvariant::Value<'_>>,
     ) -> PortalResponse<PickColorResult> {
-        // TODO create handle
-        // XXX implement
-        PortalResponse::Other
+        let outputs = self.outputs();
+        if outputs.is_empty() {
+            log::error!("No output");
+            return PortalResponse::Other;
+        };
+
+        // Freeze every output, so the color under the cursor is stable while picking
+        let output_images = match self.interactive_output_images(&outputs, app_id).await {
+            Ok(images) => images,
+            Err(err) => {
+                log::error!("Failed to capture outputs for color picker: {}", err);
+                return PortalResponse::Other;
+            }
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        if let Err(err) = self
+            .tx
+            .send(subscription::Event::PickColor(color_picker::Args {
+                handle: handle.to_owned(),
+                app_id: app_id.to_string(),
+                parent_window: parent_window.to_string(),
+                output_images,
+                tx,
+                surfaces: HashMap::new(),
+            }))
+            .await
+        {
+            log::error!("Failed to send pick color event, {}", err);
+            return PortalResponse::Other;
+        }
+        if let Some(res) = rx.recv().await {
+            res
+        } else {
+            PortalResponse::Cancelled::<PickColorResult>
+        }
     }
 
     #[zbus(property, name = "version")]
@@ -585,6 +1027,19 @@ impl Screenshot {
     }
 }
 
+/// Entry of the save location dropdown matching the current choice
+fn location_index(args: &Args, entries: usize) -> usize {
+    if matches!(args.action, Action::ChooseFolder) {
+        return entries.saturating_sub(1);
+    }
+    match args.location {
+        ImageSaveLocation::Clipboard => 0,
+        ImageSaveLocation::Pictures => 1,
+        ImageSaveLocation::Documents => 2,
+        ImageSaveLocation::Custom(_) => 3,
+    }
+}
+
 pub(crate) fn view(portal: &CosmicPortal, id: window::Id) -> cosmic::Element<'_, Msg> {
     let Some((i, output)) = portal.outputs.iter().enumerate().find(|(i, o)| o.id == id) else {
         return space::horizontal().width(Length::Fixed(1.0)).into();
@@ -597,21 +1052,99 @@ pub(crate) fn view(portal: &CosmicPortal, id: window::Id) -> cosmic::Element<'_,
         return space::horizontal().width(Length::Fixed(1.0)).into();
     };
     let theme = portal.core.system_theme().cosmic();
+
+    if let Some(history) = args.history.as_ref() {
+        if history.surface != id {
+            return cosmic::widget::image::Image::new(img.handle.clone())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into();
+        }
+        return KeyboardWrapper::new(
+            screenshot_history::view(history, theme.spacing).map(Msg::History),
+            |key, _| match key {
+                Key::Named(Named::Escape) => Some(Msg::History(screenshot_history::Msg::Back)),
+                _ => None,
+            },
+        )
+        .into();
+    }
+
+    if let Some(editor) = args.editor.as_ref() {
+        if editor.surface != id {
+            return cosmic::widget::image::Image::new(img.handle.clone())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into();
+        }
+        return KeyboardWrapper::new(
+            screenshot_editor::view(editor, theme.spacing).map(Msg::Editor),
+            |key, modifiers| {
+                if modifiers.control()
+                    && let Key::Character(ref value) = key
+                {
+                    let value = value.as_str();
+                    if value.eq_ignore_ascii_case("z") && modifiers.shift()
+                        || value.eq_ignore_ascii_case("y")
+                    {
+                        return Some(Msg::Editor(screenshot_editor::Msg::Redo));
+                    } else if value.eq_ignore_ascii_case("z") {
+                        return Some(Msg::Editor(screenshot_editor::Msg::Undo));
+                    } else if value.eq_ignore_ascii_case("c") {
+                        return Some(Msg::CaptureWithLocation(ImageSaveLocation::Clipboard));
+                    } else if value.eq_ignore_ascii_case("s") {
+                        return Some(Msg::CaptureWithLocation(ImageSaveLocation::Pictures));
+                    }
+                }
+
+                match key {
+                    Key::Named(Named::Enter) => Some(Msg::Editor(screenshot_editor::Msg::Done)),
+                    Key::Named(Named::Escape) => Some(Msg::Editor(screenshot_editor::Msg::Back)),
+                    _ => None,
+                }
+            },
+        )
+        .into();
+    }
+
     KeyboardWrapper::new(
         crate::widget::screenshot::ScreenshotSelection::new(
             args.choice.clone(),
             img,
             Msg::Capture,
+            Msg::Edit(id),
+            args.edit_window,
+            Msg::CopyText,
+            // The history is empty unless it's turned on
+            (portal.config.screenshot.history_count() > 0).then_some(Msg::ShowHistory(id)),
             Msg::Cancel,
+            args.nudged,
+            snap_edges(args, &portal.outputs),
             output,
             id,
             Msg::OutputChanged,
             Msg::Choice,
             &args.toplevel_images,
             Msg::WindowChosen,
+            portal.config.screenshot.window_shadow,
+            Msg::WindowShadow,
+            portal.config.screenshot.stitching == Stitching::PerOutput,
+            |per_output| {
+                Msg::Stitching(if per_output {
+                    Stitching::PerOutput
+                } else {
+                    Stitching::MaxScale
+                })
+            },
             &portal.location_options,
-            args.location as usize,
+            location_index(args, portal.location_options.len()),
             Msg::Location,
+            &portal.delay_options,
+            DELAYS
+                .iter()
+                .position(|d| *d == args.delay)
+                .unwrap_or_default(),
+            Msg::Delay,
             theme.spacing,
             i as u128,
         ),
@@ -636,6 +1169,23 @@ pub(crate) fn view(portal: &CosmicPortal, id: window::Id) -> cosmic::Element<'_,
                 }
             }
 
+            let direction = match key {
+                Key::Named(Named::ArrowLeft) => Some((-1, 0)),
+                Key::Named(Named::ArrowRight) => Some((1, 0)),
+                Key::Named(Named::ArrowUp) => Some((0, -1)),
+                Key::Named(Named::ArrowDown) => Some((0, 1)),
+                _ => None,
+            };
+            if let Some((x, y)) = direction {
+                let step = if modifiers.alt() { NUDGE_STEP_LARGE } else { 1 };
+                return Some(Msg::Nudge(match (modifiers.control(), modifiers.shift()) {
+                    (false, false) => Nudge::Move(x * step, y * step),
+                    (false, true) => Nudge::Resize(x * step, y * step),
+                    (true, false) => Nudge::SnapMove(x, y),
+                    (true, true) => Nudge::SnapResize(x, y),
+                }));
+            }
+
             match key {
                 Key::Named(Named::Enter) => Some(Msg::Capture),
                 Key::Named(Named::Escape) => Some(Msg::Cancel),
@@ -646,140 +1196,691 @@ pub(crate) fn view(portal: &CosmicPortal, id: window::Id) -> cosmic::Element<'_,
     .into()
 }
 
-pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::app::Msg> {
-    match msg {
-        Msg::Capture => {
-            let mut cmds: Vec<cosmic::Task<crate::app::Msg>> = portal
-                .outputs
-                .iter()
-                .map(|o| destroy_layer_surface(o.id))
-                .collect();
-            let Some(args) = portal.screenshot_args.take() else {
-                log::error!("Failed to find screenshot Args for Capture message.");
-                return cosmic::Task::batch(cmds);
-            };
-            let outputs = portal.outputs.clone();
-            let Args {
-                tx,
-                choice,
-                output_images: mut images,
-                location,
-                ..
-            } = args;
+fn output_rect(output: &OutputState) -> Rect {
+    let pos = output.logical_pos;
+    Rect {
+        left: pos.0,
+        top: pos.1,
+        right: pos.0 + output.logical_size.0 as i32,
+        bottom: pos.1 + output.logical_size.1 as i32,
+    }
+}
 
-            let mut success = true;
-            let image_path = Screenshot::get_img_path(location);
+/// Output images, with the logical rect of each output
+fn output_frames<'a>(args: &'a Args, outputs: &[OutputState]) -> Vec<(&'a RgbaImage, Rect)> {
+    args.output_images
+        .iter()
+        .filter_map(|(name, raw_img)| {
+            let output = outputs.iter().find(|o| &o.name == name)?;
+            Some((&raw_img.rgba, output_rect(output)))
+        })
+        .collect()
+}
 
-            match choice {
-                Choice::Output(name) => {
-                    if let Some(img) = images.remove(&name) {
-                        if let Ok(buffer) = Screenshot::save_rgba(&img.rgba, image_path.as_deref())
-                            .inspect_err(|err| {
-                                log::error!("Failed to capture screenshot: {:?}", err);
-                                success = false;
-                            })
-                        {
-                            cmds.push(clipboard::write_data(ScreenshotBytes::new(buffer)));
-                        }
-                    } else {
-                        log::error!("Failed to find output {}", name);
-                        success = false;
-                    }
-                }
-                Choice::Rectangle(r, s) => {
-                    if let Some(RectDimension { width, height }) = r.dimensions() {
-                        // Construct Rgba image with size of rect
-                        // then overlay the part of each image that intersects with the rect
-                        //let mut img = RgbaImage::new(width.get(), height.get());
-
-                        let frames = images
-                            .into_iter()
-                            .filter_map(|(name, raw_img)| {
-                                let output = outputs.iter().find(|o| o.name == name)?;
-                                let pos = output.logical_pos;
-                                let output_rect = Rect {
-                                    left: pos.0,
-                                    top: pos.1,
-                                    right: pos.0 + output.logical_size.0 as i32,
-                                    bottom: pos.1 + output.logical_size.1 as i32,
-                                };
-
-                                let intersect = r.intersect(output_rect)?;
-
-                                Some((raw_img.rgba, output_rect))
-                            })
-                            .collect::<Vec<_>>();
-                        let img = combined_image(r, frames);
+/// Smallest rectangle containing every output
+fn outputs_bounds(outputs: &[OutputState]) -> Option<Rect> {
+    outputs.iter().map(output_rect).reduce(|a, b| Rect {
+        left: a.left.min(b.left),
+        top: a.top.min(b.top),
+        right: a.right.max(b.right),
+        bottom: a.bottom.max(b.bottom),
+    })
+}
 
-                        if let Ok(buffer) = Screenshot::save_rgba(&img, image_path.as_deref())
-                            .inspect_err(|err| {
-                                log::error!("Failed to capture screenshot: {:?}", err);
-                                success = false;
-                            })
-                        {
-                            cmds.push(clipboard::write_data(ScreenshotBytes::new(buffer)));
-                        }
-                    } else {
-                        success = false;
-                    }
-                }
-                Choice::Window(output, Some(window_i)) => {
-                    if let Some(img) = args
-                        .toplevel_images
-                        .get(&output)
-                        .and_then(|imgs| imgs.get(window_i))
-                    {
-                        if let Ok(buffer) = Screenshot::save_rgba(&img.rgba, image_path.as_deref())
-                            .inspect_err(|err| {
-                                log::error!("Failed to capture screenshot: {:?}", err);
-                                success = false;
-                            })
-                        {
-                            cmds.push(clipboard::write_data(ScreenshotBytes::new(buffer)));
-                        }
-                    } else {
-                        success = false;
-                    }
-                }
-                _ => {
-                    success = false;
-                }
+/// Sorted x and y coordinates of the window and output edges a selection snaps to, from
+/// `ToplevelInfo::geometry` and the output rects
+fn snap_edges(args: &Args, outputs: &[OutputState]) -> (Vec<i32>, Vec<i32>) {
+    let windows = args
+        .toplevel_images
+        .values()
+        .flatten()
+        .filter_map(|img| img.window.as_ref()?.geometry);
+    let (mut xs, mut ys) = (Vec::new(), Vec::new());
+    for rect in outputs.iter().map(output_rect).chain(windows) {
+        xs.extend([rect.left, rect.right]);
+        ys.extend([rect.top, rect.bottom]);
+    }
+    for edges in [&mut xs, &mut ys] {
+        edges.sort_unstable();
+        edges.dedup();
+    }
+    (xs, ys)
+}
+
+/// The nearest of the sorted `edges` within `SNAP_DISTANCE` of `value`, or `value`
+pub fn snap(value: i32, edges: &[i32]) -> i32 {
+    let i = edges.partition_point(|edge| *edge < value);
+    edges[i.saturating_sub(1)..edges.len().min(i + 1)]
+        .iter()
+        .copied()
+        .filter(|edge| (edge - value).abs() <= SNAP_DISTANCE)
+        .min_by_key(|edge| (edge - value).abs())
+        .unwrap_or(value)
+}
+
+/// Bounds of the window under a point, clipped to the outputs. Of overlapping windows the
+/// smallest is picked, as dialogs and popups are usually above the windows they're over.
+fn window_at(args: &Args, outputs: &[OutputState], x: i32, y: i32) -> Option<Rect> {
+    let point = Rect {
+        left: x,
+        top: y,
+        right: x + 1,
+        bottom: y + 1,
+    };
+    let window = args
+        .toplevel_images
+        .values()
+        .flatten()
+        .filter_map(|img| img.window.as_ref()?.geometry)
+        .filter(|geometry| geometry.intersect(point).is_some())
+        .min_by_key(|geometry| i64::from(geometry.width()) * i64::from(geometry.height()))?;
+    window.intersect(outputs_bounds(outputs)?)
+}
+
+/// Shortest distance from any of `from` to an edge, in the direction of the sign of
+/// `direction`, or 0 if there's none
+fn snap_distance(from: &[i32], edges: &[i32], direction: i32) -> i32 {
+    from.iter()
+        .flat_map(|from| edges.iter().map(move |edge| edge - from))
+        .filter(|distance| *distance != 0 && distance.signum() == direction.signum())
+        .min_by_key(|distance| distance.abs())
+        .unwrap_or(0)
+}
+
+/// Applies an arrow key press to the rectangle selection, keeping it inside `bounds`
+fn nudge_rect(rect: Rect, nudge: Nudge, bounds: Rect, xs: &[i32], ys: &[i32]) -> Rect {
+    let r = rect.normalized();
+    match nudge {
+        Nudge::Move(dx, dy) => r.translate(
+            dx.max(bounds.left - r.left).min(bounds.right - r.right),
+            dy.max(bounds.top - r.top).min(bounds.bottom - r.bottom),
+        ),
+        Nudge::Resize(dx, dy) => Rect {
+            right: (r.right + dx).min(bounds.right).max(r.left + 1),
+            bottom: (r.bottom + dy).min(bounds.bottom).max(r.top + 1),
+            ..r
+        },
+        Nudge::SnapMove(x, y) => {
+            let dx = snap_distance(&[r.left, r.right], xs, x);
+            let dy = snap_distance(&[r.top, r.bottom], ys, y);
+            nudge_rect(r, Nudge::Move(dx, dy), bounds, xs, ys)
+        }
+        Nudge::SnapResize(x, y) => {
+            // Edges at or before the top left corner would empty the selection
+            let xs: Vec<_> = xs.iter().copied().filter(|x| *x > r.left).collect();
+            let ys: Vec<_> = ys.iter().copied().filter(|y| *y > r.top).collect();
+            let dx = snap_distance(&[r.right], &xs, x);
+            let dy = snap_distance(&[r.bottom], &ys, y);
+            nudge_rect(r, Nudge::Resize(dx, dy), bounds, &xs, &ys)
+        }
+    }
+}
+
+/// Images to save for the selection. A rectangle over several outputs gives one image per
+/// output with `Stitching::PerOutput`, unless it only goes to the clipboard.
+fn selected_images(args: &Args, outputs: &[OutputState], stitching: Stitching) -> Vec<RgbaImage> {
+    match (&args.choice, stitching) {
+        (Choice::Rectangle(r, _), Stitching::PerOutput)
+            if r.dimensions().is_some() && args.location != ImageSaveLocation::Clipboard =>
+        {
+            per_output_images(*r, &output_frames(args, outputs))
+        }
+        _ => selected_image(args, outputs).into_iter().collect(),
+    }
+}
+
+/// The part of the screenshot selected by `args.choice`
+fn selected_image(args: &Args, outputs: &[OutputState]) -> Option<RgbaImage> {
+    match &args.choice {
+        Choice::Output(name) => {
+            let img = args.output_images.get(name);
+            if img.is_none() {
+                log::error!("Failed to find output {}", name);
             }
+            Some(img?.rgba.clone())
+        }
+        Choice::Rectangle(r, _) => {
+            r.dimensions()?;
+            Some(combined_image(*r, &output_frames(args, outputs)))
+        }
+        Choice::Window(output, Some(window_i)) => args
+            .toplevel_images
+            .get(output)
+            .and_then(|imgs| imgs.get(*window_i))
+            .map(|img| img.rgba.clone()),
+        Choice::Scrolling(..) => args.scrolled.clone(),
+        _ => None,
+    }
+}
 
-            let response = if success && let Some(image_path) = image_path {
-                PortalResponse::Success(ScreenshotResult {
-                    uri: format!("file:///{}", image_path.display()),
-                })
-            } else if success && image_path.is_none() {
-                PortalResponse::Success(ScreenshotResult {
-                    uri: "clipboard:///".to_string(),
-                })
-            } else {
-                PortalResponse::Other
-            };
+/// Expands the `{output}`, `{title}` and `{app_id}` placeholders and `strftime` specifiers of
+/// the file name template, for the output and window the selection was taken from
+fn file_name(template: &str, args: &Args, outputs: &[OutputState]) -> String {
+    let (output, window) = match &args.choice {
+        Choice::Output(name) => (name.as_str(), None),
+        Choice::Rectangle(r, _) => (
+            outputs
+                .iter()
+                .find(|o| r.intersect(output_rect(o)).is_some())
+                .map_or("", |o| o.name.as_str()),
+            None,
+        ),
+        Choice::Window(output, window_i) | Choice::Scrolling(output, window_i) => (
+            output.as_str(),
+            window_i
+                .and_then(|i| args.toplevel_images.get(output)?.get(i))
+                .and_then(|img| img.window.as_ref()),
+        ),
+    };
 
-            tokio::spawn(async move {
-                if let Err(err) = tx.send(response).await {
-                    log::error!("Failed to send screenshot event");
+    // Keep values literal, and inside the screenshot directory
+    let value = |value: &str| {
+        value
+            .chars()
+            .take(64)
+            .collect::<String>()
+            .replace('%', "%%")
+            .replace('/', "_")
+    };
+    let template = template
+        .replace("{output}", &value(output))
+        .replace("{title}", &value(window.map_or("", |w| w.title.as_str())))
+        .replace("{app_id}", &value(window.map_or("", |w| w.app_id.as_str())));
+
+    let now = jiff::Zoned::now();
+    match jiff::fmt::strtime::format(&template, &now) {
+        Ok(name) if !name.is_empty() => name.replace('/', "_"),
+        result => {
+            if let Err(err) = result {
+                log::error!(
+                    "Invalid screenshot file name template {:?}: {}",
+                    template,
+                    err
+                );
+            }
+            now.strftime(config::screenshot::DEFAULT_FILENAME_TEMPLATE)
+                .to_string()
+        }
+    }
+}
+
+/// Saves the selection and sends the portal response. The layer surfaces must already be gone.
+fn finish_capture(portal: &CosmicPortal, mut args: Args) -> cosmic::Task<crate::app::Msg> {
+    let config = &portal.config.screenshot;
+    let mut images = match args.editor.take() {
+        Some(editor) => vec![editor.into_image()],
+        None => selected_images(&args, &portal.outputs, config.stitching),
+    };
+    if config.window_shadow && matches!(args.choice, Choice::Window(..)) {
+        images = images.iter().map(window_shadow).collect();
+    }
+    save_images(portal, args, images)
+}
+
+/// Saves `images` to the chosen location, adds them to the history and sends the portal response
+fn save_images(
+    portal: &CosmicPortal,
+    args: Args,
+    images: Vec<RgbaImage>,
+) -> cosmic::Task<crate::app::Msg> {
+    let mut cmds = Vec::new();
+    let config = &portal.config.screenshot;
+    let mut success = !images.is_empty();
+    let (format, quality) = (config.format, config.quality());
+    let file_name = file_name(config.filename_template(), &args, &portal.outputs);
+
+    let mut paths = Vec::new();
+    for img in &images {
+        let Some(path) = Screenshot::get_img_path(&args.location, &file_name, extension(format))
+        else {
+            break;
+        };
+        if let Err(err) = Screenshot::save_image(img, &path, format, quality) {
+            log::error!("Failed to capture screenshot: {:?}", err);
+            success = false;
+        }
+        paths.push(path);
+    }
+    // The response has room for one URI, so it names the first image. Apps
+    // that want the whole selection get it with `Stitching::MaxScale`.
+    let image_path = paths.first().cloned();
+
+    if success {
+        let entries = if paths.is_empty() {
+            images
+                .iter()
+                .take(1)
+                .map(|img| (img.clone(), None))
+                .collect()
+        } else {
+            images
+                .iter()
+                .cloned()
+                .zip(paths.into_iter().map(Some))
+                .collect()
+        };
+        let (count, size) = (config.history_count(), config.history_size());
+        tokio::task::spawn_blocking(move || screenshot_history::record(entries, count, size));
+    }
+
+    // The clipboard holds one image, so a selection split per output is copied whole
+    let clipboard_image = if images.len() > 1 {
+        selected_image(&args, &portal.outputs)
+    } else {
+        images.into_iter().next()
+    };
+    if success && let Some(img) = clipboard_image {
+        cmds.push(clipboard::write_data(ScreenshotClipboard::new(
+            img, format, quality,
+        )));
+    }
+
+    let response = if success && let Some(image_path) = image_path {
+        PortalResponse::Success(ScreenshotResult {
+            uri: format!("file:///{}", image_path.display()),
+        })
+    } else if success && image_path.is_none() {
+        PortalResponse::Success(ScreenshotResult {
+            uri: "clipboard:///".to_string(),
+        })
+    } else {
+        PortalResponse::Other
+    };
+
+    let tx = args.tx;
+    tokio::spawn(async move {
+        if let Err(err) = tx.send(response).await {
+            log::error!("Failed to send screenshot event");
+        }
+    });
+    cosmic::Task::batch(cmds)
+}
+
+/// Saves the selection, first asking for a folder if the user chose to
+/// Saves the rectangle selection if it was last moved with the keyboard, as
+/// nudging doesn't save it on each key press.
+fn save_nudged_rectangle(portal: &CosmicPortal, args: &Args) -> cosmic::Task<crate::app::Msg> {
+    let Choice::Rectangle(r, _) = &args.choice else {
+        return cosmic::Task::none();
+    };
+    if args.nudged == DragState::None {
+        return cosmic::Task::none();
+    }
+    cosmic::task::message(crate::app::Msg::ConfigSetScreenshot(
+        config::screenshot::Screenshot {
+            choice: (&args.choice).into(),
+            last_rectangle: Some(config::screenshot::Rect {
+                left: r.left,
+                top: r.top,
+                right: r.right,
+                bottom: r.bottom,
+            }),
+            ..portal.config.screenshot.clone()
+        },
+    ))
+}
+
+fn save_selection(portal: &CosmicPortal, args: Args) -> cosmic::Task<crate::app::Msg> {
+    if matches!(args.action, Action::ChooseFolder) {
+        choose_folder(portal, args)
+    } else {
+        finish_capture(portal, args)
+    }
+}
+
+/// Shows a countdown on the active output, then continues with `then` once it's hidden
+pub fn countdown(
+    portal: &mut CosmicPortal,
+    seconds: u32,
+    then: AfterCountdown,
+) -> cosmic::Task<crate::app::Msg> {
+    if portal.screenshot_countdown.is_some() {
+        // Dropping `then` cancels the request
+        log::error!("A screenshot countdown is already running");
+        return cosmic::Task::none();
+    }
+
+    // Use the output the selection was made on, when there is one
+    let output = match (&then, &portal.active_output) {
+        (AfterCountdown::Capture(_), Some(output)) => IcedOutput::Output(output.clone()),
+        _ => IcedOutput::Active,
+    };
+    let surface = window::Id::unique();
+    portal.screenshot_countdown = Some(Countdown {
+        surface,
+        remaining: seconds,
+        then,
+    });
+
+    cosmic::Task::batch([
+        get_layer_surface(SctkLayerSurfaceSettings {
+            id: surface,
+            layer: Layer::Overlay,
+            // Don't take focus from menus and tooltips that should be captured
+            keyboard_interactivity: KeyboardInteractivity::None,
+            input_zone: Some(Vec::new()),
+            anchor: Anchor::empty(),
+            output,
+            namespace: "screenshot-countdown".to_string(),
+            size: Some((Some(COUNTDOWN_SIZE), Some(COUNTDOWN_SIZE))),
+            ..Default::default()
+        }),
+        countdown_tick(),
+    ])
+}
+
+fn countdown_tick() -> cosmic::Task<crate::app::Msg> {
+    cosmic::task::future(async {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        crate::app::Msg::Screenshot(Msg::CountdownTick)
+    })
+}
+
+pub(crate) fn countdown_view(portal: &CosmicPortal) -> cosmic::Element<'_, Msg> {
+    let remaining = portal
+        .screenshot_countdown
+        .as_ref()
+        .map_or(0, |countdown| countdown.remaining);
+    cosmic::widget::container(cosmic::widget::text::title1(remaining.to_string()))
+        .center(Length::Fill)
+        .class(cosmic::theme::Container::Custom(Box::new(|theme| {
+            let theme = theme.cosmic();
+            cosmic::iced::widget::container::Style {
+                background: Some(Background::Color(theme.background.component.base.into())),
+                text_color: Some(theme.background.component.on.into()),
+                border: Border {
+                    radius: theme.corner_radii.radius_xl.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        })))
+        .into()
+}
+
+/// Hides the selection, and captures the chosen window as it's scrolled until the capture is
+/// done or cancelled
+fn start_scrolling(portal: &mut CosmicPortal) -> cosmic::Task<crate::app::Msg> {
+    let mut cmds: Vec<cosmic::Task<crate::app::Msg>> = portal
+        .outputs
+        .iter()
+        .map(|o| destroy_layer_surface(o.id))
+        .collect();
+    let Some(args) = portal.screenshot_args.take() else {
+        log::error!("Failed to find screenshot Args for scrolling capture.");
+        return cosmic::Task::batch(cmds);
+    };
+    let window = match &args.choice {
+        Choice::Scrolling(output, Some(window_i)) => args
+            .toplevel_images
+            .get(output)
+            .and_then(|imgs| imgs.get(*window_i))
+            .and_then(|img| Some((img.window.as_ref()?, &img.rgba))),
+        _ => None,
+    };
+    let Some((foreign_toplevel, first)) =
+        window.map(|(window, rgba)| (window.foreign_toplevel.clone(), rgba.clone()))
+    else {
+        log::error!("Failed to find the window to scroll");
+        tokio::spawn(async move {
+            if let Err(err) = args.tx.send(PortalResponse::Other).await {
+                log::error!("Failed to send screenshot event");
+            }
+        });
+        return cosmic::Task::batch(cmds);
+    };
+
+    let output = match &portal.active_output {
+        Some(output) => IcedOutput::Output(output.clone()),
+        None => IcedOutput::Active,
+    };
+    let surface = window::Id::unique();
+    portal.screenshot_scroll = Some(ScrollCapture {
+        surface,
+        foreign_toplevel,
+        stitcher: Some(Stitcher::new(first)),
+        frames: 1,
+        done: false,
+        args: Box::new(args),
+    });
+    cmds.push(get_layer_surface(SctkLayerSurfaceSettings {
+        id: surface,
+        layer: Layer::Overlay,
+        // Leave the keyboard to the window being scrolled, until the panel is clicked
+        keyboard_interactivity: KeyboardInteractivity::OnDemand,
+        anchor: Anchor::TOP,
+        output,
+        namespace: "screenshot-scrolling".to_string(),
+        size: Some((Some(SCROLL_PANEL_SIZE.0), Some(SCROLL_PANEL_SIZE.1))),
+        ..Default::default()
+    }));
+    cmds.push(scroll_tick());
+    cosmic::Task::batch(cmds)
+}
+
+fn scroll_tick() -> cosmic::Task<crate::app::Msg> {
+    cosmic::task::future(async {
+        tokio::time::sleep(SCROLL_INTERVAL).await;
+        crate::app::Msg::Screenshot(Msg::ScrollTick)
+    })
+}
+
+pub(crate) fn scroll_view(portal: &CosmicPortal) -> cosmic::Element<'_, Msg> {
+    let frames = portal
+        .screenshot_scroll
+        .as_ref()
+        .map_or(0, |scroll| scroll.frames);
+    let spacing = portal.core.system_theme().cosmic().spacing;
+    let panel = cosmic::widget::row::with_children(vec![
+        cosmic::widget::text::body(fl!("scrolling", "hint"))
+            .width(Length::Fill)
+            .into(),
+        cosmic::widget::text::caption(fl!("scrolling", "frames", frames = frames)).into(),
+        cosmic::widget::button::custom(cosmic::widget::text(fl!("scrolling", "done")))
+            .on_press(Msg::ScrollDone)
+            .into(),
+        cosmic::widget::button::custom(
+            cosmic::widget::icon::Icon::from(
+                cosmic::widget::icon::from_name("window-close-symbolic").size(16),
+            )
+            .width(Length::Fixed(16.0))
+            .height(Length::Fixed(16.0)),
+        )
+        .class(cosmic::theme::Button::Icon)
+        .on_press(Msg::ScrollCancel)
+        .into(),
+    ])
+    .align_y(cosmic::iced::Alignment::Center)
+    .spacing(spacing.space_s)
+    .padding([spacing.space_xxs, spacing.space_s]);
+
+    KeyboardWrapper::new(
+        cosmic::widget::container(panel).center(Length::Fill).class(
+            cosmic::theme::Container::Custom(Box::new(|theme| {
+                let theme = theme.cosmic();
+                cosmic::iced::widget::container::Style {
+                    background: Some(Background::Color(theme.background.component.base.into())),
+                    text_color: Some(theme.background.component.on.into()),
+                    border: Border {
+                        radius: theme.corner_radii.radius_s.into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
                 }
+            })),
+        ),
+        |key, _| match key {
+            Key::Named(Named::Enter) => Some(Msg::ScrollDone),
+            Key::Named(Named::Escape) => Some(Msg::ScrollCancel),
+            _ => None,
+        },
+    )
+    .into()
+}
+
+/// Opens a folder chooser, and resumes the capture with the chosen folder
+fn choose_folder(portal: &CosmicPortal, args: Args) -> cosmic::Task<crate::app::Msg> {
+    let current_folder = match &portal.config.screenshot.save_location {
+        ImageSaveLocation::Custom(path) => Some(path.clone()),
+        _ => dirs::picture_dir(),
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let chooser = file_chooser::Args {
+        handle: args.handle.clone(),
+        app_id: args.app_id.clone(),
+        parent_window: args.parent_window.clone(),
+        title: fl!("choose-folder"),
+        options: FileChooserOptions::OpenFile(OpenFileOptions::folder(
+            fl!("save"),
+            current_folder.as_deref(),
+        )),
+        tx,
+    };
+
+    cosmic::Task::batch([
+        cosmic::task::message(crate::app::Msg::Portal(subscription::Event::FileChooser(
+            chooser,
+        ))),
+        cosmic::task::future(async move {
+            let folder = match rx.recv().await {
+                Some(PortalResponse::Success(result)) => result.paths().into_iter().next(),
+                _ => None,
+            };
+            crate::app::Msg::Screenshot(Msg::FolderChosen(folder, Box::new(args)))
+        }),
+    ])
+}
+
+pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::app::Msg> {
+    match msg {
+        Msg::Capture => {
+            let mut cmds: Vec<cosmic::Task<crate::app::Msg>> = portal
+                .outputs
+                .iter()
+                .map(|o| destroy_layer_surface(o.id))
+                .collect();
+            let Some(args) = portal.screenshot_args.take() else {
+                log::error!("Failed to find screenshot Args for Capture message.");
+                return cosmic::Task::batch(cmds);
+            };
+            cmds.push(save_nudged_rectangle(portal, &args));
+            // The annotated image and window captures can't be taken again
+            let delay = if args.editor.is_none()
+                && !matches!(args.choice, Choice::Window(..) | Choice::Scrolling(..))
+            {
+                args.delay
+            } else {
+                0
+            };
+            cmds.push(if delay > 0 {
+                countdown(portal, delay, AfterCountdown::Capture(Box::new(args)))
+            } else {
+                save_selection(portal, args)
             });
             cosmic::Task::batch(cmds)
         }
+        Msg::Delay(i) => {
+            if let Some(args) = portal.screenshot_args.as_mut() {
+                args.delay = DELAYS.get(i).copied().unwrap_or_default();
+            } else {
+                log::error!("Failed to find screenshot Args for Delay message.");
+            }
+            cosmic::Task::none()
+        }
+        Msg::CountdownTick => {
+            let Some(countdown) = portal.screenshot_countdown.as_mut() else {
+                return cosmic::Task::none();
+            };
+            countdown.remaining = countdown.remaining.saturating_sub(1);
+            if countdown.remaining > 0 {
+                return countdown_tick();
+            }
+            cosmic::Task::batch([
+                destroy_layer_surface(countdown.surface),
+                cosmic::task::future(async {
+                    tokio::time::sleep(COUNTDOWN_HIDE_DELAY).await;
+                    crate::app::Msg::Screenshot(Msg::CountdownDone)
+                }),
+            ])
+        }
+        Msg::CountdownDone => {
+            let Some(countdown) = portal.screenshot_countdown.take() else {
+                log::error!("Failed to find screenshot countdown for CountdownDone message.");
+                return cosmic::Task::none();
+            };
+            match countdown.then {
+                AfterCountdown::Notify(tx) => {
+                    tokio::spawn(async move {
+                        if let Err(err) = tx.send(()).await {
+                            log::error!("Failed to send screenshot countdown event");
+                        }
+                    });
+                    cosmic::Task::none()
+                }
+                AfterCountdown::Capture(args) => {
+                    let wayland_helper = portal.wayland_helper.clone();
+                    let outputs = portal
+                        .outputs
+                        .iter()
+                        .map(|o| (o.name.clone(), o.output.clone()))
+                        .collect::<Vec<_>>();
+                    cosmic::task::future(async move {
+                        let images = capture_output_images(&wayland_helper, outputs.into_iter())
+                            .await
+                            .inspect_err(|err| {
+                                log::error!("Failed to capture outputs after delay: {:?}", err)
+                            })
+                            .ok();
+                        crate::app::Msg::Screenshot(Msg::Recaptured(images, args))
+                    })
+                }
+            }
+        }
+        Msg::Recaptured(images, mut args) => {
+            // Fall back to the images the selection was made on
+            if let Some(images) = images {
+                args.output_images = images;
+            }
+            save_selection(portal, *args)
+        }
         Msg::CaptureWithLocation(location) => {
             if let Some(args) = portal.screenshot_args.as_mut() {
                 args.location = location;
+                if matches!(args.action, Action::ChooseFolder) {
+                    args.action = Action::ReturnPath;
+                }
             } else {
                 log::error!("Failed to find screenshot Args for CaptureWithLocation message.");
                 return cosmic::Task::none();
             }
             update_msg(portal, Msg::Capture)
         }
+        Msg::FolderChosen(folder, mut args) => {
+            let Some(folder) = folder else {
+                tokio::spawn(async move {
+                    if let Err(err) = args.tx.send(PortalResponse::Cancelled).await {
+                        log::error!("Failed to send screenshot event");
+                    }
+                });
+                return cosmic::Task::none();
+            };
+            let location = ImageSaveLocation::Custom(folder);
+            args.location = location.clone();
+            let save_config = cosmic::task::message(crate::app::Msg::ConfigSetScreenshot(
+                config::screenshot::Screenshot {
+                    save_location: location,
+                    ..portal.config.screenshot.clone()
+                },
+            ));
+            cosmic::Task::batch([save_config, finish_capture(portal, *args)])
+        }
         Msg::Cancel => {
             let cmds = portal.outputs.iter().map(|o| destroy_layer_surface(o.id));
             let Some(args) = portal.screenshot_args.take() else {
                 log::error!("Failed to find screenshot Args for Cancel message.");
                 return cosmic::Task::batch(cmds);
             };
+            let save_config = save_nudged_rectangle(portal, &args);
             let Args { tx, .. } = args;
             tokio::spawn(async move {
                 if let Err(err) = tx.send(PortalResponse::Cancelled).await {
@@ -787,9 +1888,21 @@ pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::ap
                 }
             });
 
-            cosmic::Task::batch(cmds)
+            cosmic::Task::batch(cmds.chain([save_config]))
         }
-        Msg::Choice(c) => {
+        Msg::Choice(mut c) => {
+            // Clicking without dragging selects the window under the pointer
+            if let (Some(args), Choice::Rectangle(r, DragState::None)) =
+                (portal.screenshot_args.as_ref(), &c)
+                && let Choice::Rectangle(prev, prev_state) = &args.choice
+                && *prev_state != DragState::None
+                && prev == r
+                && r.width() == 1
+                && r.height() == 1
+                && let Some(window) = window_at(args, &portal.outputs, r.left, r.top)
+            {
+                c = Choice::Rectangle(window, DragState::None);
+            }
             let choice = (&c).into();
             // Only save config when drag is finished to avoid disk writes on every mouse motion
             let should_save_config =
@@ -808,6 +1921,8 @@ pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::ap
 
             if let Some(args) = portal.screenshot_args.as_mut() {
                 args.choice = c;
+                args.nudged = DragState::None;
+                args.edit_window = false;
             } else {
                 log::error!("Failed to find screenshot Args for Choice message.");
             }
@@ -816,7 +1931,7 @@ pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::ap
                     config::screenshot::Screenshot {
                         choice,
                         last_rectangle: last_rect,
-                        ..portal.config.screenshot
+                        ..portal.config.screenshot.clone()
                     },
                 ))
             } else {
@@ -844,54 +1959,396 @@ pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::ap
         }
         Msg::WindowChosen(name, i) => {
             if let Some(args) = portal.screenshot_args.as_mut() {
-                args.choice = Choice::Window(name, Some(i));
+                if let Choice::Scrolling(..) = args.choice {
+                    args.choice = Choice::Scrolling(name, Some(i));
+                    return start_scrolling(portal);
+                }
+                args.choice = Choice::Window(name.clone(), Some(i));
+                if args.edit_window
+                    && let Some(output) = portal.outputs.iter().find(|o| o.name == name)
+                {
+                    let surface = output.id;
+                    return update_msg(portal, Msg::Edit(surface));
+                }
             } else {
                 log::error!("Failed to find screenshot Args for WindowChosen message.");
             }
             update_msg(portal, Msg::Capture)
         }
-        Msg::Location(loc) => {
+        Msg::Location(i) => {
+            let Some(args) = portal.screenshot_args.as_mut() else {
+                log::error!("Failed to find screenshot Args for Location message.");
+                return cosmic::Task::none();
+            };
+            // The last entry asks for a folder when capturing
+            if i + 1 == portal.location_options.len() {
+                args.action = Action::ChooseFolder;
+                return cosmic::Task::none();
+            }
+            if matches!(args.action, Action::ChooseFolder) {
+                args.action = Action::ReturnPath;
+            }
+            let loc = match (i, &args.custom_folder) {
+                (0, _) => ImageSaveLocation::Clipboard,
+                (1, _) => ImageSaveLocation::Pictures,
+                (2, _) => ImageSaveLocation::Documents,
+                (_, Some(folder)) => ImageSaveLocation::Custom(folder.clone()),
+                _ => args.location.clone(),
+            };
+            args.location = loc.clone();
+            cosmic::task::message(crate::app::Msg::ConfigSetScreenshot(
+                config::screenshot::Screenshot {
+                    save_location: loc,
+                    choice: (&mut portal.config.screenshot.choice).into(),
+                    ..portal.config.screenshot.clone()
+                },
+            ))
+        }
+        Msg::Stitching(stitching) => cosmic::task::message(crate::app::Msg::ConfigSetScreenshot(
+            config::screenshot::Screenshot {
+                stitching,
+                ..portal.config.screenshot.clone()
+            },
+        )),
+        Msg::WindowShadow(window_shadow) => cosmic::task::message(
+            crate::app::Msg::ConfigSetScreenshot(config::screenshot::Screenshot {
+                window_shadow,
+                ..portal.config.screenshot.clone()
+            }),
+        ),
+        Msg::CopyText => {
+            let mut cmds: Vec<cosmic::Task<crate::app::Msg>> = portal
+                .outputs
+                .iter()
+                .map(|o| destroy_layer_surface(o.id))
+                .collect();
+            let Some(mut args) = portal.screenshot_args.take() else {
+                log::error!("Failed to find screenshot Args for CopyText message.");
+                return cosmic::Task::batch(cmds);
+            };
+            let image = match args.editor.take() {
+                Some(editor) => Some(editor.into_image()),
+                None => selected_image(&args, &portal.outputs),
+            };
+            let language = portal.config.screenshot.ocr_language().to_string();
+            let tx = args.tx;
+            cmds.push(cosmic::task::future(async move {
+                let text = match image {
+                    Some(image) => ocr::recognize_text(image, &language)
+                        .await
+                        .inspect_err(|err| log::error!("Failed to recognize text: {:?}", err))
+                        .ok(),
+                    None => None,
+                };
+                crate::app::Msg::Screenshot(Msg::TextRecognized(text, tx))
+            }));
+            cosmic::Task::batch(cmds)
+        }
+        Msg::TextRecognized(text, tx) => {
+            let (response, cmd) = match text {
+                Some(text) if !text.is_empty() => (
+                    PortalResponse::Success(ScreenshotResult {
+                        uri: "clipboard:///".to_string(),
+                    }),
+                    clipboard::write(text),
+                ),
+                _ => {
+                    log::warn!("No text recognized in screenshot");
+                    (PortalResponse::Other, cosmic::Task::none())
+                }
+            };
+            tokio::spawn(async move {
+                if let Err(err) = tx.send(response).await {
+                    log::error!("Failed to send screenshot event");
+                }
+            });
+            cmd
+        }
+        Msg::Edit(surface) => {
+            let Some(args) = portal.screenshot_args.as_mut() else {
+                log::error!("Failed to find screenshot Args for Edit message.");
+                return cosmic::Task::none();
+            };
+            // Windows are picked after choosing to annotate, as picking one captures it
+            if let Choice::Window(_, None) = args.choice {
+                args.edit_window = !args.edit_window;
+                return cosmic::Task::none();
+            }
+            if let Some(image) = selected_image(args, &portal.outputs) {
+                args.editor = Some(Editor::new(surface, image));
+            }
+            cosmic::Task::none()
+        }
+        Msg::Nudge(nudge) => {
+            let Some(args) = portal.screenshot_args.as_ref() else {
+                log::error!("Failed to find screenshot Args for Nudge message.");
+                return cosmic::Task::none();
+            };
+            let (Choice::Rectangle(rect, _), Some(bounds)) =
+                (&args.choice, outputs_bounds(&portal.outputs))
+            else {
+                return cosmic::Task::none();
+            };
+            let rect = if rect.dimensions().is_some() {
+                let (xs, ys) = snap_edges(args, &portal.outputs);
+                nudge_rect(*rect, nudge, bounds, &xs, &ys)
+            } else {
+                // Start with the middle of the active output, without a pointer
+                let output = portal
+                    .outputs
+                    .iter()
+                    .find(|o| portal.active_output.as_ref() == Some(&o.output))
+                    .unwrap_or(&portal.outputs[0]);
+                let r = output_rect(output);
+                Rect {
+                    left: r.left + r.width() / 4,
+                    top: r.top + r.height() / 4,
+                    right: r.right - r.width() / 4,
+                    bottom: r.bottom - r.height() / 4,
+                }
+            };
+            // The config is saved once the selection is captured or cancelled,
+            // rather than on every key press
+            portal.prev_rectangle = Some(rect);
             if let Some(args) = portal.screenshot_args.as_mut() {
-                let loc = match loc {
-                    loc if loc == ImageSaveLocation::Clipboard as usize => {
-                        ImageSaveLocation::Clipboard
+                args.choice = Choice::Rectangle(rect, DragState::None);
+                args.nudged = match nudge {
+                    Nudge::Move(..) | Nudge::SnapMove(..) => DragState::NW,
+                    Nudge::Resize(..) | Nudge::SnapResize(..) => DragState::SE,
+                };
+                args.edit_window = false;
+            }
+            cosmic::Task::none()
+        }
+        Msg::ScrollTick => {
+            let Some(scroll) = portal.screenshot_scroll.as_mut() else {
+                return cosmic::Task::none();
+            };
+            let Some(mut stitcher) = scroll.stitcher.take() else {
+                return cosmic::Task::none();
+            };
+            let wayland_helper = portal.wayland_helper.clone();
+            let source = CaptureSource::Toplevel(scroll.foreign_toplevel.clone());
+            cosmic::task::future(async move {
+                let frame = match wayland_helper.capture_source_shm(source, false).await {
+                    Some(img) => toplevel_rgba(img)
+                        .inspect_err(|err| log::error!("Failed to read window frame: {:?}", err))
+                        .ok(),
+                    None => None,
+                };
+                let Some(frame) = frame else {
+                    return crate::app::Msg::Screenshot(Msg::ScrollFrame(stitcher, false));
+                };
+                // Hashing the rows and searching for the overlap is too slow for the UI thread
+                match tokio::task::spawn_blocking(move || {
+                    stitcher.push(&frame);
+                    stitcher
+                })
+                .await
+                {
+                    Ok(stitcher) => crate::app::Msg::Screenshot(Msg::ScrollFrame(stitcher, true)),
+                    Err(err) => {
+                        log::error!("Failed to join scrolling capture frame: {}", err);
+                        crate::app::Msg::Screenshot(Msg::ScrollCancel)
                     }
-                    loc if loc == ImageSaveLocation::Pictures as usize => {
-                        ImageSaveLocation::Pictures
+                }
+            })
+        }
+        Msg::ScrollFrame(stitcher, captured) => {
+            // Cancelled while capturing
+            let Some(scroll) = portal.screenshot_scroll.as_mut() else {
+                return cosmic::Task::none();
+            };
+            if !captured {
+                log::warn!("Failed to capture window for scrolling capture");
+            }
+            scroll.frames = stitcher.frames();
+            scroll.stitcher = Some(stitcher);
+            if scroll.done {
+                return update_msg(portal, Msg::ScrollDone);
+            }
+            scroll_tick()
+        }
+        Msg::ScrollDone => {
+            // Finish once the frame being joined is back
+            if let Some(scroll) = portal.screenshot_scroll.as_mut()
+                && scroll.stitcher.is_none()
+            {
+                scroll.done = true;
+                return cosmic::Task::none();
+            }
+            let Some(ScrollCapture {
+                surface,
+                stitcher: Some(stitcher),
+                mut args,
+                ..
+            }) = portal.screenshot_scroll.take()
+            else {
+                log::error!("Failed to find scrolling capture for ScrollDone message.");
+                return cosmic::Task::none();
+            };
+            args.scrolled = Some(stitcher.into_image());
+            cosmic::Task::batch([
+                destroy_layer_surface(surface),
+                save_selection(portal, *args),
+            ])
+        }
+        Msg::ScrollCancel => {
+            let Some(scroll) = portal.screenshot_scroll.take() else {
+                log::error!("Failed to find scrolling capture for ScrollCancel message.");
+                return cosmic::Task::none();
+            };
+            let tx = scroll.args.tx;
+            tokio::spawn(async move {
+                if let Err(err) = tx.send(PortalResponse::Cancelled).await {
+                    log::error!("Failed to send screenshot event");
+                }
+            });
+            destroy_layer_surface(scroll.surface)
+        }
+        Msg::ShowHistory(surface) => {
+            if let Some(args) = portal.screenshot_args.as_mut() {
+                args.history = Some(History::new(surface));
+            } else {
+                log::error!("Failed to find screenshot Args for ShowHistory message.");
+            }
+            cosmic::Task::none()
+        }
+        Msg::History(msg) => update_history(portal, msg),
+        Msg::Editor(screenshot_editor::Msg::Done) => update_msg(portal, Msg::Capture),
+        Msg::Editor(msg) => {
+            let Some(args) = portal.screenshot_args.as_mut() else {
+                log::error!("Failed to find screenshot Args for Editor message.");
+                return cosmic::Task::none();
+            };
+            match msg {
+                screenshot_editor::Msg::Back => {
+                    args.editor = None;
+                    // Go back to picking a window
+                    if let Choice::Window(output, Some(_)) = &args.choice {
+                        args.choice = Choice::Window(output.clone(), None);
+                        args.edit_window = false;
                     }
-                    loc if loc == ImageSaveLocation::Documents as usize => {
-                        ImageSaveLocation::Documents
+                }
+                msg => {
+                    if let Some(editor) = args.editor.as_mut() {
+                        screenshot_editor::update(editor, msg);
                     }
-                    _ => args.location,
-                };
-                args.location = loc;
-                cosmic::task::message(crate::app::Msg::ConfigSetScreenshot(
-                    config::screenshot::Screenshot {
-                        save_location: loc,
-                        choice: (&mut portal.config.screenshot.choice).into(),
-                        last_rectangle: portal.config.screenshot.last_rectangle,
-                    },
-                ))
-            } else {
-                log::error!("Failed to find screenshot Args for Location message.");
-                cosmic::Task::none()
+                }
             }
+            cosmic::Task::none()
         }
     }
 }
 
+fn update_history(
+    portal: &mut CosmicPortal,
+    msg: screenshot_history::Msg,
+) -> cosmic::Task<crate::app::Msg> {
+    let Some(args) = portal.screenshot_args.as_mut() else {
+        log::error!("Failed to find screenshot Args for History message.");
+        return cosmic::Task::none();
+    };
+    let Some(history) = args.history.as_mut() else {
+        log::error!("Failed to find screenshot history for History message.");
+        return cosmic::Task::none();
+    };
+    let i = match msg {
+        screenshot_history::Msg::Back => {
+            args.history = None;
+            return cosmic::Task::none();
+        }
+        screenshot_history::Msg::Remove(i) => {
+            if i < history.entries.len() {
+                let entry = history.entries.remove(i);
+                if let Err(err) = screenshot_history::remove(&entry) {
+                    log::error!("Failed to remove screenshot from history: {:?}", err);
+                }
+            }
+            return cosmic::Task::none();
+        }
+        screenshot_history::Msg::Copy(i)
+        | screenshot_history::Msg::Save(i)
+        | screenshot_history::Msg::ShowInFolder(i)
+        | screenshot_history::Msg::Annotate(i) => i,
+    };
+    let Some(entry) = history.entries.get(i).cloned() else {
+        log::error!("Failed to find screenshot history entry {}", i);
+        return cosmic::Task::none();
+    };
+
+    if let screenshot_history::Msg::ShowInFolder(_) = msg {
+        let Some(path) = entry.path else {
+            return cosmic::Task::none();
+        };
+        // The file manager would open under the screenshot surfaces, so close them
+        let cmds = portal.outputs.iter().map(|o| destroy_layer_surface(o.id));
+        if let Some(args) = portal.screenshot_args.take() {
+            tokio::spawn(async move {
+                if let Err(err) = screenshot_history::show_in_folder(&path).await {
+                    log::error!("Failed to show {:?} in folder: {:?}", path, err);
+                }
+                if let Err(err) = args.tx.send(PortalResponse::Cancelled).await {
+                    log::error!("Failed to send screenshot event");
+                }
+            });
+        }
+        return cosmic::Task::batch(cmds);
+    }
+
+    let image = match entry.load_image() {
+        Ok(image) => image,
+        Err(err) => {
+            log::error!("Failed to load screenshot from history: {:?}", err);
+            return cosmic::Task::none();
+        }
+    };
+    if let screenshot_history::Msg::Annotate(_) = msg {
+        args.editor = Some(Editor::new(history.surface, image));
+        args.history = None;
+        return cosmic::Task::none();
+    }
+
+    let mut cmds: Vec<cosmic::Task<crate::app::Msg>> = portal
+        .outputs
+        .iter()
+        .map(|o| destroy_layer_surface(o.id))
+        .collect();
+    let Some(mut args) = portal.screenshot_args.take() else {
+        return cosmic::Task::batch(cmds);
+    };
+    if let screenshot_history::Msg::Save(_) = msg {
+        // Saving to the clipboard again is what copying is for
+        if args.location == ImageSaveLocation::Clipboard {
+            args.location = ImageSaveLocation::Pictures;
+        }
+        cmds.push(save_images(portal, args, vec![image]));
+        return cosmic::Task::batch(cmds);
+    }
+
+    let config = &portal.config.screenshot;
+    cmds.push(clipboard::write_data(ScreenshotClipboard::new(
+        image,
+        config.format,
+        config.quality(),
+    )));
+    let tx = args.tx;
+    tokio::spawn(async move {
+        let response = PortalResponse::Success(ScreenshotResult {
+            uri: "clipboard:///".to_string(),
+        });
+        if let Err(err) = tx.send(response).await {
+            log::error!("Failed to send screenshot event");
+        }
+    });
+    cosmic::Task::batch(cmds)
+}
+
 pub fn update_args(portal: &mut CosmicPortal, args: Args) -> cosmic::Task<crate::app::Msg> {
     let Args {
-        handle,
-        app_id,
-        parent_window,
-        options,
         output_images: images,
-        tx,
-        choice,
-        action,
-        location,
-        toplevel_images,
+        custom_folder,
+        ..
     } = &args;
 
     if portal.outputs.len() != images.len() {
@@ -938,6 +2395,20 @@ pub fn update_args(portal: &mut CosmicPortal, args: Args) -> cosmic::Task<crate:
         fl!("save-to", "pictures"),
         fl!("save-to", "documents"),
     ];
+    if let Some(folder) = custom_folder {
+        let folder = folder.file_name().unwrap_or(folder.as_os_str());
+        portal
+            .location_options
+            .push(fl!("save-to", "folder", folder = folder.to_string_lossy()));
+    }
+    portal.location_options.push(fl!("choose-folder"));
+    portal.delay_options = DELAYS
+        .iter()
+        .map(|&seconds| match seconds {
+            0 => fl!("delay", "none"),
+            seconds => fl!("delay", "seconds", seconds = seconds),
+        })
+        .collect();
 
     if portal.screenshot_args.replace(args).is_none() {
         // iterate over outputs and create a layer surface for each
@@ -970,3 +2441,200 @@ pub fn update_args(portal: &mut CosmicPortal, args: Args) -> cosmic::Task<crate:
         cosmic::Task::none()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frame whose pixels encode their own position and the output they came from
+    fn frame(width: u32, height: u32, output: u8) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8, y as u8, output, 255])
+        })
+    }
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+        Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn stitches_at_max_scale() {
+        let low = frame(100, 100, 1);
+        let high = frame(200, 200, 2);
+        let frames = [
+            (&low, rect(0, 0, 100, 100)),
+            (&high, rect(100, 0, 200, 100)),
+        ];
+
+        let image = combined_image(rect(0, 0, 200, 100), &frames);
+
+        assert_eq!(image.dimensions(), (400, 200));
+        // The 2x output is copied as is
+        for (x, y) in [(0, 0), (57, 133), (199, 199)] {
+            assert_eq!(image.get_pixel(200 + x, y), high.get_pixel(x, y));
+        }
+        // Each pixel of the 1x output becomes a 2x2 block
+        for (x, y) in [(0, 0), (31, 77), (99, 99)] {
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                assert_eq!(image.get_pixel(2 * x + dx, 2 * y + dy), low.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn stitches_fractional_scales_without_gaps() {
+        let fractional = frame(150, 150, 1);
+        let low = frame(100, 100, 2);
+        let frames = [
+            (&fractional, rect(0, 0, 100, 100)),
+            (&low, rect(100, 0, 200, 100)),
+        ];
+
+        let image = combined_image(rect(0, 0, 200, 100), &frames);
+
+        assert_eq!(image.dimensions(), (300, 150));
+        assert_eq!(image.get_pixel(149, 149), fractional.get_pixel(149, 149));
+        assert!(image.pixels().all(|pixel| pixel[3] == 255));
+        assert!(image.enumerate_pixels().all(|(x, _, pixel)| {
+            let output = if x < 150 { 1 } else { 2 };
+            pixel[2] == output
+        }));
+    }
+
+    #[test]
+    fn crops_one_output_at_native_scale() {
+        let fractional = frame(150, 150, 1);
+        let frames = [(&fractional, rect(0, 0, 100, 100))];
+
+        let image = combined_image(rect(11, 11, 21, 21), &frames);
+
+        // 11 and 21 logical pixels round to 17 and 32 at 1.5x
+        assert_eq!(image.dimensions(), (15, 15));
+        assert_eq!(image.get_pixel(0, 0), fractional.get_pixel(17, 17));
+        assert_eq!(image.get_pixel(14, 14), fractional.get_pixel(31, 31));
+    }
+
+    #[test]
+    fn stitches_rotated_output() {
+        // A portrait output, rotated to logical orientation when captured
+        let portrait = frame(200, 400, 1);
+        let landscape = frame(200, 100, 2);
+        let frames = [
+            (&portrait, rect(0, 0, 100, 200)),
+            (&landscape, rect(100, 0, 300, 100)),
+        ];
+
+        let image = combined_image(rect(0, 0, 300, 200), &frames);
+
+        assert_eq!(image.dimensions(), (600, 400));
+        assert_eq!(image.get_pixel(199, 399), portrait.get_pixel(199, 399));
+        assert_eq!(image.get_pixel(201, 1), landscape.get_pixel(0, 0));
+        // Nothing is below the landscape output
+        assert_eq!(image.get_pixel(599, 399)[3], 0);
+    }
+
+    #[test]
+    fn splits_per_output_at_native_scales() {
+        let low = frame(100, 100, 1);
+        let high = frame(200, 200, 2);
+        // Listed out of order, as they come from a `HashMap`
+        let frames = [
+            (&high, rect(100, 0, 200, 100)),
+            (&low, rect(0, 0, 100, 100)),
+        ];
+
+        let images = per_output_images(rect(50, 0, 150, 100), &frames);
+
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].dimensions(), (50, 100));
+        assert_eq!(images[0].get_pixel(0, 0), low.get_pixel(50, 0));
+        assert_eq!(images[1].dimensions(), (100, 200));
+        assert_eq!(images[1].get_pixel(99, 199), high.get_pixel(99, 199));
+    }
+
+    #[test]
+    fn skips_outputs_outside_selection() {
+        let low = frame(100, 100, 1);
+        let high = frame(200, 200, 2);
+        let frames = [
+            (&low, rect(0, 0, 100, 100)),
+            (&high, rect(100, 0, 200, 100)),
+        ];
+
+        // Only the 1x output is selected, so nothing is upscaled
+        let image = combined_image(rect(10, 10, 60, 60), &frames);
+        assert_eq!(image.dimensions(), (50, 50));
+        assert_eq!(image.get_pixel(0, 0), low.get_pixel(10, 10));
+
+        assert_eq!(per_output_images(rect(10, 10, 60, 60), &frames).len(), 1);
+    }
+
+    #[test]
+    fn nudges_stay_inside_outputs() {
+        let bounds = rect(0, 0, 1920, 1080);
+        let selection = rect(10, 10, 110, 60);
+
+        assert_eq!(
+            nudge_rect(selection, Nudge::Move(-1, 10), bounds, &[], &[]),
+            rect(9, 20, 109, 70)
+        );
+        assert_eq!(
+            nudge_rect(selection, Nudge::Move(-20, -20), bounds, &[], &[]),
+            rect(0, 0, 100, 50)
+        );
+        assert_eq!(
+            nudge_rect(selection, Nudge::Resize(10, -100), bounds, &[], &[]),
+            rect(10, 10, 120, 11)
+        );
+        // Selections dragged up and to the left are flipped first
+        assert_eq!(
+            nudge_rect(rect(110, 60, 10, 10), Nudge::Move(1, 0), bounds, &[], &[]),
+            rect(11, 10, 111, 60)
+        );
+    }
+
+    #[test]
+    fn snaps_to_nearest_edge() {
+        let bounds = rect(0, 0, 1920, 1080);
+        let xs = [0, 200, 250, 1920];
+        let ys = [0, 300, 1080];
+        let selection = rect(10, 10, 110, 60);
+
+        // The right edge reaches 200 before the left edge reaches 200
+        assert_eq!(
+            nudge_rect(selection, Nudge::SnapMove(1, 0), bounds, &xs, &ys),
+            rect(100, 10, 200, 60)
+        );
+        assert_eq!(
+            nudge_rect(selection, Nudge::SnapMove(0, -1), bounds, &xs, &ys),
+            rect(10, 0, 110, 50)
+        );
+        assert_eq!(
+            nudge_rect(selection, Nudge::SnapResize(1, 1), bounds, &xs, &ys),
+            rect(10, 10, 200, 300)
+        );
+        // Nothing to the left of the right edge but the left edge itself
+        assert_eq!(
+            nudge_rect(selection, Nudge::SnapResize(-1, 0), bounds, &xs, &ys),
+            selection
+        );
+    }
+
+    #[test]
+    fn snaps_within_distance() {
+        let edges = [0, 100, 105, 400];
+
+        assert_eq!(snap(3, &edges), 0);
+        assert_eq!(snap(-5, &edges), 0);
+        assert_eq!(snap(102, &edges), 100);
+        assert_eq!(snap(103, &edges), 105);
+        assert_eq!(snap(200, &edges), 200);
+        assert_eq!(snap(409, &edges), 409);
+        assert_eq!(snap(7, &[]), 7);
+    }
+}
@@ -0,0 +1,264 @@
+//! Editor for annotating the selected part of a screenshot before it's saved
+
+use cosmic::Element;
+use cosmic::cosmic_theme::Spacing;
+use cosmic::iced::core::{Alignment, Background, Border, Color, Length};
+use cosmic::iced::window;
+use cosmic::widget::{self, button, divider, icon, row, space, text};
+use image::RgbaImage;
+
+use crate::annotation::{self, COLORS, History, Region, Shape, Tool};
+use crate::fl;
+use crate::widget::annotation_canvas::{AnnotationCanvas, CanvasEvent};
+
+#[derive(Clone, Debug)]
+pub struct Editor {
+    /// Surface the editor is shown on. Other outputs keep showing their screenshot.
+    pub surface: window::Id,
+    image: RgbaImage,
+    /// `image` with every shape in `history` drawn on it
+    annotated: RgbaImage,
+    preview: widget::image::Handle,
+    history: History,
+    /// Shape being drawn, until the pointer is released
+    drawing: Option<Shape>,
+    /// `drawing` rendered on the part of `annotated` it covers, shown over the preview
+    overlay: Option<(widget::image::Handle, Region)>,
+    tool: Tool,
+    color: [u8; 3],
+    text: String,
+}
+
+impl Editor {
+    pub fn new(surface: window::Id, image: RgbaImage) -> Self {
+        Self {
+            surface,
+            preview: handle(image.clone()),
+            annotated: image.clone(),
+            image,
+            history: History::default(),
+            drawing: None,
+            overlay: None,
+            tool: Tool::default(),
+            color: COLORS[0],
+            text: String::new(),
+        }
+    }
+
+    /// The selection with the annotations drawn on it
+    pub fn into_image(self) -> RgbaImage {
+        self.annotated
+    }
+
+    fn redraw(&mut self) {
+        self.annotated = self.image.clone();
+        annotation::render(&mut self.annotated, self.history.shapes());
+        self.update_preview();
+    }
+
+    fn update_preview(&mut self) {
+        self.preview = handle(self.annotated.clone());
+    }
+
+    /// Renders the shape being drawn on only the part of the image it covers,
+    /// so dragging doesn't redraw the whole image.
+    fn update_overlay(&mut self) {
+        self.overlay = self.drawing.as_ref().and_then(|shape| {
+            let (width, height) = self.annotated.dimensions();
+            let region = shape.region(width, height)?;
+            let mut part = image::imageops::crop_imm(
+                &self.annotated,
+                region.x,
+                region.y,
+                region.width,
+                region.height,
+            )
+            .to_image();
+            let shape = shape.translated((-(region.x as f32), -(region.y as f32)));
+            annotation::render(&mut part, &[shape]);
+            Some((handle(part), region))
+        });
+    }
+}
+
+fn handle(image: RgbaImage) -> widget::image::Handle {
+    widget::image::Handle::from_rgba(image.width(), image.height(), image.into_vec())
+}
+
+#[derive(Clone, Debug)]
+pub enum Msg {
+    Canvas(CanvasEvent),
+    Tool(Tool),
+    Color([u8; 3]),
+    Text(String),
+    Undo,
+    Redo,
+    /// Save the annotated selection
+    Done,
+    /// Return to the selection without saving
+    Back,
+}
+
+/// Handles every message except `Done` and `Back`, which close the editor.
+pub fn update(editor: &mut Editor, msg: Msg) {
+    match msg {
+        Msg::Canvas(CanvasEvent::Press(point, scale)) => {
+            editor.drawing = Shape::new(editor.tool, point, editor.color, &editor.text, scale);
+            editor.update_overlay();
+        }
+        Msg::Canvas(CanvasEvent::Drag(point)) => {
+            if let Some(shape) = editor.drawing.as_mut() {
+                shape.drag_to(point);
+                editor.update_overlay();
+            }
+        }
+        Msg::Canvas(CanvasEvent::Release) => {
+            if let Some(shape) = editor.drawing.take() {
+                annotation::render(&mut editor.annotated, std::slice::from_ref(&shape));
+                editor.history.push(shape);
+                editor.update_overlay();
+                editor.update_preview();
+            }
+        }
+        Msg::Tool(tool) => editor.tool = tool,
+        Msg::Color(color) => editor.color = color,
+        Msg::Text(text) => editor.text = text,
+        Msg::Undo => {
+            if editor.history.undo() {
+                editor.redraw();
+            }
+        }
+        Msg::Redo => {
+            if editor.history.redo() {
+                editor.redraw();
+            }
+        }
+        Msg::Done | Msg::Back => {}
+    }
+}
+
+fn tool_label(tool: Tool) -> String {
+    match tool {
+        Tool::Pen => fl!("annotate", "pen"),
+        Tool::Arrow => fl!("annotate", "arrow"),
+        Tool::Rectangle => fl!("annotate", "rectangle"),
+        Tool::Text => fl!("annotate", "text"),
+        Tool::Highlight => fl!("annotate", "highlight"),
+        Tool::Pixelate => fl!("annotate", "pixelate"),
+    }
+}
+
+fn icon_button<'a>(name: &'static str, on_press: Option<Msg>) -> Element<'a, Msg> {
+    button::custom(
+        icon::Icon::from(icon::from_name(name).size(16))
+            .width(Length::Fixed(16.0))
+            .height(Length::Fixed(16.0)),
+    )
+    .class(cosmic::theme::Button::Icon)
+    .on_press_maybe(on_press)
+    .into()
+}
+
+pub(crate) fn view(editor: &Editor, spacing: Spacing) -> Element<'_, Msg> {
+    let canvas = AnnotationCanvas::new(
+        editor.preview.clone(),
+        editor.image.dimensions(),
+        editor.overlay.clone(),
+        Msg::Canvas,
+    );
+
+    let tools = Tool::ALL.into_iter().map(|tool| {
+        Element::from(
+            button::custom(text(tool_label(tool)))
+                .selected(editor.tool == tool)
+                .class(cosmic::theme::Button::Text)
+                .on_press(Msg::Tool(tool)),
+        )
+    });
+
+    let colors = COLORS.into_iter().map(|color| {
+        let [r, g, b] = color;
+        let swatch = widget::container(
+            space::horizontal()
+                .width(Length::Fixed(20.0))
+                .height(Length::Fixed(20.0)),
+        )
+        .class(cosmic::theme::Container::Custom(Box::new(move |_| {
+            widget::container::Style {
+                background: Some(Background::Color(Color::from_rgb8(r, g, b))),
+                border: Border {
+                    radius: 10.0.into(),
+                    width: 1.0,
+                    color: Color::from_rgba(0.5, 0.5, 0.5, 0.5),
+                },
+                ..Default::default()
+            }
+        })));
+        Element::from(
+            button::custom(swatch)
+                .selected(editor.color == color)
+                .class(cosmic::theme::Button::Icon)
+                .on_press(Msg::Color(color))
+                .padding(spacing.space_xxs),
+        )
+    });
+
+    let mut toolbar = row::with_children(tools)
+        .push(divider::vertical::light().height(Length::Fixed(32.0)))
+        .extend(colors);
+    if editor.tool == Tool::Text {
+        toolbar = toolbar.push(
+            widget::text_input(fl!("annotate", "label"), &editor.text)
+                .on_input(Msg::Text)
+                .width(Length::Fixed(160.0)),
+        );
+    }
+    let toolbar = toolbar
+        .push(divider::vertical::light().height(Length::Fixed(32.0)))
+        .push(icon_button(
+            "edit-undo-symbolic",
+            editor.history.can_undo().then_some(Msg::Undo),
+        ))
+        .push(icon_button(
+            "edit-redo-symbolic",
+            editor.history.can_redo().then_some(Msg::Redo),
+        ))
+        .push(divider::vertical::light().height(Length::Fixed(32.0)))
+        .push(button::custom(text(fl!("capture"))).on_press(Msg::Done))
+        .push(icon_button("window-close-symbolic", Some(Msg::Back)))
+        .align_y(Alignment::Center)
+        .spacing(spacing.space_xs)
+        .padding([spacing.space_xxs, spacing.space_s]);
+
+    let toolbar =
+        widget::container(toolbar).class(cosmic::theme::Container::Custom(Box::new(|theme| {
+            let theme = theme.cosmic();
+            widget::container::Style {
+                background: Some(Background::Color(theme.background.component.base.into())),
+                text_color: Some(theme.background.component.on.into()),
+                border: Border {
+                    radius: theme.corner_radii.radius_s.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        })));
+
+    widget::container(
+        widget::column::with_children(vec![
+            canvas.into(),
+            widget::container(toolbar).center_x(Length::Fill).into(),
+        ])
+        .spacing(spacing.space_s)
+        .padding(spacing.space_l),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .class(cosmic::theme::Container::Custom(Box::new(|theme| {
+        widget::container::Style {
+            background: Some(Background::Color(theme.cosmic().background.base.into())),
+            ..Default::default()
+        }
+    })))
+    .into()
+}
@@ -0,0 +1,338 @@
+//! Recent screenshots, kept in the cache as a copy of the image and a thumbnail, with the path
+//! they were saved to
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use cosmic::Element;
+use cosmic::cosmic_theme::Spacing;
+use cosmic::iced::core::{Alignment, Background, Border, Length};
+use cosmic::iced::widget::{column, row};
+use cosmic::iced::window;
+use cosmic::widget::{self, button, divider, icon, space, text};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::fl;
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Serializes changes to the index, which captures record from blocking tasks
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    /// Name of the entry's files in the history directory
+    id: String,
+    /// Where the screenshot was saved, or `None` if it was only copied to the clipboard
+    pub path: Option<PathBuf>,
+    /// Seconds since the Unix epoch
+    created: i64,
+}
+
+impl Entry {
+    /// Copy of the screenshot as PNG, which stays readable if the saved file is moved
+    pub fn image_path(&self) -> Option<PathBuf> {
+        Some(dir()?.join(format!("{}.png", self.id)))
+    }
+
+    fn thumbnail_path(&self) -> Option<PathBuf> {
+        Some(dir()?.join(format!("{}.thumbnail.png", self.id)))
+    }
+
+    pub fn load_image(&self) -> anyhow::Result<RgbaImage> {
+        let path = self
+            .image_path()
+            .ok_or_else(|| anyhow::anyhow!("no cache directory"))?;
+        Ok(image::open(path)?.into_rgba8())
+    }
+
+    fn size(&self) -> u64 {
+        [self.image_path(), self.thumbnail_path()]
+            .into_iter()
+            .flatten()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    fn remove_files(&self) {
+        for path in [self.image_path(), self.thumbnail_path()]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(err) = fs::remove_file(&path)
+                && err.kind() != std::io::ErrorKind::NotFound
+            {
+                log::error!(
+                    "Failed to remove {:?} from screenshot history: {}",
+                    path,
+                    err
+                );
+            }
+        }
+    }
+}
+
+fn dir() -> Option<PathBuf> {
+    Some(
+        dirs::cache_dir()?
+            .join("xdg-desktop-portal-cosmic")
+            .join("screenshots"),
+    )
+}
+
+fn index_path() -> Option<PathBuf> {
+    Some(dir()?.join("history.ron"))
+}
+
+/// Entries from newest to oldest
+fn read_index() -> Vec<Entry> {
+    let Some(path) = index_path() else {
+        return Vec::new();
+    };
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            log::error!("Failed to read screenshot history {:?}: {}", path, err);
+            return Vec::new();
+        }
+    };
+    ron::from_str(&data).unwrap_or_else(|err| {
+        log::error!("Failed to parse screenshot history {:?}: {}", path, err);
+        Vec::new()
+    })
+}
+
+fn write_index(entries: &[Entry]) -> anyhow::Result<()> {
+    let path = index_path().ok_or_else(|| anyhow::anyhow!("no cache directory"))?;
+    fs::write(path, ron::to_string(entries)?)?;
+    Ok(())
+}
+
+/// Adds screenshots to the history, with the paths they were saved to, then drops the oldest
+/// entries beyond `count` or `size` bytes. This encodes images, so it should run off the UI
+/// thread.
+pub fn record(images: Vec<(RgbaImage, Option<PathBuf>)>, count: u32, size: u64) {
+    if count == 0 {
+        return;
+    }
+    if let Err(err) = record_inner(images, count as usize, size) {
+        log::error!("Failed to add screenshot to history: {:?}", err);
+    }
+}
+
+fn record_inner(
+    images: Vec<(RgbaImage, Option<PathBuf>)>,
+    count: usize,
+    size: u64,
+) -> anyhow::Result<()> {
+    let dir = dir().ok_or_else(|| anyhow::anyhow!("no cache directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let now = jiff::Timestamp::now();
+    let mut new_entries = Vec::with_capacity(images.len());
+    for (i, (image, path)) in images.into_iter().enumerate() {
+        let entry = Entry {
+            id: format!("{}-{}", now.as_nanosecond(), i),
+            path,
+            created: now.as_second(),
+        };
+        let scale = (THUMBNAIL_SIZE as f32 / image.width().max(image.height()) as f32).min(1.0);
+        let thumbnail = image::imageops::thumbnail(
+            &image,
+            ((image.width() as f32 * scale) as u32).max(1),
+            ((image.height() as f32 * scale) as u32).max(1),
+        );
+        image.save(dir.join(format!("{}.png", entry.id)))?;
+        thumbnail.save(dir.join(format!("{}.thumbnail.png", entry.id)))?;
+        new_entries.push(entry);
+    }
+
+    let _lock = INDEX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let mut entries = read_index();
+    entries.splice(0..0, new_entries);
+
+    let mut total = 0;
+    let mut kept = 0;
+    for entry in &entries {
+        total += entry.size();
+        // Always keep the screenshot just taken
+        if kept >= count || (total > size && kept > 0) {
+            break;
+        }
+        kept += 1;
+    }
+    for entry in entries.drain(kept..) {
+        entry.remove_files();
+    }
+    write_index(&entries)
+}
+
+/// Entries from newest to oldest, without those whose files were removed
+pub fn load() -> Vec<Entry> {
+    let _lock = INDEX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    read_index()
+        .into_iter()
+        .filter(|entry| entry.image_path().is_some_and(|path| path.exists()))
+        .collect()
+}
+
+/// Removes an entry from the history. The screenshot it was saved as is kept.
+pub fn remove(entry: &Entry) -> anyhow::Result<()> {
+    let _lock = INDEX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let mut entries = read_index();
+    entries.retain(|e| e.id != entry.id);
+    entry.remove_files();
+    write_index(&entries)
+}
+
+/// Opens the file manager at the folder of `path`, with the file selected
+pub async fn show_in_folder(path: &Path) -> anyhow::Result<()> {
+    let uri = url::Url::from_file_path(path)
+        .map_err(|()| anyhow::anyhow!("{:?} is not an absolute path", path))?;
+    let connection = zbus::Connection::session().await?;
+    connection
+        .call_method(
+            Some("org.freedesktop.FileManager1"),
+            "/org/freedesktop/FileManager1",
+            Some("org.freedesktop.FileManager1"),
+            "ShowItems",
+            &(vec![uri.as_str()], ""),
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct History {
+    /// Surface the history is shown on. Other outputs keep showing their screenshot.
+    pub surface: window::Id,
+    pub entries: Vec<Entry>,
+}
+
+impl History {
+    pub fn new(surface: window::Id) -> Self {
+        Self {
+            surface,
+            entries: load(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Msg {
+    Copy(usize),
+    /// Save again to the chosen location
+    Save(usize),
+    ShowInFolder(usize),
+    Annotate(usize),
+    Remove(usize),
+    /// Return to the selection
+    Back,
+}
+
+fn entry_view(i: usize, entry: &Entry, spacing: Spacing) -> Element<'_, Msg> {
+    let thumbnail: Element<_> = match entry.thumbnail_path() {
+        Some(path) => widget::image::Image::new(widget::image::Handle::from_path(path))
+            .width(Length::Fixed(THUMBNAIL_SIZE as f32 / 2.0))
+            .into(),
+        None => space::horizontal()
+            .width(Length::Fixed(THUMBNAIL_SIZE as f32 / 2.0))
+            .into(),
+    };
+    let name = match &entry.path {
+        Some(path) => path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned(),
+        None => fl!("save-to", "clipboard"),
+    };
+    let created = jiff::Timestamp::from_second(entry.created)
+        .map(|time| {
+            time.to_zoned(jiff::tz::TimeZone::system())
+                .strftime("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    row![
+        thumbnail,
+        column![text::body(name), text::caption(created)].width(Length::Fill),
+        button::custom(text(fl!("history", "copy"))).on_press(Msg::Copy(i)),
+        button::custom(text(fl!("save"))).on_press(Msg::Save(i)),
+        button::custom(text(fl!("history", "show-in-folder")))
+            .on_press_maybe(entry.path.is_some().then_some(Msg::ShowInFolder(i))),
+        button::custom(text(fl!("annotate"))).on_press(Msg::Annotate(i)),
+        button::custom(text(fl!("history", "remove")))
+            .class(cosmic::theme::Button::Destructive)
+            .on_press(Msg::Remove(i)),
+    ]
+    .align_y(Alignment::Center)
+    .spacing(spacing.space_s)
+    .into()
+}
+
+pub(crate) fn view(history: &History, spacing: Spacing) -> Element<'_, Msg> {
+    let header = row![
+        text::title3(fl!("history")).width(Length::Fill),
+        button::custom(
+            icon::Icon::from(icon::from_name("window-close-symbolic").size(16))
+                .width(Length::Fixed(16.0))
+                .height(Length::Fixed(16.0)),
+        )
+        .class(cosmic::theme::Button::Icon)
+        .on_press(Msg::Back),
+    ]
+    .align_y(Alignment::Center);
+
+    let list: Element<_> = if history.entries.is_empty() {
+        widget::container(text::body(fl!("history", "empty")))
+            .center(Length::Fill)
+            .into()
+    } else {
+        let mut list = Vec::with_capacity(history.entries.len() * 2);
+        for (i, entry) in history.entries.iter().enumerate() {
+            if i > 0 {
+                list.push(divider::horizontal::light().into());
+            }
+            list.push(entry_view(i, entry, spacing));
+        }
+        widget::scrollable(widget::column::with_children(list).spacing(spacing.space_xs)).into()
+    };
+
+    let panel = widget::container(
+        column![header, list]
+            .spacing(spacing.space_s)
+            .padding(spacing.space_m),
+    )
+    .max_width(960.0)
+    .class(cosmic::theme::Container::Custom(Box::new(|theme| {
+        let theme = theme.cosmic();
+        widget::container::Style {
+            background: Some(Background::Color(theme.background.component.base.into())),
+            text_color: Some(theme.background.component.on.into()),
+            border: Border {
+                radius: theme.corner_radii.radius_s.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    })));
+
+    widget::container(panel)
+        .center_x(Length::Fill)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .padding(spacing.space_l)
+        .class(cosmic::theme::Container::Custom(Box::new(|theme| {
+            widget::container::Style {
+                background: Some(Background::Color(theme.cosmic().background.base.into())),
+                ..Default::default()
+            }
+        })))
+        .into()
+}
@@ -0,0 +1,172 @@
+//! Joins the frames of a window being scrolled into one tall image, where they overlap
+
+use image::RgbaImage;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Stitched images stop growing at this height
+pub const MAX_HEIGHT: u32 = 32768;
+
+/// Fewest rows a frame must share with the previous one to be joined to it
+const MIN_OVERLAP: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct Stitcher {
+    image: RgbaImage,
+    /// Row hashes of the last frame joined, which the bottom of `image` is a copy of
+    last: Vec<u64>,
+    frames: usize,
+}
+
+impl Stitcher {
+    pub fn new(first: RgbaImage) -> Self {
+        Self {
+            last: row_hashes(&first),
+            image: first,
+            frames: 1,
+        }
+    }
+
+    /// Number of frames joined so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn into_image(self) -> RgbaImage {
+        self.image
+    }
+
+    /// Appends the rows scrolled into view since the last frame. Returns `false` if the frame
+    /// wasn't joined: because nothing scrolled, it scrolled up or too far to overlap, the window
+    /// was resized, or the image is already `MAX_HEIGHT` tall.
+    pub fn push(&mut self, frame: &RgbaImage) -> bool {
+        if frame.width() != self.image.width() || frame.height() as usize != self.last.len() {
+            return false;
+        }
+        let hashes = row_hashes(frame);
+        let Some((shift, bottom)) = find_scroll(&self.last, &hashes) else {
+            return false;
+        };
+        let height = self.image.height() + shift as u32;
+        if height > MAX_HEIGHT {
+            return false;
+        }
+
+        // The rows above the footer, then the new rows and the footer of this frame,
+        // appended in place so each frame only copies its own rows
+        let width = self.image.width();
+        let stride = width as usize * 4;
+        let kept = self.image.height() as usize - bottom;
+        let from = hashes.len() - bottom - shift;
+        let mut data = std::mem::replace(&mut self.image, RgbaImage::new(0, 0)).into_raw();
+        data.truncate(kept * stride);
+        data.extend_from_slice(&frame.as_raw()[from * stride..]);
+        self.image = RgbaImage::from_raw(width, height, data)
+            .expect("stitched buffer matches its dimensions");
+        self.last = hashes;
+        self.frames += 1;
+        true
+    }
+}
+
+fn row_hashes(image: &RgbaImage) -> Vec<u64> {
+    image
+        .as_raw()
+        .chunks_exact(image.width() as usize * 4)
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Rows `next` scrolled down from `prev`, and the number of rows at the bottom that stayed in
+/// place. Rows at the top and bottom that are the same in both frames, like toolbars, are left
+/// out of the search.
+fn find_scroll(prev: &[u64], next: &[u64]) -> Option<(usize, usize)> {
+    let top = prev.iter().zip(next).take_while(|(a, b)| a == b).count();
+    if top == prev.len() {
+        return None;
+    }
+    let bottom = prev
+        .iter()
+        .rev()
+        .zip(next.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let prev = &prev[top..prev.len() - bottom];
+    let next = &next[top..next.len() - bottom];
+
+    // Allow a few rows to differ, such as a blinking caret or an animation
+    let mut best: Option<(usize, f32)> = None;
+    for shift in 1..prev.len() {
+        let overlap = prev.len() - shift;
+        if overlap < MIN_OVERLAP {
+            break;
+        }
+        let matching = (0..overlap).filter(|&y| next[y] == prev[y + shift]).count();
+        let ratio = matching as f32 / overlap as f32;
+        if ratio >= 0.9 && best.is_none_or(|(_, best_ratio)| ratio > best_ratio) {
+            best = Some((shift, ratio));
+        }
+    }
+    best.map(|(shift, _)| (shift, bottom))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Image whose rows all differ
+    fn page(height: u32) -> RgbaImage {
+        RgbaImage::from_fn(8, height, |x, y| {
+            image::Rgba([y as u8, (y >> 8) as u8, x as u8, 255])
+        })
+    }
+
+    fn crop(image: &RgbaImage, y: u32, height: u32) -> RgbaImage {
+        image::imageops::crop_imm(image, 0, y, image.width(), height).to_image()
+    }
+
+    /// `content` between a toolbar and a status bar that don't scroll
+    fn window(content: RgbaImage) -> RgbaImage {
+        let bar = |color| RgbaImage::from_pixel(8, 20, image::Rgba([color, 0, 0, 255]));
+        let mut image = RgbaImage::new(8, content.height() + 40);
+        image::imageops::replace(&mut image, &bar(200), 0, 0);
+        image::imageops::replace(&mut image, &content, 0, 20);
+        image::imageops::replace(&mut image, &bar(100), 0, content.height() as i64 + 20);
+        image
+    }
+
+    #[test]
+    fn joins_scrolled_frames() {
+        let page = page(300);
+        let mut stitcher = Stitcher::new(crop(&page, 0, 100));
+        assert!(stitcher.push(&crop(&page, 30, 100)));
+        assert!(stitcher.push(&crop(&page, 75, 100)));
+        assert_eq!(stitcher.frames(), 3);
+        assert_eq!(stitcher.into_image(), crop(&page, 0, 175));
+    }
+
+    #[test]
+    fn keeps_toolbars_in_place() {
+        let page = page(300);
+        let mut stitcher = Stitcher::new(window(crop(&page, 0, 100)));
+        assert!(stitcher.push(&window(crop(&page, 40, 100))));
+        assert_eq!(stitcher.into_image(), window(crop(&page, 0, 140)));
+    }
+
+    #[test]
+    fn skips_frames_without_overlap() {
+        let page = page(300);
+        let mut stitcher = Stitcher::new(crop(&page, 0, 100));
+        // Nothing scrolled
+        assert!(!stitcher.push(&crop(&page, 0, 100)));
+        // Scrolled past the last frame
+        assert!(!stitcher.push(&crop(&page, 150, 100)));
+        // Scrolled up
+        assert!(stitcher.push(&crop(&page, 50, 100)));
+        assert!(!stitcher.push(&crop(&page, 20, 100)));
+        assert_eq!(stitcher.into_image(), crop(&page, 0, 150));
+    }
+}
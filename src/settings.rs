@@ -0,0 +1,866 @@
+use cosmic::config::CosmicTk;
+use cosmic::cosmic_theme::palette::Srgba;
+use cosmic::cosmic_theme::{Density, Theme};
+use cosmic_config::cosmic_config_derive::CosmicConfigEntry;
+use cosmic_config::{ConfigGet, CosmicConfigEntry};
+use futures::{StreamExt, future};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::Sender;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{self, OwnedValue, Value};
+
+use crate::subscription;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ColorScheme {
+    /// No preference
+    NoPreference,
+    /// Prefers dark appearance
+    PreferDark,
+    /// Prefers light appearance
+    PreferLight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Contrast {
+    /// No preference
+    NoPreference,
+    /// Higher contrast
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, zvariant::Value, zvariant::OwnedValue)]
+struct Color {
+    red: f64,
+    green: f64,
+    blue: f64,
+}
+
+impl From<Srgba> for Color {
+    fn from(color: Srgba) -> Self {
+        Self {
+            red: color.red.into(),
+            green: color.green.into(),
+            blue: color.blue.into(),
+        }
+    }
+}
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const GNOME_DESKTOP_INTERFACE_NAMESPACE: &str = "org.gnome.desktop.interface";
+const GNOME_A11Y_INTERFACE_NAMESPACE: &str = "org.gnome.desktop.a11y.interface";
+const COSMIC_THEME_NAMESPACE: &str = "com.system76.cosmic.theme";
+
+// Size of COSMIC's 14px body text, in points. libcosmic has no font size
+// setting, so text size follows the interface density through
+// `text-scaling-factor` instead.
+const FONT_SIZE: f64 = 10.5;
+const DEFAULT_CURSOR_THEME: &str = "default";
+const DEFAULT_CURSOR_SIZE: i32 = 24;
+
+pub const TIME_APPLET_ID: &str = "com.system76.CosmicAppletTime";
+
+/// The parts of the time applet's config used for `clock-format`
+#[derive(Debug, Clone, Default, PartialEq, CosmicConfigEntry)]
+#[version = 1]
+#[id = "com.system76.CosmicAppletTime"]
+pub struct TimeAppletConfig {
+    pub military_time: bool,
+}
+
+impl TimeAppletConfig {
+    fn load() -> Self {
+        cosmic_config::Config::new(TIME_APPLET_ID, Self::VERSION)
+            .map(|config| Self::get_entry(&config).unwrap_or_else(|(_, config)| config))
+            .unwrap_or_default()
+    }
+}
+
+/// A change to one of the sources settings are read from
+#[derive(Debug, Clone)]
+pub enum Update {
+    Theme(Box<Theme>),
+    IsDark(bool),
+    Toolkit(CosmicTk),
+    /// Whether the clock uses 24-hour time
+    ClockFormat(bool),
+    /// Whether assistive technologies are enabled on the accessibility bus
+    ToolkitAccessibility(bool),
+    /// Every entry of the component with this namespace
+    Component(&'static str, BTreeMap<String, ron::Value>),
+}
+
+/// A key served by the settings portal, and how to read its value
+struct Setting {
+    namespace: &'static str,
+    key: &'static str,
+    value: fn(&Settings) -> Value<'static>,
+}
+
+/// Every key served by the settings portal. `ReadOne`, `ReadAll` and
+/// `SettingChanged` are all driven by this table. COSMIC has no setting to
+/// reduce motion or turn off animations, so `reduced-motion` and
+/// `enable-animations` are left out rather than given fixed values.
+static SETTINGS: &[Setting] = &[
+    Setting {
+        namespace: APPEARANCE_NAMESPACE,
+        key: "color-scheme",
+        value: |settings| (settings.color_scheme as u32).into(),
+    },
+    Setting {
+        namespace: APPEARANCE_NAMESPACE,
+        key: "accent-color",
+        value: |settings| Color::from(settings.theme.accent_color()).into(),
+    },
+    Setting {
+        namespace: APPEARANCE_NAMESPACE,
+        key: "contrast",
+        value: |settings| (settings.contrast() as u32).into(),
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "font-name",
+        value: |settings| {
+            format!("{} {}", settings.toolkit.interface_font.family, FONT_SIZE).into()
+        },
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "monospace-font-name",
+        value: |settings| {
+            format!("{} {}", settings.toolkit.monospace_font.family, FONT_SIZE).into()
+        },
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "cursor-theme",
+        value: |settings| settings.cursor.theme.clone().into(),
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "cursor-size",
+        value: |settings| settings.cursor.size.into(),
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "icon-theme",
+        value: |settings| settings.toolkit.icon_theme.clone().into(),
+    },
+    // cosmic-settings-daemon styles GTK 3 apps with adw-gtk3
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "gtk-theme",
+        value: |settings| {
+            if settings.color_scheme == ColorScheme::PreferDark {
+                "adw-gtk3-dark".into()
+            } else {
+                "adw-gtk3".into()
+            }
+        },
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "text-scaling-factor",
+        value: |settings| text_scaling_factor(settings.toolkit.interface_density).into(),
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "clock-format",
+        value: |settings| Value::from(if settings.military_time { "24h" } else { "12h" }),
+    },
+    Setting {
+        namespace: GNOME_DESKTOP_INTERFACE_NAMESPACE,
+        key: "toolkit-accessibility",
+        value: |settings| settings.toolkit_accessibility.into(),
+    },
+    Setting {
+        namespace: GNOME_A11Y_INTERFACE_NAMESPACE,
+        key: "high-contrast",
+        value: |settings| (settings.contrast() == Contrast::High).into(),
+    },
+    // Corner radii are in the order top left, top right, bottom right, bottom left
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "corner-radii",
+        value: |settings| {
+            let radii = &settings.theme.corner_radii;
+            let corner_radii: HashMap<&str, (f64, f64, f64, f64)> = [
+                ("radius-0", radii.radius_0),
+                ("radius-xs", radii.radius_xs),
+                ("radius-s", radii.radius_s),
+                ("radius-m", radii.radius_m),
+                ("radius-l", radii.radius_l),
+                ("radius-xl", radii.radius_xl),
+            ]
+            .into_iter()
+            .map(|(name, [a, b, c, d])| (name, (a.into(), b.into(), c.into(), d.into())))
+            .collect();
+            corner_radii.into()
+        },
+    },
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "spacing",
+        value: |settings| {
+            let spacing = &settings.theme.spacing;
+            let spacing: HashMap<&str, u32> = [
+                ("space-none", spacing.space_none),
+                ("space-xxxs", spacing.space_xxxs),
+                ("space-xxs", spacing.space_xxs),
+                ("space-xs", spacing.space_xs),
+                ("space-s", spacing.space_s),
+                ("space-m", spacing.space_m),
+                ("space-l", spacing.space_l),
+                ("space-xl", spacing.space_xl),
+                ("space-xxl", spacing.space_xxl),
+                ("space-xxxl", spacing.space_xxxl),
+            ]
+            .into_iter()
+            .map(|(name, space)| (name, space.into()))
+            .collect();
+            spacing.into()
+        },
+    },
+    // Without a hint, windows use the accent color
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "window-hint",
+        value: |settings| {
+            let theme = &settings.theme;
+            Color::from(
+                theme
+                    .window_hint
+                    .map_or_else(|| theme.accent_color(), Srgba::from),
+            )
+            .into()
+        },
+    },
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "background-color",
+        value: |settings| Color::from(settings.theme.background.base).into(),
+    },
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "primary-container-color",
+        value: |settings| Color::from(settings.theme.primary.base).into(),
+    },
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "secondary-container-color",
+        value: |settings| Color::from(settings.theme.secondary.base).into(),
+    },
+    Setting {
+        namespace: COSMIC_THEME_NAMESPACE,
+        key: "frosted-glass",
+        value: |settings| settings.theme.is_frosted.into(),
+    },
+];
+
+/// Scales text with COSMIC's interface density, as GTK has no density setting.
+fn text_scaling_factor(density: Density) -> f64 {
+    match density {
+        Density::Compact => 0.9,
+        Density::Standard => 1.0,
+        Density::Spacious => 1.1,
+    }
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, in order of precedence
+fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::data_dir().into_iter().collect();
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|data_dirs| !data_dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(std::env::split_paths(&data_dirs).filter(|dir| dir.is_absolute()));
+    dirs
+}
+
+/// The cursor theme and size cosmic-comp uses
+#[derive(Debug, Clone, PartialEq)]
+struct Cursor {
+    theme: String,
+    size: i32,
+}
+
+impl Cursor {
+    /// cosmic-comp reads `XCURSOR_THEME` and `XCURSOR_SIZE` once at startup,
+    /// so these can't change during the session. Without a theme, it uses the
+    /// one the `default` theme inherits, which isn't visible inside a sandbox.
+    fn load() -> Self {
+        let theme = std::env::var("XCURSOR_THEME")
+            .ok()
+            .filter(|theme| !theme.is_empty())
+            .or_else(inherited_cursor_theme)
+            .unwrap_or_else(|| DEFAULT_CURSOR_THEME.to_string());
+        let size = std::env::var("XCURSOR_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<i32>().ok())
+            .unwrap_or(DEFAULT_CURSOR_SIZE);
+        Self { theme, size }
+    }
+}
+
+/// Theme inherited by the `default` cursor theme, from the first of its
+/// `index.theme` files in the icon theme search path.
+fn inherited_cursor_theme() -> Option<String> {
+    let index = dirs::home_dir()
+        .map(|home| home.join(".icons"))
+        .into_iter()
+        .chain(data_dirs().into_iter().map(|dir| dir.join("icons")))
+        .map(|dir| dir.join(DEFAULT_CURSOR_THEME).join("index.theme"))
+        .find_map(|path| std::fs::read_to_string(path).ok())?;
+    index
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Inherits="))
+        .and_then(|themes| themes.split([',', ';']).next())
+        .map(|theme| theme.trim().to_string())
+        .filter(|theme| !theme.is_empty())
+}
+
+/// A cosmic-config component served read-only to sandboxed apps
+struct Component {
+    namespace: &'static str,
+    id: &'static str,
+    version: u64,
+}
+
+/// Components whose config is exposed, each under its own namespace. Only
+/// appearance and layout settings belong here, since any app can read them.
+static COMPONENTS: &[Component] = &[
+    Component {
+        namespace: "com.system76.cosmic.panel",
+        id: "com.system76.CosmicPanel.Panel",
+        version: 1,
+    },
+    Component {
+        namespace: "com.system76.cosmic.dock",
+        id: "com.system76.CosmicPanel.Dock",
+        version: 1,
+    },
+    Component {
+        namespace: "com.system76.cosmic.applet-time",
+        id: TIME_APPLET_ID,
+        version: TimeAppletConfig::VERSION,
+    },
+    Component {
+        namespace: "com.system76.cosmic.theme-builder-dark",
+        id: "com.system76.CosmicTheme.Dark.Builder",
+        version: 1,
+    },
+    Component {
+        namespace: "com.system76.cosmic.theme-builder-light",
+        id: "com.system76.CosmicTheme.Light.Builder",
+        version: 1,
+    },
+];
+
+impl Component {
+    /// Keys with a system default or user value.
+    fn keys(&self) -> BTreeSet<String> {
+        let dir = Path::new("cosmic")
+            .join(self.id)
+            .join(format!("v{}", self.version));
+        // System defaults are searched for in the data directories, like cosmic-config does
+        let mut paths: Vec<_> = data_dirs()
+            .into_iter()
+            .map(|path| path.join(&dir))
+            .collect();
+        if let Some(config_dir) = dirs::config_dir() {
+            paths.push(config_dir.join(&dir));
+        }
+        paths
+            .iter()
+            .filter_map(|path| std::fs::read_dir(path).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| !name.starts_with('.'))
+            .collect()
+    }
+
+    fn read(&self, config: &cosmic_config::Config) -> BTreeMap<String, ron::Value> {
+        self.keys()
+            .into_iter()
+            .filter_map(|key| match config.get::<ron::Value>(&key) {
+                Ok(value) => Some((key, value)),
+                Err(err) => {
+                    log::debug!("Failed to read {} {}: {}", self.id, key, err);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Converts a RON value to a D-Bus variant. Structs and maps become `a{sv}`,
+/// sequences become `av`, and `None` and unit values become an empty `av`.
+/// Enum variant names aren't kept by RON, so only their fields are converted.
+fn ron_to_value(value: &ron::Value) -> Value<'static> {
+    match value {
+        ron::Value::Bool(b) => (*b).into(),
+        ron::Value::Char(c) => c.to_string().into(),
+        ron::Value::String(s) => s.clone().into(),
+        ron::Value::Bytes(bytes) => bytes.clone().into(),
+        ron::Value::Number(number) => match *number {
+            ron::Number::I8(n) => i64::from(n).into(),
+            ron::Number::I16(n) => i64::from(n).into(),
+            ron::Number::I32(n) => i64::from(n).into(),
+            ron::Number::I64(n) => n.into(),
+            ron::Number::U8(n) => u64::from(n).into(),
+            ron::Number::U16(n) => u64::from(n).into(),
+            ron::Number::U32(n) => u64::from(n).into(),
+            ron::Number::U64(n) => n.into(),
+            number => number.into_f64().into(),
+        },
+        ron::Value::Option(Some(value)) => vec![ron_to_value(value)].into(),
+        ron::Value::Option(None) | ron::Value::Unit => Vec::<Value<'static>>::new().into(),
+        ron::Value::Seq(values) => values.iter().map(ron_to_value).collect::<Vec<_>>().into(),
+        ron::Value::Map(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    ron::Value::String(key) => key.clone(),
+                    key => ron::to_string(key).unwrap_or_default(),
+                };
+                (key, ron_to_value(value))
+            })
+            .collect::<HashMap<String, Value<'static>>>()
+            .into(),
+    }
+}
+
+/// Watches each component in `COMPONENTS`, sending its entries when they change.
+pub async fn component_watch_task(tx: Sender<subscription::Event>) {
+    let mut watchers = Vec::with_capacity(COMPONENTS.len());
+    for component in COMPONENTS {
+        let config = match cosmic_config::Config::new(component.id, component.version) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("Failed to open config for {}: {}", component.id, err);
+                continue;
+            }
+        };
+        let tx = tx.clone();
+        let watcher = config.watch(move |config, _keys| {
+            let update = Update::Component(component.namespace, component.read(config));
+            let _ = tx.blocking_send(subscription::Event::Settings(update));
+        });
+        match watcher {
+            Ok(watcher) => watchers.push(watcher),
+            Err(err) => log::error!("Failed to watch config for {}: {}", component.id, err),
+        }
+    }
+    // Changes are only reported while the watchers are alive
+    future::pending::<()>().await;
+}
+
+/// Matches a namespace pattern against a known namespace.
+/// Supports trailing '*' glob (e.g., "org.gnome.*" matches "org.gnome.desktop.interface").
+fn namespace_matches(pattern: &str, namespace: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        namespace.starts_with(prefix)
+    } else {
+        pattern == namespace
+    }
+}
+
+pub struct Settings {
+    color_scheme: ColorScheme,
+    theme: Theme,
+    toolkit: CosmicTk,
+    cursor: Cursor,
+    military_time: bool,
+    toolkit_accessibility: bool,
+    /// Entries of each component in `COMPONENTS`, by namespace
+    components: HashMap<&'static str, BTreeMap<String, ron::Value>>,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        let theme = cosmic::theme::system_preference();
+        let cosmic = theme.cosmic();
+        Self {
+            color_scheme: if cosmic.is_dark {
+                ColorScheme::PreferDark
+            } else {
+                ColorScheme::PreferLight
+            },
+            theme: cosmic.clone(),
+            toolkit: cosmic_config::Config::new(cosmic::config::ID, CosmicTk::VERSION)
+                .map(|config| CosmicTk::get_entry(&config).unwrap_or_else(|(_, config)| config))
+                .unwrap_or_default(),
+            cursor: Cursor::load(),
+            military_time: TimeAppletConfig::load().military_time,
+            // Set from the accessibility bus once it's been queried
+            toolkit_accessibility: false,
+            components: COMPONENTS
+                .iter()
+                .map(|component| {
+                    let entries = cosmic_config::Config::new(component.id, component.version)
+                        .map(|config| component.read(&config))
+                        .unwrap_or_default();
+                    (component.namespace, entries)
+                })
+                .collect(),
+        }
+    }
+
+    fn contrast(&self) -> Contrast {
+        if self.theme.is_high_contrast {
+            Contrast::High
+        } else {
+            Contrast::NoPreference
+        }
+    }
+
+    fn values(&self) -> Vec<Value<'static>> {
+        SETTINGS
+            .iter()
+            .map(|setting| (setting.value)(self))
+            .collect()
+    }
+
+    /// Applies `update`, then emits `SettingChanged` for each key whose value changed.
+    pub async fn update(
+        &mut self,
+        signal_emitter: &SignalEmitter<'_>,
+        update: Update,
+    ) -> zbus::Result<()> {
+        let old_values = self.values();
+        match update {
+            Update::Theme(theme) => self.theme = *theme,
+            Update::IsDark(is_dark) => {
+                self.color_scheme = if is_dark {
+                    ColorScheme::PreferDark
+                } else {
+                    ColorScheme::PreferLight
+                };
+            }
+            Update::Toolkit(toolkit) => self.toolkit = toolkit,
+            Update::ClockFormat(military_time) => self.military_time = military_time,
+            Update::ToolkitAccessibility(enabled) => self.toolkit_accessibility = enabled,
+            Update::Component(namespace, entries) => {
+                let old_entries = self
+                    .components
+                    .insert(namespace, entries)
+                    .unwrap_or_default();
+                for (key, value) in changed_entries(&old_entries, &self.components[namespace]) {
+                    self.setting_changed(signal_emitter, namespace, &key, value)
+                        .await?;
+                }
+            }
+        }
+
+        for (setting, value) in changed_settings(old_values, self.values()) {
+            self.setting_changed(signal_emitter, setting.namespace, setting.key, value)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys of `SETTINGS` whose value differs between `old_values` and `values`,
+/// with their new value.
+fn changed_settings(
+    old_values: Vec<Value<'static>>,
+    values: Vec<Value<'static>>,
+) -> Vec<(&'static Setting, Value<'static>)> {
+    SETTINGS
+        .iter()
+        .zip(values.into_iter().zip(old_values))
+        .filter(|(_, (value, old_value))| value != old_value)
+        .map(|(setting, (value, _))| (setting, value))
+        .collect()
+}
+
+/// Component entries that were added, changed or removed, with their new value.
+/// Removed keys get an empty value, as there's no signal for removal.
+fn changed_entries(
+    old_entries: &BTreeMap<String, ron::Value>,
+    entries: &BTreeMap<String, ron::Value>,
+) -> Vec<(String, Value<'static>)> {
+    let changed = entries
+        .iter()
+        .filter(|(key, value)| old_entries.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), ron_to_value(value)));
+    let removed = old_entries
+        .keys()
+        .filter(|key| !entries.contains_key(*key))
+        .map(|key| (key.clone(), ron_to_value(&ron::Value::Unit)));
+    changed.chain(removed).collect()
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Settings")]
+impl Settings {
+    /// Read method (deprecated)
+    async fn read(&self, namespace: &str, key: &str) -> zbus::fdo::Result<zvariant::OwnedValue> {
+        self.read_one(namespace, key).await
+    }
+
+    /// ReadAll method
+    async fn read_all(
+        &self,
+        namespaces: Vec<&str>,
+    ) -> HashMap<String, HashMap<String, OwnedValue>> {
+        let mut map = HashMap::<String, HashMap<String, OwnedValue>>::new();
+        for setting in SETTINGS {
+            let matched = namespaces.is_empty()
+                || namespaces
+                    .iter()
+                    .any(|pattern| namespace_matches(pattern, setting.namespace));
+            if !matched {
+                continue;
+            }
+            match OwnedValue::try_from((setting.value)(self)) {
+                Ok(value) => {
+                    map.entry(setting.namespace.to_string())
+                        .or_default()
+                        .insert(setting.key.to_string(), value);
+                }
+                Err(err) => log::error!(
+                    "Failed to read setting {} {}: {}",
+                    setting.namespace,
+                    setting.key,
+                    err
+                ),
+            }
+        }
+        for (namespace, entries) in &self.components {
+            let matched = namespaces.is_empty()
+                || namespaces
+                    .iter()
+                    .any(|pattern| namespace_matches(pattern, namespace));
+            if !matched {
+                continue;
+            }
+            let inner = map.entry(namespace.to_string()).or_default();
+            for (key, value) in entries {
+                if let Ok(value) = OwnedValue::try_from(ron_to_value(value)) {
+                    inner.insert(key.clone(), value);
+                }
+            }
+        }
+        map
+    }
+
+    /// ReadOne method
+    async fn read_one(&self, namespace: &str, key: &str) -> zbus::fdo::Result<OwnedValue> {
+        let value = if let Some(setting) = SETTINGS
+            .iter()
+            .find(|setting| setting.namespace == namespace && setting.key == key)
+        {
+            (setting.value)(self)
+        } else if let Some(value) = self
+            .components
+            .get(namespace)
+            .and_then(|entries| entries.get(key))
+        {
+            ron_to_value(value)
+        } else {
+            return Err(zbus::fdo::Error::Failed(
+                "Unknown namespace or key".to_string(),
+            ));
+        };
+        OwnedValue::try_from(value).map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+    }
+
+    /// SettingChanged signal
+    #[zbus(signal)]
+    async fn setting_changed(
+        &self,
+        _signal_ctxt: &SignalEmitter<'_>,
+        namespace: &str,
+        key: &str,
+        value: zvariant::Value<'_>,
+    ) -> zbus::Result<()>;
+
+    /// version property
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+}
+
+/// Status of the AT-SPI accessibility bus, toggled by COSMIC's screen reader setting
+#[zbus::proxy(
+    interface = "org.a11y.Status",
+    default_service = "org.a11y.Bus",
+    default_path = "/org/a11y/bus"
+)]
+trait A11yStatus {
+    #[zbus(property)]
+    fn is_enabled(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn screen_reader_enabled(&self) -> zbus::Result<bool>;
+}
+
+/// Reports whether assistive technologies are enabled, initially and on each change.
+pub async fn a11y_status_task(connection: zbus::Connection, tx: Sender<subscription::Event>) {
+    let status = match A11yStatusProxy::new(&connection).await {
+        Ok(status) => status,
+        Err(err) => {
+            log::error!("Failed to connect to accessibility bus: {}", err);
+            return;
+        }
+    };
+    let mut changes = futures::stream::select(
+        status.receive_is_enabled_changed().await.map(|_| ()),
+        status
+            .receive_screen_reader_enabled_changed()
+            .await
+            .map(|_| ()),
+    );
+    loop {
+        let enabled = status.is_enabled().await.unwrap_or(false)
+            || status.screen_reader_enabled().await.unwrap_or(false);
+        let update = Update::ToolkitAccessibility(enabled);
+        if tx
+            .send(subscription::Event::Settings(update))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        if changes.next().await.is_none() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ron(s: &str) -> ron::Value {
+        ron::from_str(s).unwrap()
+    }
+
+    fn dict<const N: usize>(entries: [(&str, Value<'static>); N]) -> Value<'static> {
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<HashMap<String, Value<'static>>>()
+            .into()
+    }
+
+    fn empty() -> Value<'static> {
+        Vec::<Value<'static>>::new().into()
+    }
+
+    #[test]
+    fn converts_nested_structs() {
+        let value = ron_to_value(&ron(r#"(name: "panel", anchor: (edge: "top", gap: 1.5))"#));
+
+        let anchor = dict([("edge", "top".into()), ("gap", 1.5.into())]);
+        assert_eq!(value, dict([("name", "panel".into()), ("anchor", anchor)]));
+    }
+
+    #[test]
+    fn converts_maps_with_non_string_keys() {
+        let value = ron_to_value(&ron(r#"{"a": true, 'b': false}"#));
+
+        assert_eq!(value, dict([("a", true.into()), ("'b'", false.into())]));
+    }
+
+    #[test]
+    fn converts_enums_to_their_fields() {
+        assert_eq!(
+            ron_to_value(&ron(r#"Rgb("red", "green")"#)),
+            Value::from(vec![Value::from("red"), Value::from("green")])
+        );
+        assert_eq!(
+            ron_to_value(&ron(r#"Named(size: "large")"#)),
+            dict([("size", "large".into())])
+        );
+        assert_eq!(ron_to_value(&ron("()")), empty());
+    }
+
+    #[test]
+    fn converts_options() {
+        assert_eq!(
+            ron_to_value(&ron(r#"Some("cosmic")"#)),
+            Value::from(vec![Value::from("cosmic")])
+        );
+        assert_eq!(ron_to_value(&ron("None")), empty());
+    }
+
+    #[test]
+    fn widens_integers() {
+        let signed = ron::Value::Number(ron::Number::I8(-3));
+        let unsigned = ron::Value::Number(ron::Number::U16(300));
+
+        assert_eq!(ron_to_value(&signed), Value::from(-3i64));
+        assert_eq!(ron_to_value(&unsigned), Value::from(300u64));
+    }
+
+    #[test]
+    fn matches_namespaces() {
+        assert!(namespace_matches(
+            "org.freedesktop.appearance",
+            "org.freedesktop.appearance"
+        ));
+        assert!(!namespace_matches(
+            "org.freedesktop",
+            "org.freedesktop.appearance"
+        ));
+        assert!(namespace_matches(
+            "org.gnome.*",
+            "org.gnome.desktop.interface"
+        ));
+        assert!(!namespace_matches(
+            "org.gnome.*",
+            "com.system76.cosmic.theme"
+        ));
+        assert!(namespace_matches("*", "com.system76.cosmic.theme"));
+    }
+
+    #[test]
+    fn diffs_only_changed_settings() {
+        let old_values: Vec<Value<'static>> = SETTINGS.iter().map(|_| 0u32.into()).collect();
+        let values: Vec<Value<'static>> = (0..SETTINGS.len())
+            .map(|i| u32::from(i == 1).into())
+            .collect();
+
+        let changed = changed_settings(old_values, values);
+
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0.key, SETTINGS[1].key);
+        assert_eq!(changed[0].1, Value::from(1u32));
+    }
+
+    #[test]
+    fn diffs_only_changed_entries() {
+        let entries = |pairs: &[(&str, &str)]| -> BTreeMap<String, ron::Value> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), ron(value)))
+                .collect()
+        };
+        let old_entries = entries(&[
+            ("size", "(a: 1.0)"),
+            ("anchor", r#""top""#),
+            ("gone", "true"),
+        ]);
+        let new_entries = entries(&[
+            ("size", "(a: 1.0)"),
+            ("anchor", r#""left""#),
+            ("added", "false"),
+        ]);
+
+        let changed: BTreeMap<String, Value<'static>> = changed_entries(&old_entries, &new_entries)
+            .into_iter()
+            .collect();
+
+        let expected = BTreeMap::from([
+            ("added".to_string(), Value::from(false)),
+            ("anchor".to_string(), Value::from("left")),
+            ("gone".to_string(), empty()),
+        ]);
+        assert_eq!(changed, expected);
+    }
+}
@@ -3,31 +3,46 @@
 use std::any::TypeId;
 use std::hash::Hash;
 
-use cosmic::cosmic_theme::palette::Srgba;
+use cosmic::config::CosmicTk;
 use cosmic::iced::Subscription;
 use futures::{SinkExt, StreamExt, future};
 use tokio::sync::mpsc::Receiver;
 use zbus::{Connection, fdo, zvariant};
 
 use crate::access::Access;
+use crate::app_chooser::AppChooser;
 use crate::file_chooser::FileChooser;
+use crate::global_shortcuts::GlobalShortcuts;
+use crate::inhibit::Inhibit;
+use crate::remote_desktop::RemoteDesktop;
 use crate::screencast::ScreenCast;
 use crate::screenshot::Screenshot;
-use crate::{
-    ACCENT_COLOR_KEY, APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY, CONTRAST_KEY, ColorScheme, Contrast,
-    DBUS_NAME, DBUS_PATH, Settings, config, wayland,
-};
+use crate::settings::{self, Settings, TIME_APPLET_ID, TimeAppletConfig};
+use crate::wallpaper::Wallpaper;
+use crate::{DBUS_NAME, DBUS_PATH, config, wayland};
 
 #[derive(Clone, Debug)]
 pub enum Event {
     Access(crate::access::AccessDialogArgs),
     FileChooser(crate::file_chooser::Args),
     Screenshot(crate::screenshot::Args),
+    /// Show a countdown for a delayed screenshot, then notify the sender once it's hidden
+    ScreenshotCountdown(u32, tokio::sync::mpsc::Sender<()>),
+    PickColor(crate::color_picker::Args),
     Screencast(crate::screencast_dialog::Args),
     CancelScreencast(zvariant::ObjectPath<'static>),
-    Accent(Srgba),
-    IsDark(bool),
-    HighContrast(bool),
+    RemoteDesktop(crate::remote_desktop_dialog::Args),
+    CancelRemoteDesktop(zvariant::ObjectPath<'static>),
+    Wallpaper(crate::wallpaper::Args),
+    AppChooser(crate::app_chooser::Args),
+    UpdateAppChooser(
+        zvariant::ObjectPath<'static>,
+        Vec<crate::app_chooser::Choice>,
+    ),
+    CancelAppChooser(zvariant::ObjectPath<'static>),
+    GlobalShortcuts(crate::global_shortcuts_dialog::Args),
+    CancelGlobalShortcuts(zvariant::ObjectPath<'static>),
+    Settings(settings::Update),
     Config(config::Config),
     Init(tokio::sync::mpsc::Sender<Event>),
     NameLost,
@@ -42,6 +57,8 @@ pub(crate) fn portal_subscription(
     helper: wayland::WaylandHelper,
 ) -> cosmic::iced::Subscription<Event> {
     struct ConfigSubscription;
+    struct ToolkitSubscription;
+    struct TimeAppletSubscription;
     struct Wrapper {
         helper: wayland::WaylandHelper,
     }
@@ -75,6 +92,20 @@ pub(crate) fn portal_subscription(
 
             Event::Config(update.config)
         }),
+        cosmic_config::config_subscription(
+            TypeId::of::<ToolkitSubscription>(),
+            cosmic::config::ID.into(),
+            CosmicTk::VERSION,
+        )
+        .map(|update| Event::Settings(settings::Update::Toolkit(update.config))),
+        cosmic_config::config_subscription(
+            TypeId::of::<TimeAppletSubscription>(),
+            TIME_APPLET_ID.into(),
+            TimeAppletConfig::VERSION,
+        )
+        .map(|update: cosmic_config::Update<TimeAppletConfig>| {
+            Event::Settings(settings::Update::ClockFormat(update.config.military_time))
+        }),
     ])
 }
 
@@ -87,6 +118,7 @@ pub(crate) async fn process_changes(
         State::Init => {
             let (tx, rx) = tokio::sync::mpsc::channel(10);
 
+            let global_shortcuts = GlobalShortcuts::new(tx.clone());
             let connection = zbus::connection::Builder::session()?
                 .serve_at(DBUS_PATH, Access::new(wayland_helper.clone(), tx.clone()))?
                 .serve_at(DBUS_PATH, FileChooser::new(tx.clone()))?
@@ -98,6 +130,15 @@ pub(crate) async fn process_changes(
                     DBUS_PATH,
                     ScreenCast::new(wayland_helper.clone(), tx.clone()),
                 )?
+                .serve_at(
+                    DBUS_PATH,
+                    RemoteDesktop::new(wayland_helper.clone(), tx.clone()),
+                )?
+                .serve_at(DBUS_PATH, Inhibit::new())?
+                .serve_at(DBUS_PATH, Wallpaper::new(tx.clone()))?
+                .serve_at(DBUS_PATH, AppChooser::new(tx.clone()))?
+                .serve_at(DBUS_PATH, global_shortcuts.activations())?
+                .serve_at(DBUS_PATH, global_shortcuts)?
                 .serve_at(DBUS_PATH, Settings::new())?
                 .build()
                 .await?;
@@ -111,6 +152,9 @@ pub(crate) async fn process_changes(
 
             connection.request_name(DBUS_NAME).await?;
 
+            tokio::spawn(settings::a11y_status_task(connection.clone(), tx.clone()));
+            tokio::spawn(settings::component_watch_task(tx.clone()));
+
             _ = output.send(Event::Init(tx)).await;
             *state = State::Waiting(connection, rx);
         }
@@ -132,6 +176,17 @@ pub(crate) async fn process_changes(
                             log::error!("Error sending screenshot event: {:?}", err);
                         };
                     }
+                    Event::ScreenshotCountdown(seconds, tx) => {
+                        if let Err(err) = output.send(Event::ScreenshotCountdown(seconds, tx)).await
+                        {
+                            log::error!("Error sending screenshot countdown event: {:?}", err);
+                        };
+                    }
+                    Event::PickColor(args) => {
+                        if let Err(err) = output.send(Event::PickColor(args)).await {
+                            log::error!("Error sending pick color event: {:?}", err);
+                        };
+                    }
                     Event::Screencast(args) => {
                         if let Err(err) = output.send(Event::Screencast(args)).await {
                             log::error!("Error sending screencast event: {:?}", err);
@@ -142,60 +197,53 @@ pub(crate) async fn process_changes(
                             log::error!("Error sending screencast cancel: {:?}", err);
                         };
                     }
-                    Event::Accent(a) => {
-                        let object_server = conn.object_server();
-                        let iface_ref = object_server.interface::<_, Settings>(DBUS_PATH).await?;
-                        let mut iface = iface_ref.get_mut().await;
-                        iface.accent = a.into_format();
-                        iface
-                            .setting_changed(
-                                iface_ref.signal_emitter(),
-                                APPEARANCE_NAMESPACE,
-                                ACCENT_COLOR_KEY,
-                                zvariant::Array::from(
-                                    [iface.accent.red, iface.accent.green, iface.accent.blue]
-                                        .as_slice(),
-                                )
-                                .into(),
-                            )
-                            .await?;
-                    }
-                    Event::IsDark(is_dark) => {
-                        let object_server = conn.object_server();
-                        let iface_ref = object_server.interface::<_, Settings>(DBUS_PATH).await?;
-                        let mut iface = iface_ref.get_mut().await;
-                        iface.color_scheme = if is_dark {
-                            ColorScheme::PreferDark
-                        } else {
-                            ColorScheme::PreferLight
+                    Event::RemoteDesktop(args) => {
+                        if let Err(err) = output.send(Event::RemoteDesktop(args)).await {
+                            log::error!("Error sending remote desktop event: {:?}", err);
+                        };
+                    }
+                    Event::CancelRemoteDesktop(handle) => {
+                        if let Err(err) = output.send(Event::CancelRemoteDesktop(handle)).await {
+                            log::error!("Error sending remote desktop cancel: {:?}", err);
+                        };
+                    }
+                    Event::Wallpaper(args) => {
+                        if let Err(err) = output.send(Event::Wallpaper(args)).await {
+                            log::error!("Error sending wallpaper event: {:?}", err);
+                        };
+                    }
+                    Event::AppChooser(args) => {
+                        if let Err(err) = output.send(Event::AppChooser(args)).await {
+                            log::error!("Error sending app chooser event: {:?}", err);
+                        };
+                    }
+                    Event::UpdateAppChooser(handle, choices) => {
+                        if let Err(err) =
+                            output.send(Event::UpdateAppChooser(handle, choices)).await
+                        {
+                            log::error!("Error sending app chooser update: {:?}", err);
                         };
-                        iface
-                            .setting_changed(
-                                iface_ref.signal_emitter(),
-                                APPEARANCE_NAMESPACE,
-                                COLOR_SCHEME_KEY,
-                                zvariant::Value::from(iface.color_scheme as u32),
-                            )
-                            .await?;
-                    }
-                    Event::HighContrast(is_high_contrast) => {
+                    }
+                    Event::CancelAppChooser(handle) => {
+                        if let Err(err) = output.send(Event::CancelAppChooser(handle)).await {
+                            log::error!("Error sending app chooser cancel: {:?}", err);
+                        };
+                    }
+                    Event::GlobalShortcuts(args) => {
+                        if let Err(err) = output.send(Event::GlobalShortcuts(args)).await {
+                            log::error!("Error sending global shortcuts event: {:?}", err);
+                        };
+                    }
+                    Event::CancelGlobalShortcuts(handle) => {
+                        if let Err(err) = output.send(Event::CancelGlobalShortcuts(handle)).await {
+                            log::error!("Error sending global shortcuts cancel: {:?}", err);
+                        };
+                    }
+                    Event::Settings(update) => {
                         let object_server = conn.object_server();
                         let iface_ref = object_server.interface::<_, Settings>(DBUS_PATH).await?;
                         let mut iface = iface_ref.get_mut().await;
-                        iface.contrast = if is_high_contrast {
-                            Contrast::High
-                        } else {
-                            Contrast::NoPreference
-                        };
-
-                        iface
-                            .setting_changed(
-                                iface_ref.signal_emitter(),
-                                APPEARANCE_NAMESPACE,
-                                CONTRAST_KEY,
-                                zvariant::Value::from(iface.contrast as u32),
-                            )
-                            .await?;
+                        iface.update(iface_ref.signal_emitter(), update).await?;
                     }
                     Event::Config(config) => {
                         if let Err(err) = output.send(Event::Config(config)).await {
@@ -0,0 +1,297 @@
+use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::key::Named;
+use cosmic::iced::platform_specific::shell::commands::layer_surface::{
+    destroy_layer_surface, get_layer_surface,
+};
+use cosmic::iced::runtime::platform_specific::wayland::layer_surface::{
+    IcedOutput, SctkLayerSurfaceSettings,
+};
+use cosmic::iced::widget::stack;
+use cosmic::iced::{ContentFit, Length, Limits, window};
+use cosmic::widget::{self, button, space};
+use cosmic_client_toolkit::sctk::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
+use freedesktop_desktop_entry::get_languages_from_env;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::sync::mpsc::Sender;
+use zbus::zvariant;
+
+use crate::app::{CosmicPortal, OutputState};
+use crate::screencast_dialog::{get_desktop_entry, load_desktop_entries};
+use crate::widget::keyboard_wrapper::KeyboardWrapper;
+use crate::{
+    PORTAL_RESPONSE_CANCELLED, PORTAL_RESPONSE_OTHER, PORTAL_RESPONSE_SUCCESS, fl, subscription,
+};
+
+const SET_ON_BACKGROUND: &str = "background";
+/// Not supported on its own. cosmic-greeter's lock screen draws the backgrounds from the
+/// cosmic-bg config, the same as the desktop, and has no setting for a separate wallpaper.
+/// Setting the background, or both, changes the lock screen as well.
+const SET_ON_LOCKSCREEN: &str = "lockscreen";
+const SET_ON_BOTH: &str = "both";
+
+#[derive(zvariant::DeserializeDict, zvariant::Type, Debug)]
+#[zvariant(signature = "a{sv}", rename_all = "kebab-case")]
+struct SetWallpaperOptions {
+    show_preview: Option<bool>,
+    set_on: Option<String>,
+}
+
+/// Outputs to set the wallpaper on
+#[derive(Clone, Debug)]
+pub enum Target {
+    All,
+    Output(String),
+}
+
+pub struct Wallpaper {
+    tx: Sender<subscription::Event>,
+}
+
+impl Wallpaper {
+    pub fn new(tx: Sender<subscription::Event>) -> Self {
+        Self { tx }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Wallpaper")]
+impl Wallpaper {
+    #[zbus(name = "SetWallpaperURI")]
+    async fn set_wallpaper_uri(
+        &self,
+        _handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        uri: &str,
+        options: SetWallpaperOptions,
+    ) -> u32 {
+        log::debug!("Set wallpaper {app_id} {parent_window} {uri} {options:?}");
+        match options.set_on.as_deref().unwrap_or(SET_ON_BOTH) {
+            SET_ON_BACKGROUND | SET_ON_BOTH => {}
+            SET_ON_LOCKSCREEN => {
+                log::error!(
+                    "Setting a lock screen wallpaper on its own isn't supported, as the lock screen shows the desktop background"
+                );
+                return PORTAL_RESPONSE_OTHER;
+            }
+            set_on => {
+                log::error!("Unknown wallpaper 'set-on' value '{}'", set_on);
+                return PORTAL_RESPONSE_OTHER;
+            }
+        }
+
+        let Some(path) = url::Url::parse(uri)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+        else {
+            log::error!("Wallpaper URI '{}' is not a local file", uri);
+            return PORTAL_RESPONSE_OTHER;
+        };
+
+        let target = if options.show_preview.unwrap_or(false) {
+            let locales = get_languages_from_env();
+            let desktop_entries = load_desktop_entries(&locales).await;
+            let app_name = get_desktop_entry(&desktop_entries, app_id)
+                .and_then(|x| Some(x.name(&locales)?.into_owned()));
+
+            let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+            if let Err(err) = self
+                .tx
+                .send(subscription::Event::Wallpaper(Args {
+                    app_name,
+                    image: widget::image::Handle::from_path(&path),
+                    surfaces: HashMap::new(),
+                    tx,
+                }))
+                .await
+            {
+                log::error!("Failed to send wallpaper event, {}", err);
+                return PORTAL_RESPONSE_OTHER;
+            }
+            match rx.recv().await.flatten() {
+                Some(target) => target,
+                None => return PORTAL_RESPONSE_CANCELLED,
+            }
+        } else {
+            Target::All
+        };
+
+        match set_wallpaper(&path, target).await {
+            Ok(()) => PORTAL_RESPONSE_SUCCESS,
+            Err(err) => {
+                log::error!("Failed to set wallpaper: {}", err);
+                PORTAL_RESPONSE_OTHER
+            }
+        }
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+/// Copies the image to the user's backgrounds directory, since the app may
+/// remove the original, and writes it to the cosmic-bg config.
+async fn set_wallpaper(path: &Path, target: Target) -> anyhow::Result<()> {
+    let backgrounds_dir = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("no data directory"))?
+        .join("backgrounds");
+    let path = if path.starts_with(&backgrounds_dir) {
+        path.to_owned()
+    } else {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not a file", path.display()))?;
+        let mut name = jiff::Timestamp::now().as_second().to_string();
+        name.push('-');
+        name.push_str(&file_name.to_string_lossy());
+        let dest = backgrounds_dir.join(name);
+        tokio::fs::create_dir_all(&backgrounds_dir).await?;
+        tokio::fs::copy(path, &dest).await?;
+        dest
+    };
+
+    let context = cosmic_bg_config::context()?;
+    let mut config = cosmic_bg_config::Config::load(&context)?;
+    let source = cosmic_bg_config::Source::Path(path);
+    let entry = match target {
+        Target::All => {
+            context.set_same_on_all(true)?;
+            cosmic_bg_config::Entry::new(cosmic_bg_config::DEFAULT_BACKGROUND.to_string(), source)
+        }
+        Target::Output(name) => {
+            context.set_same_on_all(false)?;
+            cosmic_bg_config::Entry::new(name, source)
+        }
+    };
+    config.set_entry(&context, entry)?;
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct Args {
+    pub app_name: Option<String>,
+    pub image: widget::image::Handle,
+    /// Preview surface for each output, by output name. Filled in by `update_args`.
+    pub surfaces: HashMap<window::Id, String>,
+    pub tx: Sender<Option<Target>>,
+}
+
+impl Args {
+    fn send_response(self, target: Option<Target>) {
+        tokio::spawn(async move {
+            if let Err(err) = self.tx.send(target).await {
+                log::error!("Failed to send wallpaper response: {}", err);
+            }
+        });
+    }
+
+    fn destroy_surfaces(&self) -> cosmic::Task<crate::app::Msg> {
+        cosmic::Task::batch(self.surfaces.keys().map(|id| destroy_layer_surface(*id)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Msg {
+    SetAll,
+    SetOutput(String),
+    Cancel,
+}
+
+pub(crate) fn view(portal: &CosmicPortal, id: window::Id) -> cosmic::Element<'_, Msg> {
+    let Some((args, output_name)) = portal
+        .wallpaper_args
+        .as_ref()
+        .and_then(|args| Some((args, args.surfaces.get(&id)?)))
+    else {
+        return space::horizontal().width(Length::Fixed(1.0)).into();
+    };
+
+    let unknown = fl!("unknown-application");
+    let app_name = args.app_name.as_deref().unwrap_or(&unknown);
+
+    let cancel_button = button::standard(fl!("cancel")).on_press(Msg::Cancel);
+    let mut dialog = widget::dialog()
+        .title(fl!("set-wallpaper"))
+        .body(fl!("set-wallpaper", "description", app_name = app_name))
+        .secondary_action(cancel_button);
+    dialog = if args.surfaces.len() > 1 {
+        dialog
+            .primary_action(
+                button::suggested(fl!("set-wallpaper", "all-displays")).on_press(Msg::SetAll),
+            )
+            .tertiary_action(
+                button::text(fl!("set-wallpaper", "this-display"))
+                    .on_press(Msg::SetOutput(output_name.clone())),
+            )
+    } else {
+        dialog.primary_action(button::suggested(fl!("set")).on_press(Msg::SetAll))
+    };
+
+    let preview = widget::image(args.image.clone())
+        .content_fit(ContentFit::Cover)
+        .width(Length::Fill)
+        .height(Length::Fill);
+    let dialog = widget::container(dialog)
+        .center_x(Length::Fill)
+        .center_y(Length::Fill);
+
+    KeyboardWrapper::new(stack![preview, dialog], |key, _| match key {
+        Key::Named(Named::Enter) => Some(Msg::SetAll),
+        Key::Named(Named::Escape) => Some(Msg::Cancel),
+        _ => None,
+    })
+    .into()
+}
+
+pub fn update_msg(portal: &mut CosmicPortal, msg: Msg) -> cosmic::Task<crate::app::Msg> {
+    let Some(args) = portal.wallpaper_args.take() else {
+        log::error!("Failed to find wallpaper Args for {:?} message.", msg);
+        return cosmic::Task::none();
+    };
+    let cmd = args.destroy_surfaces();
+    match msg {
+        Msg::SetAll => args.send_response(Some(Target::All)),
+        Msg::SetOutput(name) => args.send_response(Some(Target::Output(name))),
+        Msg::Cancel => args.send_response(None),
+    }
+    cmd
+}
+
+pub fn update_args(portal: &mut CosmicPortal, mut args: Args) -> cosmic::Task<crate::app::Msg> {
+    let mut cmds = Vec::with_capacity(portal.outputs.len() + 1);
+
+    // Only one preview can be shown at a time; cancel the previous request
+    if let Some(prev) = portal.wallpaper_args.take() {
+        cmds.push(prev.destroy_surfaces());
+        prev.send_response(None);
+    }
+
+    for OutputState { output, name, .. } in &portal.outputs {
+        let id = window::Id::unique();
+        args.surfaces.insert(id, name.clone());
+        cmds.push(get_layer_surface(SctkLayerSurfaceSettings {
+            id,
+            layer: Layer::Overlay,
+            keyboard_interactivity: KeyboardInteractivity::Exclusive,
+            input_zone: None,
+            anchor: Anchor::all(),
+            output: IcedOutput::Output(output.clone()),
+            namespace: "wallpaper preview".to_string(),
+            size: Some((None, None)),
+            exclusive_zone: -1,
+            size_limits: Limits::NONE.min_height(1.0).min_width(1.0),
+            ..Default::default()
+        }));
+    }
+
+    if args.surfaces.is_empty() {
+        log::error!("No outputs to show wallpaper preview on");
+        args.send_response(None);
+        return cosmic::Task::batch(cmds);
+    }
+
+    portal.wallpaper_args = Some(args);
+    cosmic::Task::batch(cmds)
+}
@@ -0,0 +1,218 @@
+// Cursor position and image for a capture source, used for cursor metadata in screencasts
+
+use cosmic_client_toolkit::screencopy::{
+    CaptureCursorSession, CaptureSource, Rect, ScreencopyCursorSessionData,
+    ScreencopyCursorSessionDataExt,
+};
+use futures::executor::block_on;
+use futures::future::{Either, select};
+use std::pin::pin;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use tokio::sync::Notify;
+use wayland_client::protocol::{wl_pointer, wl_shm};
+use wayland_client::{Connection, Dispatch, QueueHandle};
+
+use super::{AppData, Session, SessionData, WaylandHelper};
+use crate::buffer;
+
+#[derive(Default)]
+pub struct CursorState {
+    /// Position in buffer coordinates of the capture source, if the cursor is over it
+    pub position: Option<(i32, i32)>,
+    pub hotspot: (i32, i32),
+    /// Latest cursor image, in RGBA
+    pub image: Option<Arc<image::RgbaImage>>,
+    /// Incremented for each new cursor image, starting at 1 for the first
+    pub serial: u32,
+}
+
+struct Shared {
+    state: Mutex<CursorState>,
+    /// Notified when `state` changes
+    changed: Notify,
+    /// Notified when the `CursorSession` is dropped, to stop capturing images
+    closed: Notify,
+}
+
+pub struct CursorSession {
+    _cursor_session: CaptureCursorSession,
+    shared: Arc<Shared>,
+}
+
+impl CursorSession {
+    pub fn state(&self) -> MutexGuard<'_, CursorState> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// Completes once the state changed since the last call
+    pub async fn changed(&self) {
+        self.shared.changed.notified().await
+    }
+}
+
+impl Drop for CursorSession {
+    fn drop(&mut self) {
+        self.shared.closed.notify_one();
+    }
+}
+
+pub(super) struct CursorSessionData {
+    shared: Weak<Shared>,
+    session_data: ScreencopyCursorSessionData,
+}
+
+impl CursorSessionData {
+    pub(super) fn update<F: FnOnce(&mut CursorState)>(&self, f: F) {
+        if let Some(shared) = self.shared.upgrade() {
+            f(&mut shared.state.lock().unwrap());
+            shared.changed.notify_one();
+        }
+    }
+}
+
+impl ScreencopyCursorSessionDataExt for CursorSessionData {
+    fn screencopy_cursor_session_data(&self) -> &ScreencopyCursorSessionData {
+        &self.session_data
+    }
+}
+
+impl WaylandHelper {
+    /// Tracks the cursor over `source`. Returns `None` if the compositor doesn't
+    /// support cursor capture, or there is no pointer.
+    pub fn capture_cursor_session(&self, source: &CaptureSource) -> Option<CursorSession> {
+        let pointer = self.inner.pointer.as_ref()?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(CursorState::default()),
+            changed: Notify::new(),
+            closed: Notify::new(),
+        });
+        let cursor_session = self
+            .inner
+            .capturer
+            .create_cursor_session(
+                source,
+                pointer,
+                &self.inner.qh,
+                CursorSessionData {
+                    shared: Arc::downgrade(&shared),
+                    session_data: Default::default(),
+                },
+            )
+            .inspect_err(|err| log::error!("Failed to create cursor session: {:?}", err))
+            .ok()?;
+
+        let image_session = Session(Arc::new_cyclic(|weak_session| super::SessionInner {
+            wayland_helper: self.clone(),
+            capture_session: cursor_session.capture_session(
+                &self.inner.qh,
+                SessionData {
+                    session: weak_session.clone(),
+                    session_data: Default::default(),
+                },
+            ),
+            condvar: Default::default(),
+            state: Default::default(),
+        }));
+        if let Err(err) = self.inner.conn.flush() {
+            log::error!("Failed to flush Wayland connection: {}", err);
+            return None;
+        }
+
+        let wayland_helper = self.clone();
+        let thread_shared = shared.clone();
+        thread::spawn(move || capture_cursor_images(wayland_helper, image_session, thread_shared));
+
+        Some(CursorSession {
+            _cursor_session: cursor_session,
+            shared,
+        })
+    }
+}
+
+/// Runs `future` until it completes, or `None` once the `CursorSession` is dropped
+fn until_closed<F: Future>(shared: &Shared, future: F) -> Option<F::Output> {
+    match block_on(select(pin!(future), pin!(shared.closed.notified()))) {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// Captures a new cursor image whenever the compositor reports a change to it.
+///
+/// Captures only complete once the cursor image is damaged, so this runs on its
+/// own thread, until the `CursorSession` is dropped.
+fn capture_cursor_images(wayland_helper: WaylandHelper, session: Session, shared: Arc<Shared>) {
+    let mut buffer = None;
+    loop {
+        let Some(Some((width, height))) = until_closed(
+            &shared,
+            session.wait_for_formats(|formats| formats.buffer_size),
+        ) else {
+            break;
+        };
+        let (fd, wl_buffer) = match buffer.take() {
+            Some((size, fd, wl_buffer)) if size == (width, height) => (fd, wl_buffer),
+            old => {
+                if let Some((_, _, wl_buffer)) = old {
+                    wl_buffer.destroy();
+                }
+                let fd = buffer::create_memfd(width, height);
+                let wl_buffer = wayland_helper.create_shm_buffer(
+                    &fd,
+                    width,
+                    height,
+                    width * 4,
+                    wl_shm::Format::Abgr8888,
+                );
+                (fd, wl_buffer)
+            }
+        };
+
+        let damage = &[Rect {
+            x: 0,
+            y: 0,
+            width: width as i32,
+            height: height as i32,
+        }];
+        let Some(res) = until_closed(&shared, session.capture_wl_buffer(&wl_buffer, damage)) else {
+            wl_buffer.destroy();
+            break;
+        };
+        if res.is_ok() {
+            let image = super::ShmImage {
+                fd: &fd,
+                width,
+                height,
+                transform: wayland_client::protocol::wl_output::Transform::Normal,
+            }
+            .image();
+            match image {
+                Ok(image) => {
+                    let mut state = shared.state.lock().unwrap();
+                    state.image = Some(Arc::new(image));
+                    state.serial = state.serial.wrapping_add(1).max(1);
+                    drop(state);
+                    shared.changed.notify_one();
+                }
+                Err(err) => log::error!("Failed to read cursor image: {}", err),
+            }
+        } else if session.is_stopped() {
+            wl_buffer.destroy();
+            break;
+        }
+        buffer = Some(((width, height), fd, wl_buffer));
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for AppData {
+    fn event(
+        _app_data: &mut Self,
+        _pointer: &wl_pointer::WlPointer,
+        _event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
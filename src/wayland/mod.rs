@@ -1,7 +1,7 @@
 use cosmic_client_toolkit::screencopy::{
-    CaptureFrame, CaptureOptions, CaptureSession, Capturer, FailureReason, Formats, Frame,
-    ScreencopyFrameData, ScreencopyFrameDataExt, ScreencopyHandler, ScreencopySessionData,
-    ScreencopySessionDataExt, ScreencopyState,
+    CaptureCursorSession, CaptureFrame, CaptureOptions, CaptureSession, Capturer, FailureReason,
+    Formats, Frame, ScreencopyFrameData, ScreencopyFrameDataExt, ScreencopyHandler,
+    ScreencopySessionData, ScreencopySessionDataExt, ScreencopyState,
 };
 use cosmic_client_toolkit::sctk::dmabuf::{
     DmabufFeedback, DmabufFormat, DmabufHandler, DmabufState,
@@ -19,7 +19,7 @@ use std::os::fd::{AsFd, OwnedFd};
 use std::sync::{Arc, Condvar, Mutex, Weak};
 use std::thread;
 use wayland_client::globals::registry_queue_init;
-use wayland_client::protocol::{wl_buffer, wl_output, wl_shm, wl_shm_pool};
+use wayland_client::protocol::{wl_buffer, wl_output, wl_pointer, wl_seat, wl_shm, wl_shm_pool};
 use wayland_client::{Connection, Dispatch, QueueHandle, WEnum};
 use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1;
 use wayland_protocols::ext::workspace::v1::client::ext_workspace_handle_v1;
@@ -28,13 +28,19 @@ use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_buffer_params_v1
 };
 use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_feedback_v1::ZwpLinuxDmabufFeedbackV1;
 use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1;
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1;
+use wayland_protocols_wlr::virtual_pointer::v1::client::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1;
 
 pub use cosmic_client_toolkit::screencopy::{CaptureSource, Rect};
+pub use cursor::CursorSession;
+pub use virtual_input::{VirtualKeyboard, VirtualPointer};
 
 use crate::buffer;
 
+mod cursor;
 mod gbm_devices;
 mod toplevel;
+mod virtual_input;
 mod workspaces;
 
 #[derive(Clone)]
@@ -82,6 +88,10 @@ struct WaylandHelperInner {
     wl_shm: wl_shm::WlShm,
     dmabuf: Mutex<Option<DmabufHelper>>,
     zwp_dmabuf: Option<ZwpLinuxDmabufV1>,
+    seat: Option<wl_seat::WlSeat>,
+    pointer: Option<wl_pointer::WlPointer>,
+    virtual_pointer_manager: Option<ZwlrVirtualPointerManagerV1>,
+    virtual_keyboard_manager: Option<ZwpVirtualKeyboardManagerV1>,
 }
 
 // TODO seperate state object from what is passed to threads
@@ -237,6 +247,12 @@ impl WaylandHelper {
         let screencopy_state = ScreencopyState::new(&globals, &qh);
         let shm_state = Shm::bind(&globals, &qh).unwrap();
         let zwp_dmabuf = globals.bind(&qh, 4..=4, sctk::globals::GlobalData).ok();
+        // Used for remote desktop input
+        let seat: Option<wl_seat::WlSeat> = globals.bind(&qh, 1..=1, ()).ok();
+        // Used for cursor capture
+        let pointer = seat.as_ref().map(|seat| seat.get_pointer(&qh, ()));
+        let virtual_pointer_manager = globals.bind(&qh, 1..=2, ()).ok();
+        let virtual_keyboard_manager = globals.bind(&qh, 1..=1, ()).ok();
         let wayland_helper = WaylandHelper {
             inner: Arc::new(WaylandHelperInner {
                 conn,
@@ -249,6 +265,10 @@ impl WaylandHelper {
                 wl_shm: shm_state.wl_shm().clone(),
                 dmabuf: Mutex::new(None),
                 zwp_dmabuf,
+                seat,
+                pointer,
+                virtual_pointer_manager,
+                virtual_keyboard_manager,
             }),
         };
         let dmabuf_state = DmabufState::new(&globals, &qh);
@@ -317,29 +337,25 @@ impl WaylandHelper {
         }
     }
 
-    pub fn capture_output_toplevels_shm<'a>(
-        &'a self,
-        output: &wl_output::WlOutput,
-        overlay_cursor: bool,
-    ) -> impl Stream<Item = ShmImage<OwnedFd>> + 'a {
-        // get the active workspace for this output
-        // get the toplevels for that workspace
-        // capture each toplevel
-
-        let toplevels = self
-            .inner
-            .output_toplevels
-            .lock()
-            .unwrap()
-            .get(output)
-            .cloned()
-            .unwrap_or_default();
+    /// Toplevels on the active workspace of each output
+    pub fn output_toplevels(
+        &self,
+    ) -> HashMap<wl_output::WlOutput, Vec<ExtForeignToplevelHandleV1>> {
+        self.inner.output_toplevels.lock().unwrap().clone()
+    }
 
+    /// Captures each toplevel whole, including the parts on other outputs
+    pub fn capture_toplevels_shm(
+        &self,
+        toplevels: Vec<ExtForeignToplevelHandleV1>,
+        overlay_cursor: bool,
+    ) -> impl Stream<Item = (ExtForeignToplevelHandleV1, ShmImage<OwnedFd>)> + '_ {
         toplevels
             .into_iter()
-            .map(|foreign_toplevel| {
+            .map(|foreign_toplevel| async move {
                 let source = CaptureSource::Toplevel(foreign_toplevel.clone());
-                self.capture_source_shm(source, overlay_cursor)
+                let image = self.capture_source_shm(source, overlay_cursor).await?;
+                Some((foreign_toplevel, image))
             })
             .collect::<FuturesOrdered<_>>()
             .filter_map(|x| async { x })
@@ -384,7 +400,6 @@ impl WaylandHelper {
         overlay_cursor: bool,
     ) -> Option<ShmImage<OwnedFd>> {
         // XXX error type?
-        // TODO: way to get cursor metadata?
 
         let session = self.capture_source_session(source, overlay_cursor);
 
@@ -613,6 +628,43 @@ impl ScreencopyHandler for AppData {
         // TODO signal users of session in some way?
     }
 
+    fn cursor_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        cursor_session: &CaptureCursorSession,
+    ) {
+        if let Some(data) = cursor_session.data::<cursor::CursorSessionData>() {
+            data.update(|state| state.position = None);
+        }
+    }
+
+    fn cursor_position(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        cursor_session: &CaptureCursorSession,
+        x: i32,
+        y: i32,
+    ) {
+        if let Some(data) = cursor_session.data::<cursor::CursorSessionData>() {
+            data.update(|state| state.position = Some((x, y)));
+        }
+    }
+
+    fn cursor_hotspot(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        cursor_session: &CaptureCursorSession,
+        x: i32,
+        y: i32,
+    ) {
+        if let Some(data) = cursor_session.data::<cursor::CursorSessionData>() {
+            data.update(|state| state.hotspot = (x, y));
+        }
+    }
+
     fn ready(
         &mut self,
         _conn: &Connection,
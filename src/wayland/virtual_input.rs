@@ -0,0 +1,356 @@
+// Virtual pointer and keyboard devices, used to inject input for remote desktop sessions
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::time::Instant;
+use wayland_client::protocol::{wl_output, wl_pointer, wl_seat};
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+    zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1,
+    zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+use xkbcommon::xkb;
+
+use super::{AppData, WaylandHelper};
+
+const KEYMAP_FORMAT_XKB_V1: u32 = 1;
+// Evdev keycodes are offset by 8 from xkb keycodes
+const XKB_KEYCODE_OFFSET: u32 = 8;
+const KEY_LEFTSHIFT: u32 = 42;
+
+/// Milliseconds since the device was created, for event timestamps
+fn timestamp(start: Instant) -> u32 {
+    start.elapsed().as_millis() as u32
+}
+
+pub struct VirtualPointer {
+    wayland_helper: WaylandHelper,
+    pointer: ZwlrVirtualPointerV1,
+    start: Instant,
+}
+
+impl VirtualPointer {
+    fn frame(&self) {
+        self.pointer.frame();
+        let _ = self.wayland_helper.inner.conn.flush();
+    }
+
+    pub fn motion(&self, dx: f64, dy: f64) {
+        self.pointer.motion(timestamp(self.start), dx, dy);
+        self.frame();
+    }
+
+    /// Moves to a position within `extent`, which is mapped to the output the
+    /// pointer was created for.
+    pub fn motion_absolute(&self, x: f64, y: f64, extent: (u32, u32)) {
+        let x = x.clamp(0., extent.0 as f64) as u32;
+        let y = y.clamp(0., extent.1 as f64) as u32;
+        self.pointer
+            .motion_absolute(timestamp(self.start), x, y, extent.0, extent.1);
+        self.frame();
+    }
+
+    pub fn button(&self, button: u32, pressed: bool) {
+        let state = if pressed {
+            wl_pointer::ButtonState::Pressed
+        } else {
+            wl_pointer::ButtonState::Released
+        };
+        self.pointer.button(timestamp(self.start), button, state);
+        self.frame();
+    }
+
+    pub fn axis(&self, dx: f64, dy: f64, finish: bool) {
+        let time = timestamp(self.start);
+        self.pointer.axis_source(wl_pointer::AxisSource::Finger);
+        for (axis, value) in [
+            (wl_pointer::Axis::HorizontalScroll, dx),
+            (wl_pointer::Axis::VerticalScroll, dy),
+        ] {
+            if value != 0. {
+                self.pointer.axis(time, axis, value);
+            } else if finish {
+                self.pointer.axis_stop(time, axis);
+            }
+        }
+        self.frame();
+    }
+
+    pub fn axis_discrete(&self, axis: wl_pointer::Axis, steps: i32) {
+        // Matches the scroll distance of one wheel click in libinput
+        const STEP_DISTANCE: f64 = 15.;
+        self.pointer.axis_source(wl_pointer::AxisSource::Wheel);
+        self.pointer.axis_discrete(
+            timestamp(self.start),
+            axis,
+            steps as f64 * STEP_DISTANCE,
+            steps,
+        );
+        self.frame();
+    }
+}
+
+impl Drop for VirtualPointer {
+    fn drop(&mut self) {
+        self.pointer.destroy();
+        let _ = self.wayland_helper.inner.conn.flush();
+    }
+}
+
+/// How a key in the keymap changes modifier state
+#[derive(Clone, Copy, Default)]
+struct ModifierKey {
+    depressed: u32,
+    locked: u32,
+}
+
+pub struct VirtualKeyboard {
+    wayland_helper: WaylandHelper,
+    keyboard: ZwpVirtualKeyboardV1,
+    start: Instant,
+    /// Null terminated keymap text, and its size
+    keymap: (File, u32),
+    /// Evdev keycode and whether shift is needed, for each keysym in the keymap
+    keysyms: HashMap<u32, (u32, bool)>,
+    modifier_keys: HashMap<u32, ModifierKey>,
+    pressed: HashSet<u32>,
+    locked: u32,
+}
+
+impl VirtualKeyboard {
+    /// The keymap of the keyboard, as a file descriptor and size
+    pub fn keymap(&self) -> (BorrowedFd<'_>, u32) {
+        (self.keymap.0.as_fd(), self.keymap.1)
+    }
+
+    /// Presses or releases a key, by evdev keycode
+    pub fn key(&mut self, keycode: u32, pressed: bool) {
+        self.keyboard
+            .key(timestamp(self.start), keycode, u32::from(pressed));
+        self.update_modifiers(keycode, pressed);
+        let _ = self.wayland_helper.inner.conn.flush();
+    }
+
+    /// Presses or releases the key producing `keysym` in the keymap, holding
+    /// shift if the keysym is on the second level.
+    ///
+    /// Returns `false` if no key produces the keysym.
+    pub fn keysym(&mut self, keysym: u32, pressed: bool) -> bool {
+        let Some(&(keycode, shift)) = self.keysyms.get(&keysym) else {
+            return false;
+        };
+        let shift = shift && !self.pressed.contains(&KEY_LEFTSHIFT);
+        if shift && pressed {
+            self.key(KEY_LEFTSHIFT, true);
+        }
+        self.key(keycode, pressed);
+        if shift && pressed {
+            self.key(KEY_LEFTSHIFT, false);
+        }
+        true
+    }
+
+    fn update_modifiers(&mut self, keycode: u32, pressed: bool) {
+        if pressed {
+            self.pressed.insert(keycode);
+        } else {
+            self.pressed.remove(&keycode);
+        }
+        let Some(modifier) = self.modifier_keys.get(&keycode) else {
+            return;
+        };
+        if pressed {
+            self.locked ^= modifier.locked;
+        }
+        let depressed = self
+            .pressed
+            .iter()
+            .filter_map(|keycode| self.modifier_keys.get(keycode))
+            .fold(0, |mask, modifier| mask | modifier.depressed);
+        self.keyboard.modifiers(depressed, 0, self.locked, 0);
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        self.keyboard.destroy();
+        let _ = self.wayland_helper.inner.conn.flush();
+    }
+}
+
+/// Compiles the default keymap, honoring the `XKB_DEFAULT_*` environment variables
+fn default_keymap() -> Option<xkb::Keymap> {
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    xkb::Keymap::new_from_names(&context, "", "", "", "", None, xkb::KEYMAP_COMPILE_NO_FLAGS)
+}
+
+fn keymap_keysyms(keymap: &xkb::Keymap) -> HashMap<u32, (u32, bool)> {
+    let mut keysyms = HashMap::new();
+    for level in 0..2 {
+        for keycode in keymap.min_keycode().raw()..=keymap.max_keycode().raw() {
+            let key = xkb::Keycode::new(keycode);
+            for keysym in keymap.key_get_syms_by_level(key, 0, level) {
+                keysyms
+                    .entry(keysym.raw())
+                    .or_insert((keycode - XKB_KEYCODE_OFFSET, level == 1));
+            }
+        }
+    }
+    keysyms
+}
+
+fn keymap_modifier_keys(keymap: &xkb::Keymap) -> HashMap<u32, ModifierKey> {
+    let mut modifier_keys = HashMap::new();
+    for keycode in keymap.min_keycode().raw()..=keymap.max_keycode().raw() {
+        let key = xkb::Keycode::new(keycode);
+        let mut state = xkb::State::new(keymap);
+        state.update_key(key, xkb::KeyDirection::Down);
+        let depressed = state.serialize_mods(xkb::STATE_MODS_DEPRESSED);
+        state.update_key(key, xkb::KeyDirection::Up);
+        let locked = state.serialize_mods(xkb::STATE_MODS_LOCKED);
+        if depressed != 0 || locked != 0 {
+            modifier_keys.insert(
+                keycode - XKB_KEYCODE_OFFSET,
+                ModifierKey { depressed, locked },
+            );
+        }
+    }
+    modifier_keys
+}
+
+impl WaylandHelper {
+    pub fn has_virtual_pointer(&self) -> bool {
+        self.inner.virtual_pointer_manager.is_some()
+    }
+
+    pub fn has_virtual_keyboard(&self) -> bool {
+        self.inner.seat.is_some() && self.inner.virtual_keyboard_manager.is_some()
+    }
+
+    /// Creates a virtual pointer. Absolute motion is mapped to `output`, if given.
+    pub fn create_virtual_pointer(
+        &self,
+        output: Option<&wl_output::WlOutput>,
+    ) -> Option<VirtualPointer> {
+        let manager = self.inner.virtual_pointer_manager.as_ref()?;
+        let pointer = if manager.version() >= 2 {
+            manager.create_virtual_pointer_with_output(
+                self.inner.seat.as_ref(),
+                output,
+                &self.inner.qh,
+                (),
+            )
+        } else {
+            manager.create_virtual_pointer(self.inner.seat.as_ref(), &self.inner.qh, ())
+        };
+        let _ = self.inner.conn.flush();
+        Some(VirtualPointer {
+            wayland_helper: self.clone(),
+            pointer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn create_virtual_keyboard(&self) -> Option<VirtualKeyboard> {
+        let manager = self.inner.virtual_keyboard_manager.as_ref()?;
+        let seat = self.inner.seat.as_ref()?;
+
+        let Some(keymap) = default_keymap() else {
+            log::error!("Failed to compile keymap for virtual keyboard");
+            return None;
+        };
+        let keymap_string = keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1);
+        let fd =
+            rustix::fs::memfd_create(c"virtual-keyboard-keymap", rustix::fs::MemfdFlags::CLOEXEC)
+                .inspect_err(|err| log::error!("Failed to create keymap memfd: {}", err))
+                .ok()?;
+        let mut file = File::from(fd);
+        // The keymap is sent null terminated
+        file.write_all(keymap_string.as_bytes())
+            .and_then(|()| file.write_all(&[0]))
+            .inspect_err(|err| log::error!("Failed to write keymap: {}", err))
+            .ok()?;
+
+        let size = keymap_string.len() as u32 + 1;
+        let keyboard = manager.create_virtual_keyboard(seat, &self.inner.qh, ());
+        keyboard.keymap(KEYMAP_FORMAT_XKB_V1, file.as_fd(), size);
+        let _ = self.inner.conn.flush();
+
+        Some(VirtualKeyboard {
+            wayland_helper: self.clone(),
+            keyboard,
+            start: Instant::now(),
+            keymap: (file, size),
+            keysyms: keymap_keysyms(&keymap),
+            modifier_keys: keymap_modifier_keys(&keymap),
+            pressed: HashSet::new(),
+            locked: 0,
+        })
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for AppData {
+    fn event(
+        _app_data: &mut Self,
+        _seat: &wl_seat::WlSeat,
+        _event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerManagerV1, ()> for AppData {
+    fn event(
+        _app_data: &mut Self,
+        _manager: &ZwlrVirtualPointerManagerV1,
+        _event: <ZwlrVirtualPointerManagerV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerV1, ()> for AppData {
+    fn event(
+        _app_data: &mut Self,
+        _pointer: &ZwlrVirtualPointerV1,
+        _event: <ZwlrVirtualPointerV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwpVirtualKeyboardManagerV1, ()> for AppData {
+    fn event(
+        _app_data: &mut Self,
+        _manager: &ZwpVirtualKeyboardManagerV1,
+        _event: <ZwpVirtualKeyboardManagerV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwpVirtualKeyboardV1, ()> for AppData {
+    fn event(
+        _app_data: &mut Self,
+        _keyboard: &ZwpVirtualKeyboardV1,
+        _event: <ZwpVirtualKeyboardV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
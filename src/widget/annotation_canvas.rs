@@ -0,0 +1,232 @@
+//! Shows the screenshot being annotated, scaled to fit, with the shape being
+//! drawn over it, and reports pointer presses and drags in image pixel coordinates.
+
+use cosmic::Element;
+use cosmic::iced::core::layout::{self, Node};
+use cosmic::iced::core::widget::{Tree, tree};
+use cosmic::iced::core::{
+    Clipboard, ContentFit, Event, Layout, Length, Point, Rectangle, Shell, Size, Widget, mouse,
+    renderer,
+};
+use cosmic::widget::image;
+
+use crate::annotation::{self, Region};
+
+#[derive(Clone, Copy, Debug)]
+pub enum CanvasEvent {
+    /// Pointer pressed at a point, with the number of image pixels per logical pixel
+    Press(annotation::Point, f32),
+    Drag(annotation::Point),
+    Release,
+}
+
+pub struct AnnotationCanvas<'a, Msg> {
+    /// The image, followed by the overlay if there is one
+    elements: Vec<Element<'a, Msg>>,
+    /// Part of the image the overlay covers
+    overlay: Option<Region>,
+    image_size: Size,
+    on_event: Box<dyn Fn(CanvasEvent) -> Msg + 'a>,
+}
+
+impl<'a, Msg> AnnotationCanvas<'a, Msg>
+where
+    Msg: 'static + Clone,
+{
+    pub fn new(
+        handle: image::Handle,
+        (width, height): (u32, u32),
+        overlay: Option<(image::Handle, Region)>,
+        on_event: impl Fn(CanvasEvent) -> Msg + 'a,
+    ) -> Self {
+        let mut elements = vec![
+            image::Image::new(handle)
+                .content_fit(ContentFit::Contain)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+        ];
+        let overlay = overlay.map(|(handle, region)| {
+            elements.push(
+                image::Image::new(handle)
+                    .content_fit(ContentFit::Fill)
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .into(),
+            );
+            region
+        });
+        Self {
+            elements,
+            overlay,
+            image_size: Size::new(width as f32, height as f32),
+            on_event: Box::new(on_event),
+        }
+    }
+
+    /// Area of `bounds` the image is drawn in, and its scale
+    fn image_bounds(&self, bounds: Rectangle) -> (Rectangle, f32) {
+        let scale =
+            (bounds.width / self.image_size.width).min(bounds.height / self.image_size.height);
+        let size = self.image_size * scale;
+        let position = Point::new(
+            bounds.x + (bounds.width - size.width) / 2.0,
+            bounds.y + (bounds.height - size.height) / 2.0,
+        );
+        (Rectangle::new(position, size), scale)
+    }
+
+    fn image_point(&self, bounds: Rectangle, position: Point) -> annotation::Point {
+        let (image_bounds, scale) = self.image_bounds(bounds);
+        (
+            ((position.x - image_bounds.x) / scale).clamp(0.0, self.image_size.width),
+            ((position.y - image_bounds.y) / scale).clamp(0.0, self.image_size.height),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pressed: bool,
+}
+
+impl<'a, Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for AnnotationCanvas<'a, Msg>
+where
+    Msg: 'static + Clone,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        self.elements.iter().map(Tree::new).collect()
+    }
+
+    fn diff(&mut self, tree: &mut Tree) {
+        tree.diff_children(&mut self.elements);
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        renderer: &cosmic::Renderer,
+        limits: &layout::Limits,
+    ) -> Node {
+        let size = limits.resolve(Length::Fill, Length::Fill, Size::ZERO);
+        let mut nodes =
+            vec![
+                self.elements[0]
+                    .as_widget_mut()
+                    .layout(&mut tree.children[0], renderer, limits),
+            ];
+        if let Some(region) = self.overlay {
+            let (image_bounds, scale) = self.image_bounds(Rectangle::with_size(size));
+            let overlay_size = Size::new(region.width as f32, region.height as f32) * scale;
+            let node = self.elements[1]
+                .as_widget_mut()
+                .layout(
+                    &mut tree.children[1],
+                    renderer,
+                    &layout::Limits::new(Size::ZERO, overlay_size),
+                )
+                .move_to(Point::new(
+                    image_bounds.x + region.x as f32 * scale,
+                    image_bounds.y + region.y as f32 * scale,
+                ));
+            nodes.push(node);
+        }
+        Node::with_children(size, nodes)
+    }
+
+    fn mouse_interaction(
+        &self,
+        _tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &cosmic::Renderer,
+    ) -> mouse::Interaction {
+        let (image_bounds, _) = self.image_bounds(layout.bounds());
+        if cursor.is_over(image_bounds) {
+            mouse::Interaction::Crosshair
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &cosmic::Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Msg>,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let state = tree.state.downcast_mut::<State>();
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let (image_bounds, scale) = self.image_bounds(bounds);
+                if let Some(position) = cursor.position_over(image_bounds) {
+                    state.pressed = true;
+                    let point = self.image_point(bounds, position);
+                    shell.publish((self.on_event)(CanvasEvent::Press(point, 1.0 / scale)));
+                    shell.capture_event();
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) if state.pressed => {
+                let point = self.image_point(bounds, *position);
+                shell.publish((self.on_event)(CanvasEvent::Drag(point)));
+                shell.capture_event();
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) if state.pressed => {
+                state.pressed = false;
+                shell.publish((self.on_event)(CanvasEvent::Release));
+                shell.capture_event();
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut cosmic::Renderer,
+        theme: &cosmic::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        for ((element, tree), layout) in self
+            .elements
+            .iter()
+            .zip(&tree.children)
+            .zip(layout.children())
+        {
+            element
+                .as_widget()
+                .draw(tree, renderer, theme, style, layout, cursor, viewport);
+        }
+    }
+}
+
+impl<'a, Msg> From<AnnotationCanvas<'a, Msg>> for Element<'a, Msg>
+where
+    Msg: 'static + Clone,
+{
+    fn from(w: AnnotationCanvas<'a, Msg>) -> Element<'a, Msg> {
+        Element::new(w)
+    }
+}
@@ -0,0 +1,188 @@
+//! Full screen color picker shown over a frozen screenshot of one output. The
+//! pixel under the cursor is magnified in a loupe together with its value.
+
+use cosmic::Element;
+use cosmic::iced::core::layout::{self, Node};
+use cosmic::iced::core::widget::{Tree, tree};
+use cosmic::iced::core::{
+    Clipboard, Event, Layout, Length, Point, Rectangle, Shell, Size, Widget, mouse, renderer,
+};
+use cosmic::widget::image;
+use image::RgbaImage;
+
+use super::loupe;
+
+pub struct ColorPicker<'a, Msg> {
+    bg_element: Element<'a, Msg>,
+    image: &'a RgbaImage,
+    on_pick: Box<dyn Fn([u8; 3]) -> Msg + 'a>,
+}
+
+impl<'a, Msg> ColorPicker<'a, Msg>
+where
+    Msg: 'static + Clone,
+{
+    pub fn new(
+        image: &'a RgbaImage,
+        handle: image::Handle,
+        on_pick: impl Fn([u8; 3]) -> Msg + 'a,
+    ) -> Self {
+        Self {
+            bg_element: image::Image::new(handle)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+            image,
+            on_pick: Box::new(on_pick),
+        }
+    }
+
+    fn color_at(&self, bounds: Rectangle, position: Point) -> [u8; 3] {
+        let (x, y) = loupe::image_pixel(self.image, bounds, position);
+        let [r, g, b, _] = self.image.get_pixel(x, y).0;
+        [r, g, b]
+    }
+}
+
+/// Formats a color as shown in the loupe, e.g. `#3584E4  53, 132, 228`
+pub fn color_label([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02X}{g:02X}{b:02X}  {r}, {g}, {b}")
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cursor: Option<Point>,
+}
+
+impl<'a, Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for ColorPicker<'a, Msg>
+where
+    Msg: 'static + Clone,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.bg_element)]
+    }
+
+    fn diff(&mut self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_mut(&mut self.bg_element));
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        renderer: &cosmic::Renderer,
+        limits: &layout::Limits,
+    ) -> Node {
+        let bg_node =
+            self.bg_element
+                .as_widget_mut()
+                .layout(&mut tree.children[0], renderer, limits);
+        Node::with_children(
+            limits.resolve(Length::Fill, Length::Fill, Size::ZERO),
+            vec![bg_node],
+        )
+    }
+
+    fn mouse_interaction(
+        &self,
+        _tree: &Tree,
+        _layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &cosmic::Renderer,
+    ) -> mouse::Interaction {
+        mouse::Interaction::Crosshair
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &cosmic::Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Msg>,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let state = tree.state.downcast_mut::<State>();
+        match event {
+            Event::Mouse(mouse::Event::CursorMoved { .. })
+            | Event::Mouse(mouse::Event::CursorEntered) => {
+                state.cursor = cursor.position_over(bounds);
+                shell.request_redraw();
+            }
+            Event::Mouse(mouse::Event::CursorLeft) => {
+                state.cursor = None;
+                shell.request_redraw();
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                if let Some(position) = cursor.position_over(bounds) {
+                    shell.publish((self.on_pick)(self.color_at(bounds, position)));
+                    shell.capture_event();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut cosmic::Renderer,
+        theme: &cosmic::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        if let Some(bg_layout) = layout.children().next() {
+            self.bg_element.as_widget().draw(
+                &tree.children[0],
+                renderer,
+                theme,
+                style,
+                bg_layout,
+                cursor,
+                viewport,
+            );
+        }
+
+        let state = tree.state.downcast_ref::<State>();
+        let Some(position) = state.cursor.or_else(|| cursor.position_over(bounds)) else {
+            return;
+        };
+        let center = loupe::image_pixel(self.image, bounds, position);
+        let color = self.color_at(bounds, position);
+        loupe::draw(
+            renderer,
+            theme,
+            self.image,
+            center,
+            loupe::placement(bounds, position, true),
+            Some(color_label(color)),
+        );
+    }
+}
+
+impl<'a, Msg> From<ColorPicker<'a, Msg>> for Element<'a, Msg>
+where
+    Msg: 'static + Clone,
+{
+    fn from(w: ColorPicker<'a, Msg>) -> Element<'a, Msg> {
+        Element::new(w)
+    }
+}
@@ -0,0 +1,192 @@
+//! A magnifier that draws the pixels around a point of a screenshot as a grid
+//! of enlarged cells, used by the color picker and the rectangle selection.
+
+use cosmic::iced::core::border::Radius;
+use cosmic::iced::core::renderer::Quad;
+use cosmic::iced::core::{
+    self as core, Background, Border, Color, Pixels, Point, Rectangle, Renderer as _, Size,
+    alignment, text,
+};
+use image::RgbaImage;
+
+/// Number of image pixels shown along each side of the loupe. Odd, so there is
+/// a center pixel.
+const PIXELS: u32 = 11;
+/// Size in logical pixels of one magnified image pixel.
+const CELL: f32 = 10.0;
+/// Distance between the cursor and the loupe.
+const OFFSET: f32 = 24.0;
+pub const LABEL_HEIGHT: f32 = 24.0;
+/// Approximate width of a character of label text
+const LABEL_CHAR_WIDTH: f32 = 7.5;
+
+pub const SIZE: f32 = PIXELS as f32 * CELL;
+
+/// Converts a position in a widget showing `image` scaled to `bounds` into a
+/// pixel coordinate of `image`.
+pub fn image_pixel(image: &RgbaImage, bounds: Rectangle, position: Point) -> (u32, u32) {
+    let x = (position.x - bounds.x) / bounds.width * image.width() as f32;
+    let y = (position.y - bounds.y) / bounds.height * image.height() as f32;
+    (
+        (x.max(0.) as u32).min(image.width().saturating_sub(1)),
+        (y.max(0.) as u32).min(image.height().saturating_sub(1)),
+    )
+}
+
+/// Places the loupe next to `cursor`, flipping it to the other side when it
+/// would leave `bounds`.
+pub fn placement(bounds: Rectangle, cursor: Point, label: bool) -> Rectangle {
+    let height = SIZE + if label { LABEL_HEIGHT } else { 0.0 };
+    let mut x = cursor.x + OFFSET;
+    let mut y = cursor.y + OFFSET;
+    if x + SIZE > bounds.x + bounds.width {
+        x = cursor.x - OFFSET - SIZE;
+    }
+    if y + height > bounds.y + bounds.height {
+        y = cursor.y - OFFSET - height;
+    }
+    Rectangle::new(Point::new(x, y), Size::new(SIZE, height))
+}
+
+/// Draws the pixels around `center` of `image` into `area`, with an optional
+/// text label underneath.
+pub fn draw(
+    renderer: &mut cosmic::Renderer,
+    theme: &cosmic::Theme,
+    image: &RgbaImage,
+    center: (u32, u32),
+    area: Rectangle,
+    label: Option<String>,
+) {
+    let cosmic = theme.cosmic();
+    let accent = Color::from(cosmic.accent_color());
+    let radius = cosmic.radius_s();
+    let half = (PIXELS / 2) as i64;
+
+    renderer.with_layer(area, |renderer| {
+        for row in 0..PIXELS {
+            for col in 0..PIXELS {
+                let x = center.0 as i64 + col as i64 - half;
+                let y = center.1 as i64 + row as i64 - half;
+                let color = if x < 0
+                    || y < 0
+                    || x >= i64::from(image.width())
+                    || y >= i64::from(image.height())
+                {
+                    Color::BLACK
+                } else {
+                    let [r, g, b, _] = image.get_pixel(x as u32, y as u32).0;
+                    Color::from_rgb8(r, g, b)
+                };
+                renderer.fill_quad(
+                    Quad {
+                        bounds: Rectangle::new(
+                            Point::new(area.x + col as f32 * CELL, area.y + row as f32 * CELL),
+                            Size::new(CELL, CELL),
+                        ),
+                        snap: true,
+                        ..Default::default()
+                    },
+                    Background::Color(color),
+                );
+            }
+        }
+
+        // Outline the pixel under the cursor
+        renderer.fill_quad(
+            Quad {
+                bounds: Rectangle::new(
+                    Point::new(
+                        area.x + half as f32 * CELL - 1.0,
+                        area.y + half as f32 * CELL - 1.0,
+                    ),
+                    Size::new(CELL + 2.0, CELL + 2.0),
+                ),
+                border: Border {
+                    color: Color::WHITE,
+                    width: 1.0,
+                    radius: 0.0.into(),
+                },
+                snap: true,
+                ..Default::default()
+            },
+            Background::Color(Color::TRANSPARENT),
+        );
+
+        renderer.fill_quad(
+            Quad {
+                bounds: Rectangle::new(area.position(), Size::new(SIZE, SIZE)),
+                border: Border {
+                    color: accent,
+                    width: 2.0,
+                    radius: 0.0.into(),
+                },
+                snap: true,
+                ..Default::default()
+            },
+            Background::Color(Color::TRANSPARENT),
+        );
+
+        if let Some(label) = label {
+            let bar = Rectangle::new(
+                Point::new(area.x, area.y + SIZE),
+                Size::new(SIZE, LABEL_HEIGHT),
+            );
+            draw_label(
+                renderer,
+                bar,
+                [0.0, 0.0, radius[2], radius[3]].into(),
+                label,
+            );
+        }
+    });
+}
+
+/// Width of a label bar that fits `label`
+pub fn label_width(label: &str) -> f32 {
+    label.chars().count() as f32 * LABEL_CHAR_WIDTH + 16.0
+}
+
+/// Draws `label` in white on a dark bar filling `bounds`
+pub fn draw_label(
+    renderer: &mut cosmic::Renderer,
+    bounds: Rectangle,
+    radius: Radius,
+    label: String,
+) {
+    renderer.fill_quad(
+        Quad {
+            bounds,
+            border: Border {
+                radius,
+                ..Default::default()
+            },
+            snap: true,
+            ..Default::default()
+        },
+        Background::Color(Color {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 0.85,
+        }),
+    );
+    core::text::Renderer::fill_text(
+        renderer,
+        core::Text {
+            content: label,
+            size: Pixels(12.0),
+            line_height: text::LineHeight::Relative(1.0),
+            font: cosmic::font::mono(),
+            bounds: bounds.size(),
+            align_x: text::Alignment::Center,
+            align_y: alignment::Vertical::Center,
+            shaping: text::Shaping::Advanced,
+            wrapping: text::Wrapping::None,
+            ellipsize: text::Ellipsize::None,
+        },
+        Point::new(bounds.center_x(), bounds.center_y()),
+        Color::WHITE,
+        bounds,
+    );
+}
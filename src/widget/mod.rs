@@ -1,4 +1,7 @@
+pub mod annotation_canvas;
+pub mod color_picker;
 pub mod keyboard_wrapper;
+pub mod loupe;
 pub mod output_arrangement;
 pub mod output_selection;
 pub mod rectangle_selection;
@@ -7,11 +7,14 @@ use cosmic::iced::clipboard::mime::{AllowedMimeTypes, AsMimeTypes};
 use cosmic::iced::core::clipboard::DndSource;
 use cosmic::iced::core::layout::Node;
 use cosmic::iced::core::renderer::Quad;
+use cosmic::iced::core::widget::tree;
 use cosmic::iced::core::{Border, Color, Length, Point, Rectangle, Renderer, Shadow, Size};
-use cosmic::iced::{self, mouse};
+use cosmic::iced::{self, keyboard, mouse};
 use cosmic::widget::{self, Widget};
+use image::RgbaImage;
 
-use crate::screenshot::Rect;
+use super::loupe;
+use crate::screenshot::{self, Rect};
 
 pub const MIME: &str = "X-COSMIC-PORTAL-MyData";
 pub struct MyData;
@@ -70,24 +73,41 @@ impl From<u8> for DragState {
     }
 }
 
+#[derive(Debug, Default)]
+struct State {
+    modifiers: keyboard::Modifiers,
+}
+
 const EDGE_GRAB_THICKNESS: f32 = 8.0;
 const CORNER_DIAMETER: f32 = 16.0;
+/// Space between the selection and its size readout
+const READOUT_GAP: f32 = 8.0;
 
-pub struct RectangleSelection<Msg> {
+pub struct RectangleSelection<'a, Msg> {
     output_rect: Rect,
     rectangle_selection: Rect,
     window_id: iced::core::window::Id,
     on_rectangle: Box<dyn Fn(DragState, Rect) -> Msg>,
     drag_state: DragState,
+    /// Screenshot of this output, for the loupe
+    image: &'a RgbaImage,
+    /// Corner or edge last moved with the keyboard
+    nudged: DragState,
+    /// Sorted x and y coordinates of the edges a drag snaps to
+    snap_edges: (Vec<i32>, Vec<i32>),
     widget_id: widget::Id,
     drag_id: u128,
 }
 
-impl<Msg> RectangleSelection<Msg> {
+impl<'a, Msg> RectangleSelection<'a, Msg> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         output_rect: Rect,
         rectangle_selection: Rect,
         drag_direction: DragState,
+        nudged: DragState,
+        snap_edges: (Vec<i32>, Vec<i32>),
+        image: &'a RgbaImage,
         window_id: iced::core::window::Id,
         drag_id: u128,
         on_rectangle: impl Fn(DragState, Rect) -> Msg + 'static,
@@ -95,6 +115,9 @@ impl<Msg> RectangleSelection<Msg> {
         Self {
             on_rectangle: Box::new(on_rectangle),
             drag_state: drag_direction,
+            nudged,
+            snap_edges,
+            image,
             rectangle_selection,
             output_rect,
             window_id,
@@ -103,6 +126,69 @@ impl<Msg> RectangleSelection<Msg> {
         }
     }
 
+    /// Point of the selection being dragged or nudged, in global logical coordinates
+    fn active_point(&self) -> Option<Point> {
+        let state = if self.drag_state != DragState::None {
+            self.drag_state
+        } else {
+            self.nudged
+        };
+        let r = self.rectangle_selection;
+        let (left, top) = (r.left.min(r.right) as f32, r.top.min(r.bottom) as f32);
+        let (right, bottom) = (r.left.max(r.right) as f32, r.top.max(r.bottom) as f32);
+        let (center_x, center_y) = ((left + right) / 2.0, (top + bottom) / 2.0);
+        let (x, y) = match state {
+            DragState::None => return None,
+            DragState::NW => (left, top),
+            DragState::N => (center_x, top),
+            DragState::NE => (right, top),
+            DragState::E => (right, center_y),
+            DragState::SE => (right, bottom),
+            DragState::S => (center_x, bottom),
+            DragState::SW => (left, bottom),
+            DragState::W => (left, center_y),
+        };
+        Some(Point::new(x, y))
+    }
+
+    /// Draws the size and position of the selection under its bottom left corner, on the
+    /// output that corner is on
+    fn draw_readout(&self, renderer: &mut cosmic::Renderer, output_bounds: Rectangle) {
+        let r = self.rectangle_selection;
+        let (left, top) = (r.left.min(r.right), r.top.min(r.bottom));
+        let bottom = r.top.max(r.bottom);
+        let corner = Point::new(
+            (left - self.output_rect.left) as f32,
+            (bottom - self.output_rect.top) as f32,
+        );
+        if !output_bounds.contains(corner) {
+            return;
+        }
+        let label = format!(
+            "{} × {}  {}, {}",
+            r.width().abs(),
+            r.height().abs(),
+            left,
+            top
+        );
+        let size = Size::new(loupe::label_width(&label), loupe::LABEL_HEIGHT);
+
+        // Below the selection, or above it, or inside it when neither fits
+        let top_edge = (top - self.output_rect.top) as f32;
+        let mut y = corner.y + READOUT_GAP;
+        if y + size.height > output_bounds.height {
+            y = top_edge - READOUT_GAP - size.height;
+        }
+        if y < 0.0 {
+            y = corner.y - READOUT_GAP - size.height;
+        }
+        let x = corner.x.min(output_bounds.width - size.width).max(0.0);
+        let bounds = Rectangle::new(Point::new(x, y), size);
+        renderer.with_layer(bounds, |renderer| {
+            loupe::draw_label(renderer, bounds, 4.0.into(), label);
+        });
+    }
+
     pub fn translated_inner_rect(&self) -> Rectangle {
         let inner_rect = self.rectangle_selection;
         let inner_rect = Rectangle::new(
@@ -209,11 +295,21 @@ impl<Msg> RectangleSelection<Msg> {
         DragState::None
     }
 
-    fn handle_drag_pos(&mut self, x: i32, y: i32, shell: &mut iced::core::Shell<'_, Msg>) {
+    fn handle_drag_pos(
+        &mut self,
+        x: i32,
+        y: i32,
+        snap: bool,
+        shell: &mut iced::core::Shell<'_, Msg>,
+    ) {
         let prev = self.rectangle_selection;
 
-        let d_x = self.output_rect.left + x;
-        let d_y = self.output_rect.top + y;
+        let mut d_x = self.output_rect.left + x;
+        let mut d_y = self.output_rect.top + y;
+        if snap {
+            d_x = screenshot::snap(d_x, &self.snap_edges.0);
+            d_y = screenshot::snap(d_y, &self.snap_edges.1);
+        }
 
         let prev_state = self.drag_state;
         // the point of reflection is where, when crossed, the drag state changes to the opposit direction
@@ -299,7 +395,7 @@ impl<Msg> RectangleSelection<Msg> {
 }
 
 impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
-    for RectangleSelection<Msg>
+    for RectangleSelection<'_, Msg>
 {
     fn size(&self) -> Size<Length> {
         Size::new(Length::Fill, Length::Fill)
@@ -318,9 +414,12 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
         ))
     }
 
-    fn tag(&self) -> iced::core::widget::tree::Tag {
-        struct MyState;
-        iced::core::widget::tree::Tag::of::<MyState>()
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
     }
 
     fn mouse_interaction(
@@ -353,7 +452,7 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
 
     fn update(
         &mut self,
-        _state: &mut iced::core::widget::Tree,
+        tree: &mut iced::core::widget::Tree,
         event: &iced::core::Event,
         layout: iced::core::Layout<'_>,
         cursor: iced::core::mouse::Cursor,
@@ -362,7 +461,13 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
         shell: &mut iced::core::Shell<'_, Msg>,
         _viewport: &Rectangle,
     ) {
+        let state = tree.state.downcast_mut::<State>();
+        // Holding Ctrl while dragging turns snapping off
+        let snap = !state.modifiers.control();
         match event {
+            cosmic::iced::core::Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.modifiers = *modifiers;
+            }
             cosmic::iced::core::Event::Dnd(DndEvent::Offer(id, e)) if *id == Some(self.drag_id) => {
                 if self.drag_state == DragState::None {
                     return;
@@ -376,7 +481,7 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
                             return;
                         }
 
-                        self.handle_drag_pos(x.round() as i32, y.round() as i32, shell);
+                        self.handle_drag_pos(x.round() as i32, y.round() as i32, snap, shell);
                         shell.capture_event();
                     }
                     OfferEvent::Motion { x, y } => {
@@ -385,7 +490,7 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
                         if !cursor.is_over(layout.bounds()) {
                             return;
                         }
-                        self.handle_drag_pos(x.round() as i32, y.round() as i32, shell);
+                        self.handle_drag_pos(x.round() as i32, y.round() as i32, snap, shell);
                         shell.capture_event();
                     }
                     OfferEvent::Drop => {
@@ -598,6 +703,24 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
             };
             renderer.fill_quad(quad, accent);
         }
+
+        let output_bounds = Rectangle::new(Point::ORIGIN, outer_size);
+        self.draw_readout(renderer, output_bounds);
+
+        // Magnify the point being moved, for pixel-precise selections
+        if let Some(point) = self.active_point()
+            && outer_rect.contains(point)
+        {
+            let point = Point::new(point.x - outer_rect.x, point.y - outer_rect.y);
+            loupe::draw(
+                renderer,
+                theme,
+                self.image,
+                loupe::image_pixel(self.image, output_bounds, point),
+                loupe::placement(output_bounds, point, false),
+                None,
+            );
+        }
     }
 
     fn drag_destinations(
@@ -627,11 +750,11 @@ impl<Msg: 'static + Clone> Widget<Msg, cosmic::Theme, cosmic::Renderer>
     }
 }
 
-impl<'a, Message> From<RectangleSelection<Message>> for cosmic::Element<'a, Message>
+impl<'a, Message> From<RectangleSelection<'a, Message>> for cosmic::Element<'a, Message>
 where
     Message: 'static + Clone,
 {
-    fn from(w: RectangleSelection<Message>) -> cosmic::Element<'a, Message> {
+    fn from(w: RectangleSelection<'a, Message>) -> cosmic::Element<'a, Message> {
         cosmic::Element::new(w)
     }
 }
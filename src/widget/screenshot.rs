@@ -62,18 +62,31 @@ where
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         choice: Choice,
-        image: &ScreenshotImage,
+        image: &'a ScreenshotImage,
         on_capture: Msg,
+        on_edit: Msg,
+        edit_window: bool,
+        on_copy_text: Msg,
+        on_history: Option<Msg>,
         on_cancel: Msg,
+        nudged: DragState,
+        snap_edges: (Vec<i32>, Vec<i32>),
         output: &OutputState,
         window_id: window::Id,
         on_output_change: impl Fn(WlOutput) -> Msg,
         on_choice_change: impl Fn(Choice) -> Msg + 'static + Clone,
         toplevel_images: &HashMap<String, Vec<ScreenshotImage>>,
         toplevel_chosen: impl Fn(String, usize) -> Msg,
+        window_shadow: bool,
+        on_window_shadow: impl Fn(bool) -> Msg + 'static,
+        per_output: bool,
+        on_per_output: impl Fn(bool) -> Msg + 'static,
         save_locations: &'a Vec<String>,
         selected_save_location: usize,
         dropdown_selected: impl Fn(usize) -> Msg + 'static + Clone,
+        delays: &'a Vec<String>,
+        selected_delay: usize,
+        delay_selected: impl Fn(usize) -> Msg + 'static + Clone,
         spacing: Spacing,
         dnd_id: u128,
     ) -> Self {
@@ -95,6 +108,9 @@ where
                 output_rect,
                 r,
                 drag_state,
+                nudged,
+                snap_edges,
+                &image.rgba,
                 window_id,
                 dnd_id,
                 move |s, r| on_choice_change_clone(Choice::Rectangle(r, s)),
@@ -104,7 +120,7 @@ where
                 OutputSelection::new(on_output_change(output.output.clone()), on_capture.clone())
                     .into()
             }
-            Choice::Window(..) => {
+            Choice::Window(..) | Choice::Scrolling(..) => {
                 let imgs = toplevel_images
                     .get(&output.name)
                     .map(|x| x.as_slice())
@@ -147,7 +163,7 @@ where
                 .width(Length::Fill)
                 .height(Length::Fill)
                 .into(),
-            Choice::Window(..) => match output.bg_source.clone() {
+            Choice::Window(..) | Choice::Scrolling(..) => match output.bg_source.clone() {
                 Some(Source::Path(path)) => image::Image::new(image::Handle::from_path(path))
                     .content_fit(ContentFit::Cover)
                     .width(Length::Fill)
@@ -249,6 +265,23 @@ where
                         .class(cosmic::theme::Button::Icon)
                         .on_press(on_choice_change(Choice::Window(output.name.clone(), None)))
                         .padding(space_xs),
+                        button::custom(
+                            icon::Icon::from(icon::from_name("view-continuous-symbolic").size(64))
+                                .class(if matches!(choice, Choice::Scrolling(..)) {
+                                    active_icon.clone()
+                                } else {
+                                    cosmic::theme::Svg::default()
+                                })
+                                .width(Length::Fixed(40.0))
+                                .height(Length::Fixed(40.0))
+                        )
+                        .selected(matches!(choice, Choice::Scrolling(..)))
+                        .class(cosmic::theme::Button::Icon)
+                        .on_press(on_choice_change(Choice::Scrolling(
+                            output.name.clone(),
+                            None
+                        )))
+                        .padding(space_xs),
                         button::custom(
                             icon::Icon::from(
                                 icon::from_name("screenshot-screen-symbolic").size(64)
@@ -268,9 +301,35 @@ where
                         .on_press(on_choice_change(Choice::Output(output.name.clone())))
                         .padding(space_xs)
                     ]
+                    .push_maybe(matches!(choice, Choice::Window(..)).then(|| {
+                        widget::checkbox(fl!("window-shadow"), window_shadow)
+                            .on_toggle(on_window_shadow)
+                    }))
+                    // Saves a selection across displays as one image per display
+                    .push_maybe(matches!(choice, Choice::Rectangle(..)).then(|| {
+                        widget::checkbox(fl!("per-display"), per_output).on_toggle(on_per_output)
+                    }))
                     .spacing(space_s)
                     .align_y(Alignment::Center),
                     divider::vertical::light().height(Length::Fixed(64.0)),
+                    button::custom(text(fl!("annotate")))
+                        .on_press_maybe(match &choice {
+                            Choice::Output(_) => Some(on_edit),
+                            Choice::Rectangle(r, ..) => {
+                                r.dimensions().is_some().then_some(on_edit)
+                            }
+                            // Toggles annotating the window picked next
+                            Choice::Window(..) => Some(on_edit),
+                            Choice::Scrolling(..) => None,
+                        })
+                        .selected(edit_window),
+                    button::custom(text(fl!("copy-text"))).on_press_maybe(match &choice {
+                        Choice::Output(_) => Some(on_copy_text),
+                        Choice::Rectangle(r, ..) => {
+                            r.dimensions().is_some().then_some(on_copy_text)
+                        }
+                        Choice::Window(..) | Choice::Scrolling(..) => None,
+                    }),
                     button::custom(text(fl!("capture"))).on_press_maybe(
                         if let Choice::Rectangle(r, ..) = choice {
                             // Disable button on empty selection
@@ -280,12 +339,15 @@ where
                         }
                     ),
                     divider::vertical::light().height(Length::Fixed(64.0)),
+                    Element::from(dropdown(delays.as_slice(), Some(selected_delay), |i| i))
+                        .map(delay_selected),
                     Element::from(dropdown(
                         save_locations.as_slice(),
                         Some(selected_save_location),
                         |i| i
                     ))
                     .map(dropdown_selected),
+                    button::custom(text(fl!("history"))).on_press_maybe(on_history),
                     divider::vertical::light().height(Length::Fixed(64.0)),
                     button::custom(
                         icon::Icon::from(icon::from_name("window-close-symbolic").size(63))